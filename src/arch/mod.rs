@@ -10,7 +10,9 @@ macro_rules! gated {
     };
 }
 
+pub mod registration;
+
 gated! {
-    #[cfg(windows)]
+    #[cfg(any(windows, feature = "com-emulation"))]
     pub mod windows;
 }
@@ -0,0 +1,493 @@
+//! Registering a thumbnail provider with the Windows shell.
+//!
+//! Everything in here is plain string manipulation, so installers and build
+//! scripts can generate registry scripts on any platform.
+
+use std::fmt::Write;
+
+/// The `IThumbnailProvider` interface ID, used as the name of the `ShellEx`
+/// key which tells Explorer which thumbnail handler to use for a file type.
+pub const THUMBNAIL_HANDLER_KEY: &str =
+    "{e357fccd-a995-4576-b01f-234630154e96}";
+
+/// A description of the registry keys needed to install a thumbnail
+/// provider DLL.
+///
+/// All keys are relative to `HKEY_CLASSES_ROOT`.
+///
+/// # Examples
+///
+/// ```rust
+/// use thumbnails::arch::registration::ShellExtension;
+///
+/// let ext = ShellExtension::new(
+///     "1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e",
+///     r"C:\Program Files\Thumbnails\thumbnails.dll",
+/// )
+/// .name("Thumbnails")
+/// .extension(".png");
+///
+/// let script = ext.to_reg_file();
+/// assert!(script.starts_with("Windows Registry Editor Version 5.00"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellExtension {
+    clsid: String,
+    dll_path: String,
+    name: Option<String>,
+    threading_model: String,
+    process_isolation: bool,
+    extensions: Vec<String>,
+}
+
+impl ShellExtension {
+    /// Describe the registration for the COM class, `clsid`, implemented by
+    /// the DLL at `dll_path`.
+    ///
+    /// The surrounding braces on `clsid` are optional.
+    pub fn new<C, P>(clsid: C, dll_path: P) -> Self
+    where
+        C: AsRef<str>,
+        P: Into<String>,
+    {
+        let clsid =
+            clsid.as_ref().trim_start_matches('{').trim_end_matches('}');
+
+        ShellExtension {
+            clsid: format!("{{{}}}", clsid),
+            dll_path: dll_path.into(),
+            name: None,
+            threading_model: String::from("Apartment"),
+            process_isolation: true,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// A human-readable name for the COM class.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Override the `ThreadingModel` (defaults to `Apartment`).
+    pub fn threading_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.threading_model = model.into();
+        self
+    }
+
+    /// Load the DLL into the process asking for thumbnails instead of an
+    /// isolated surrogate process.
+    ///
+    /// Explorer only initializes a thumbnail provider with a path
+    /// (`IInitializeWithFile` or `IInitializeWithItem`) when process isolation
+    /// is disabled.
+    pub fn disable_process_isolation(mut self) -> Self {
+        self.process_isolation = false;
+        self
+    }
+
+    /// Use this thumbnail provider for files with a particular extension.
+    pub fn extension<S: Into<String>>(mut self, extension: S) -> Self {
+        let extension = extension.into();
+        let extension = extension.trim_start_matches('.').to_string();
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn extensions<I, S>(self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extensions.into_iter().fold(self, ShellExtension::extension)
+    }
+
+    /// The CLSID, in its braced form (e.g. `{1f0e4a63-...}`).
+    pub fn clsid(&self) -> &str { &self.clsid }
+
+    fn class_key(&self) -> String { format!("CLSID\\{}", self.clsid) }
+
+    fn extension_key(extension: &str) -> String {
+        format!(".{}\\ShellEx\\{}", extension, THUMBNAIL_HANDLER_KEY)
+    }
+
+    /// The operations needed to install the thumbnail provider, in the
+    /// order they should be applied.
+    ///
+    /// The COM class is registered before any file types refer to it.
+    pub fn install_operations(&self) -> Vec<RegistryOperation> {
+        let class_key = self.class_key();
+        let server_key = format!("{}\\InprocServer32", class_key);
+
+        let mut ops = Vec::new();
+
+        ops.push(RegistryOperation::SetValue {
+            key: class_key.clone(),
+            name: None,
+            data: self.name.clone().unwrap_or_default(),
+        });
+        if !self.process_isolation {
+            ops.push(RegistryOperation::SetDword {
+                key: class_key,
+                name: String::from("DisableProcessIsolation"),
+                data: 1,
+            });
+        }
+        ops.push(RegistryOperation::SetValue {
+            key: server_key.clone(),
+            name: None,
+            data: self.dll_path.clone(),
+        });
+        ops.push(RegistryOperation::SetValue {
+            key: server_key,
+            name: Some(String::from("ThreadingModel")),
+            data: self.threading_model.clone(),
+        });
+
+        for extension in &self.extensions {
+            ops.push(RegistryOperation::SetValue {
+                key: ShellExtension::extension_key(extension),
+                name: None,
+                data: self.clsid.clone(),
+            });
+        }
+
+        ops
+    }
+
+    /// The operations needed to remove the thumbnail provider, in the order
+    /// they should be applied.
+    ///
+    /// File types stop referring to the COM class before it is removed.
+    pub fn uninstall_operations(&self) -> Vec<RegistryOperation> {
+        let mut ops: Vec<_> = self
+            .extensions
+            .iter()
+            .map(|ext| RegistryOperation::DeleteKey {
+                key: ShellExtension::extension_key(ext),
+            })
+            .collect();
+
+        ops.push(RegistryOperation::DeleteKey {
+            key: self.class_key(),
+        });
+
+        ops
+    }
+
+    /// Generate a `.reg` file which installs the thumbnail provider.
+    pub fn to_reg_file(&self) -> String { reg_file(&self.install_operations()) }
+
+    /// Generate a `.reg` file which removes the thumbnail provider.
+    pub fn to_uninstall_reg_file(&self) -> String {
+        reg_file(&self.uninstall_operations())
+    }
+
+    /// Generate a [WiX] fragment containing a single component which
+    /// installs the thumbnail provider.
+    ///
+    /// The `component_guid` identifies the component itself (not the COM
+    /// class), so it must be unique to your installer and stay the same
+    /// between builds. Use `"*"` to let WiX generate one. Windows Installer
+    /// takes care of removing the keys on uninstall. The DLL path is a
+    /// [formatted] string, so something like `[#thumbnails.dll]` can be used.
+    ///
+    /// [WiX]: https://wixtoolset.org/
+    /// [formatted]: https://docs.microsoft.com/en-us/windows/win32/msi/formatted
+    pub fn to_wix_fragment(
+        &self,
+        component_id: &str,
+        component_guid: &str,
+    ) -> String {
+        let mut xml = String::new();
+
+        xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str(
+            "<Wix xmlns=\"http://schemas.microsoft.com/wix/2006/wi\">\n",
+        );
+        xml.push_str("  <Fragment>\n");
+        xml.push_str("    <DirectoryRef Id=\"TARGETDIR\">\n");
+        let _ = writeln!(
+            xml,
+            "      <Component Id=\"{}\" Guid=\"{}\">",
+            xml_escape(component_id),
+            xml_escape(component_guid)
+        );
+
+        let values =
+            self.install_operations()
+                .into_iter()
+                .filter_map(|op| match op {
+                    RegistryOperation::SetValue { key, name, data } => {
+                        Some((key, name, "string", data))
+                    },
+                    RegistryOperation::SetDword { key, name, data } => {
+                        Some((key, Some(name), "integer", data.to_string()))
+                    },
+                    RegistryOperation::DeleteKey { .. } => None,
+                });
+
+        for (i, (key, name, kind, data)) in values.enumerate() {
+            let _ = write!(
+                xml,
+                "        <RegistryValue Root=\"HKCR\" Key=\"{}\"",
+                xml_escape(&key)
+            );
+            if let Some(name) = name {
+                let _ = write!(xml, " Name=\"{}\"", xml_escape(&name));
+            }
+            let _ = write!(
+                xml,
+                " Type=\"{}\" Value=\"{}\"",
+                kind,
+                xml_escape(&data)
+            );
+            if i == 0 {
+                xml.push_str(" KeyPath=\"yes\"");
+            }
+            xml.push_str(" />\n");
+        }
+
+        xml.push_str("      </Component>\n");
+        xml.push_str("    </DirectoryRef>\n");
+        xml.push_str("  </Fragment>\n");
+        xml.push_str("</Wix>\n");
+
+        xml
+    }
+}
+
+/// A single change to the registry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryOperation {
+    /// Set a string (`REG_SZ`) value, creating the key if necessary. A
+    /// `name` of `None` refers to the key's default value.
+    SetValue {
+        key: String,
+        name: Option<String>,
+        data: String,
+    },
+    /// Set a `REG_DWORD` value, creating the key if necessary.
+    SetDword {
+        key: String,
+        name: String,
+        data: u32,
+    },
+    /// Delete a key and everything under it. Deleting a key which doesn't
+    /// exist isn't an error.
+    DeleteKey { key: String },
+}
+
+fn reg_file(ops: &[RegistryOperation]) -> String {
+    let mut script = String::from("Windows Registry Editor Version 5.00\r\n");
+    let mut current_key: Option<&str> = None;
+
+    for op in ops {
+        match op {
+            RegistryOperation::SetValue { key, name, data } => {
+                open_key(&mut script, &mut current_key, key);
+
+                match name {
+                    Some(name) => {
+                        let _ = write!(script, "\"{}\"", reg_escape(name));
+                    },
+                    None => script.push('@'),
+                }
+                let _ = write!(script, "=\"{}\"\r\n", reg_escape(data));
+            },
+            RegistryOperation::SetDword { key, name, data } => {
+                open_key(&mut script, &mut current_key, key);
+
+                let _ = write!(
+                    script,
+                    "\"{}\"=dword:{:08x}\r\n",
+                    reg_escape(name),
+                    data
+                );
+            },
+            RegistryOperation::DeleteKey { key } => {
+                let _ = write!(script, "\r\n[-HKEY_CLASSES_ROOT\\{}]\r\n", key);
+                current_key = None;
+            },
+        }
+    }
+
+    script
+}
+
+/// Start a new `[key]` section, unless we're already in it.
+fn open_key<'a>(
+    script: &mut String,
+    current_key: &mut Option<&'a str>,
+    key: &'a str,
+) {
+    if *current_key != Some(key) {
+        let _ = write!(script, "\r\n[HKEY_CLASSES_ROOT\\{}]\r\n", key);
+        *current_key = Some(key);
+    }
+}
+
+fn reg_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLSID: &str = "{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}";
+
+    fn dummy() -> ShellExtension {
+        ShellExtension::new(CLSID, r"C:\dummy.dll")
+            .name("Dummy")
+            .extensions(vec![".dummy", "dmy"])
+    }
+
+    #[test]
+    fn braces_are_optional() {
+        let got = ShellExtension::new(&CLSID[1..CLSID.len() - 1], "");
+
+        assert_eq!(got.clsid(), CLSID);
+    }
+
+    #[test]
+    fn install_operations() {
+        let got = dummy().install_operations();
+
+        let class_key = format!("CLSID\\{}", CLSID);
+        let server_key = format!("{}\\InprocServer32", class_key);
+        let should_be = vec![
+            RegistryOperation::SetValue {
+                key: class_key,
+                name: None,
+                data: String::from("Dummy"),
+            },
+            RegistryOperation::SetValue {
+                key: server_key.clone(),
+                name: None,
+                data: String::from(r"C:\dummy.dll"),
+            },
+            RegistryOperation::SetValue {
+                key: server_key,
+                name: Some(String::from("ThreadingModel")),
+                data: String::from("Apartment"),
+            },
+            RegistryOperation::SetValue {
+                key: format!(".dummy\\ShellEx\\{}", THUMBNAIL_HANDLER_KEY),
+                name: None,
+                data: String::from(CLSID),
+            },
+            RegistryOperation::SetValue {
+                key: format!(".dmy\\ShellEx\\{}", THUMBNAIL_HANDLER_KEY),
+                name: None,
+                data: String::from(CLSID),
+            },
+        ];
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn uninstall_operations() {
+        let got = dummy().uninstall_operations();
+
+        let should_be = vec![
+            RegistryOperation::DeleteKey {
+                key: format!(".dummy\\ShellEx\\{}", THUMBNAIL_HANDLER_KEY),
+            },
+            RegistryOperation::DeleteKey {
+                key: format!(".dmy\\ShellEx\\{}", THUMBNAIL_HANDLER_KEY),
+            },
+            RegistryOperation::DeleteKey {
+                key: format!("CLSID\\{}", CLSID),
+            },
+        ];
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn generate_a_reg_file() {
+        let got = ShellExtension::new(CLSID, r"C:\dummy.dll")
+            .name("\"Dummy\"")
+            .extension("dummy")
+            .to_reg_file();
+
+        let should_be = r#"Windows Registry Editor Version 5.00
+
+[HKEY_CLASSES_ROOT\CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}]
+@="\"Dummy\""
+
+[HKEY_CLASSES_ROOT\CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}\InprocServer32]
+@="C:\\dummy.dll"
+"ThreadingModel"="Apartment"
+
+[HKEY_CLASSES_ROOT\.dummy\ShellEx\{e357fccd-a995-4576-b01f-234630154e96}]
+@="{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}"
+"#;
+        assert_eq!(got, should_be.replace('\n', "\r\n"));
+    }
+
+    #[test]
+    fn disable_process_isolation() {
+        let got = ShellExtension::new(CLSID, r"C:\dummy.dll")
+            .name("Dummy")
+            .disable_process_isolation()
+            .to_reg_file();
+
+        let should_be = r#"
+[HKEY_CLASSES_ROOT\CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}]
+@="Dummy"
+"DisableProcessIsolation"=dword:00000001
+"#;
+        assert!(got.contains(&should_be.replace('\n', "\r\n")), "{}", got);
+    }
+
+    #[test]
+    fn generate_an_uninstall_reg_file() {
+        let got = ShellExtension::new(CLSID, r"C:\dummy.dll")
+            .extension("dummy")
+            .to_uninstall_reg_file();
+
+        let should_be = r#"Windows Registry Editor Version 5.00
+
+[-HKEY_CLASSES_ROOT\.dummy\ShellEx\{e357fccd-a995-4576-b01f-234630154e96}]
+
+[-HKEY_CLASSES_ROOT\CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}]
+"#;
+        assert_eq!(got, should_be.replace('\n', "\r\n"));
+    }
+
+    #[test]
+    fn generate_a_wix_fragment() {
+        let got = ShellExtension::new(CLSID, "[#thumbnails.dll]")
+            .name("Dummy & Co")
+            .extension("dummy")
+            .to_wix_fragment(
+                "ThumbnailProvider",
+                "{7c1e8d2a-53b4-4f0e-9a61-2d8f0b3c4e5a}",
+            );
+
+        let should_be = r#"<?xml version="1.0" encoding="utf-8"?>
+<Wix xmlns="http://schemas.microsoft.com/wix/2006/wi">
+  <Fragment>
+    <DirectoryRef Id="TARGETDIR">
+      <Component Id="ThumbnailProvider" Guid="{7c1e8d2a-53b4-4f0e-9a61-2d8f0b3c4e5a}">
+        <RegistryValue Root="HKCR" Key="CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}" Type="string" Value="Dummy &amp; Co" KeyPath="yes" />
+        <RegistryValue Root="HKCR" Key="CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}\InprocServer32" Type="string" Value="[#thumbnails.dll]" />
+        <RegistryValue Root="HKCR" Key="CLSID\{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}\InprocServer32" Name="ThreadingModel" Type="string" Value="Apartment" />
+        <RegistryValue Root="HKCR" Key=".dummy\ShellEx\{e357fccd-a995-4576-b01f-234630154e96}" Type="string" Value="{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}" />
+      </Component>
+    </DirectoryRef>
+  </Fragment>
+</Wix>
+"#;
+        assert_eq!(got, should_be);
+    }
+}
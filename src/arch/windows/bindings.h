@@ -1,3 +1,4 @@
 #include <Unknwnbase.h>
 #include <Propsys.h>
+#include <ShObjIdl_core.h>
 #include <thumbcache.h>
@@ -1,15 +1,15 @@
 /* automatically generated by rust-bindgen */
 
-pub type wchar_t = ::std::os::raw::c_ushort;
-pub type ULONG = ::std::os::raw::c_ulong;
-pub type DWORD = ::std::os::raw::c_ulong;
-pub type BOOL = ::std::os::raw::c_int;
-pub type UINT = ::std::os::raw::c_uint;
-pub type LONG = ::std::os::raw::c_long;
+pub type wchar_t = ctypes::c_ushort;
+pub type ULONG = ctypes::c_ulong;
+pub type DWORD = ctypes::c_ulong;
+pub type BOOL = ctypes::c_int;
+pub type UINT = ctypes::c_uint;
+pub type LONG = ctypes::c_long;
 pub type WCHAR = wchar_t;
-pub type HRESULT = ::std::os::raw::c_long;
-pub type LONGLONG = ::std::os::raw::c_longlong;
-pub type ULONGLONG = ::std::os::raw::c_ulonglong;
+pub type HRESULT = ctypes::c_long;
+pub type LONGLONG = ctypes::c_longlong;
+pub type ULONGLONG = ctypes::c_ulonglong;
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub union _LARGE_INTEGER {
@@ -37,10 +37,7 @@ fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_1() {
         concat!("Alignment of ", stringify!(_LARGE_INTEGER__bindgen_ty_1))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER__bindgen_ty_1>())).LowPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER__bindgen_ty_1, LowPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -50,10 +47,7 @@ fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_1() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER__bindgen_ty_1>())).HighPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER__bindgen_ty_1, HighPart),
         4usize,
         concat!(
             "Offset of field: ",
@@ -82,10 +76,7 @@ fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_2() {
         concat!("Alignment of ", stringify!(_LARGE_INTEGER__bindgen_ty_2))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER__bindgen_ty_2>())).LowPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER__bindgen_ty_2, LowPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -95,10 +86,7 @@ fn bindgen_test_layout__LARGE_INTEGER__bindgen_ty_2() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER__bindgen_ty_2>())).HighPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER__bindgen_ty_2, HighPart),
         4usize,
         concat!(
             "Offset of field: ",
@@ -121,9 +109,7 @@ fn bindgen_test_layout__LARGE_INTEGER() {
         concat!("Alignment of ", stringify!(_LARGE_INTEGER))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER>())).u as *const _ as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER, u),
         0usize,
         concat!(
             "Offset of field: ",
@@ -133,10 +119,7 @@ fn bindgen_test_layout__LARGE_INTEGER() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_LARGE_INTEGER>())).QuadPart as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(_LARGE_INTEGER, QuadPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -182,10 +165,7 @@ fn bindgen_test_layout__ULARGE_INTEGER__bindgen_ty_1() {
         concat!("Alignment of ", stringify!(_ULARGE_INTEGER__bindgen_ty_1))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER__bindgen_ty_1>())).LowPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER__bindgen_ty_1, LowPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -195,10 +175,7 @@ fn bindgen_test_layout__ULARGE_INTEGER__bindgen_ty_1() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER__bindgen_ty_1>())).HighPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER__bindgen_ty_1, HighPart),
         4usize,
         concat!(
             "Offset of field: ",
@@ -227,10 +204,7 @@ fn bindgen_test_layout__ULARGE_INTEGER__bindgen_ty_2() {
         concat!("Alignment of ", stringify!(_ULARGE_INTEGER__bindgen_ty_2))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER__bindgen_ty_2>())).LowPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER__bindgen_ty_2, LowPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -240,10 +214,7 @@ fn bindgen_test_layout__ULARGE_INTEGER__bindgen_ty_2() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER__bindgen_ty_2>())).HighPart
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER__bindgen_ty_2, HighPart),
         4usize,
         concat!(
             "Offset of field: ",
@@ -266,9 +237,7 @@ fn bindgen_test_layout__ULARGE_INTEGER() {
         concat!("Alignment of ", stringify!(_ULARGE_INTEGER))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER>())).u as *const _ as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER, u),
         0usize,
         concat!(
             "Offset of field: ",
@@ -278,10 +247,7 @@ fn bindgen_test_layout__ULARGE_INTEGER() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_ULARGE_INTEGER>())).QuadPart as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(_ULARGE_INTEGER, QuadPart),
         0usize,
         concat!(
             "Offset of field: ",
@@ -303,10 +269,10 @@ pub type ULARGE_INTEGER = _ULARGE_INTEGER;
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct _GUID {
-    pub Data1: ::std::os::raw::c_ulong,
-    pub Data2: ::std::os::raw::c_ushort,
-    pub Data3: ::std::os::raw::c_ushort,
-    pub Data4: [::std::os::raw::c_uchar; 8usize],
+    pub Data1: ctypes::c_ulong,
+    pub Data2: ctypes::c_ushort,
+    pub Data3: ctypes::c_ushort,
+    pub Data4: [ctypes::c_uchar; 8usize],
 }
 #[test]
 fn bindgen_test_layout__GUID() {
@@ -321,7 +287,7 @@ fn bindgen_test_layout__GUID() {
         concat!("Alignment of ", stringify!(_GUID))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<_GUID>())).Data1 as *const _ as usize },
+        ::std::mem::offset_of!(_GUID, Data1),
         0usize,
         concat!(
             "Offset of field: ",
@@ -331,7 +297,7 @@ fn bindgen_test_layout__GUID() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<_GUID>())).Data2 as *const _ as usize },
+        ::std::mem::offset_of!(_GUID, Data2),
         4usize,
         concat!(
             "Offset of field: ",
@@ -341,7 +307,7 @@ fn bindgen_test_layout__GUID() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<_GUID>())).Data3 as *const _ as usize },
+        ::std::mem::offset_of!(_GUID, Data3),
         6usize,
         concat!(
             "Offset of field: ",
@@ -351,7 +317,7 @@ fn bindgen_test_layout__GUID() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<_GUID>())).Data4 as *const _ as usize },
+        ::std::mem::offset_of!(_GUID, Data4),
         8usize,
         concat!(
             "Offset of field: ",
@@ -383,10 +349,7 @@ fn bindgen_test_layout__FILETIME() {
         concat!("Alignment of ", stringify!(_FILETIME))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_FILETIME>())).dwLowDateTime as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(_FILETIME, dwLowDateTime),
         0usize,
         concat!(
             "Offset of field: ",
@@ -396,10 +359,7 @@ fn bindgen_test_layout__FILETIME() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<_FILETIME>())).dwHighDateTime as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(_FILETIME, dwHighDateTime),
         4usize,
         concat!(
             "Offset of field: ",
@@ -413,7 +373,7 @@ pub type FILETIME = _FILETIME;
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct HBITMAP__ {
-    pub unused: ::std::os::raw::c_int,
+    pub unused: ctypes::c_int,
 }
 #[test]
 fn bindgen_test_layout_HBITMAP__() {
@@ -428,9 +388,7 @@ fn bindgen_test_layout_HBITMAP__() {
         concat!("Alignment of ", stringify!(HBITMAP__))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<HBITMAP__>())).unused as *const _ as usize
-        },
+        ::std::mem::offset_of!(HBITMAP__, unused),
         0usize,
         concat!(
             "Offset of field: ",
@@ -447,17 +405,17 @@ pub type LPOLESTR = *mut OLECHAR;
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct IUnknownVtbl {
     pub QueryInterface: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IUnknown,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub AddRef: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IUnknown) -> ULONG,
+        unsafe extern "system" fn(This: *mut IUnknown) -> ULONG,
     >,
     pub Release: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IUnknown) -> ULONG,
+        unsafe extern "system" fn(This: *mut IUnknown) -> ULONG,
     >,
 }
 #[test]
@@ -473,10 +431,7 @@ fn bindgen_test_layout_IUnknownVtbl() {
         concat!("Alignment of ", stringify!(IUnknownVtbl))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IUnknownVtbl>())).QueryInterface as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IUnknownVtbl, QueryInterface),
         0usize,
         concat!(
             "Offset of field: ",
@@ -486,9 +441,7 @@ fn bindgen_test_layout_IUnknownVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IUnknownVtbl>())).AddRef as *const _ as usize
-        },
+        ::std::mem::offset_of!(IUnknownVtbl, AddRef),
         8usize,
         concat!(
             "Offset of field: ",
@@ -498,10 +451,7 @@ fn bindgen_test_layout_IUnknownVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IUnknownVtbl>())).Release as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IUnknownVtbl, Release),
         16usize,
         concat!(
             "Offset of field: ",
@@ -529,9 +479,7 @@ fn bindgen_test_layout_IUnknown() {
         concat!("Alignment of ", stringify!(IUnknown))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IUnknown>())).lpVtbl as *const _ as usize
-        },
+        ::std::mem::offset_of!(IUnknown, lpVtbl),
         0usize,
         concat!(
             "Offset of field: ",
@@ -548,28 +496,28 @@ impl Default for IUnknown {
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct IClassFactoryVtbl {
     pub QueryInterface: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IClassFactory,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub AddRef: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IClassFactory) -> ULONG,
+        unsafe extern "system" fn(This: *mut IClassFactory) -> ULONG,
     >,
     pub Release: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IClassFactory) -> ULONG,
+        unsafe extern "system" fn(This: *mut IClassFactory) -> ULONG,
     >,
     pub CreateInstance: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IClassFactory,
             pUnkOuter: *mut IUnknown,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub LockServer: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IClassFactory, fLock: BOOL) -> HRESULT,
+        unsafe extern "system" fn(This: *mut IClassFactory, fLock: BOOL) -> HRESULT,
     >,
 }
 #[test]
@@ -585,10 +533,7 @@ fn bindgen_test_layout_IClassFactoryVtbl() {
         concat!("Alignment of ", stringify!(IClassFactoryVtbl))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactoryVtbl>())).QueryInterface
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IClassFactoryVtbl, QueryInterface),
         0usize,
         concat!(
             "Offset of field: ",
@@ -598,10 +543,7 @@ fn bindgen_test_layout_IClassFactoryVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactoryVtbl>())).AddRef as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IClassFactoryVtbl, AddRef),
         8usize,
         concat!(
             "Offset of field: ",
@@ -611,10 +553,7 @@ fn bindgen_test_layout_IClassFactoryVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactoryVtbl>())).Release as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IClassFactoryVtbl, Release),
         16usize,
         concat!(
             "Offset of field: ",
@@ -624,10 +563,7 @@ fn bindgen_test_layout_IClassFactoryVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactoryVtbl>())).CreateInstance
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IClassFactoryVtbl, CreateInstance),
         24usize,
         concat!(
             "Offset of field: ",
@@ -637,10 +573,7 @@ fn bindgen_test_layout_IClassFactoryVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactoryVtbl>())).LockServer as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IClassFactoryVtbl, LockServer),
         32usize,
         concat!(
             "Offset of field: ",
@@ -668,10 +601,7 @@ fn bindgen_test_layout_IClassFactory() {
         concat!("Alignment of ", stringify!(IClassFactory))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IClassFactory>())).lpVtbl as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IClassFactory, lpVtbl),
         0usize,
         concat!(
             "Offset of field: ",
@@ -712,9 +642,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         concat!("Alignment of ", stringify!(tagSTATSTG))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).pwcsName as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, pwcsName),
         0usize,
         concat!(
             "Offset of field: ",
@@ -724,9 +652,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).type_ as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, type_),
         8usize,
         concat!(
             "Offset of field: ",
@@ -736,9 +662,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).cbSize as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, cbSize),
         16usize,
         concat!(
             "Offset of field: ",
@@ -748,9 +672,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).mtime as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, mtime),
         24usize,
         concat!(
             "Offset of field: ",
@@ -760,9 +682,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).ctime as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, ctime),
         32usize,
         concat!(
             "Offset of field: ",
@@ -772,9 +692,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).atime as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, atime),
         40usize,
         concat!(
             "Offset of field: ",
@@ -784,9 +702,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).grfMode as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, grfMode),
         48usize,
         concat!(
             "Offset of field: ",
@@ -796,10 +712,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).grfLocksSupported as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, grfLocksSupported),
         52usize,
         concat!(
             "Offset of field: ",
@@ -809,9 +722,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).clsid as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, clsid),
         56usize,
         concat!(
             "Offset of field: ",
@@ -821,10 +732,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).grfStateBits as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, grfStateBits),
         72usize,
         concat!(
             "Offset of field: ",
@@ -834,9 +742,7 @@ fn bindgen_test_layout_tagSTATSTG() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<tagSTATSTG>())).reserved as *const _ as usize
-        },
+        ::std::mem::offset_of!(tagSTATSTG, reserved),
         76usize,
         concat!(
             "Offset of field: ",
@@ -859,36 +765,36 @@ pub type STATSTG = tagSTATSTG;
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct IStreamVtbl {
     pub QueryInterface: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub AddRef: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IStream) -> ULONG,
+        unsafe extern "system" fn(This: *mut IStream) -> ULONG,
     >,
     pub Release: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IStream) -> ULONG,
+        unsafe extern "system" fn(This: *mut IStream) -> ULONG,
     >,
     pub Read: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
-            pv: *mut ::std::os::raw::c_void,
+            pv: *mut ctypes::c_void,
             cb: ULONG,
             pcbRead: *mut ULONG,
         ) -> HRESULT,
     >,
     pub Write: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
-            pv: *const ::std::os::raw::c_void,
+            pv: *const ctypes::c_void,
             cb: ULONG,
             pcbWritten: *mut ULONG,
         ) -> HRESULT,
     >,
     pub Seek: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             dlibMove: LARGE_INTEGER,
             dwOrigin: DWORD,
@@ -896,13 +802,13 @@ pub struct IStreamVtbl {
         ) -> HRESULT,
     >,
     pub SetSize: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             libNewSize: ULARGE_INTEGER,
         ) -> HRESULT,
     >,
     pub CopyTo: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             pstm: *mut IStream,
             cb: ULARGE_INTEGER,
@@ -911,16 +817,16 @@ pub struct IStreamVtbl {
         ) -> HRESULT,
     >,
     pub Commit: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             grfCommitFlags: DWORD,
         ) -> HRESULT,
     >,
     pub Revert: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IStream) -> HRESULT,
+        unsafe extern "system" fn(This: *mut IStream) -> HRESULT,
     >,
     pub LockRegion: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             libOffset: ULARGE_INTEGER,
             cb: ULARGE_INTEGER,
@@ -928,7 +834,7 @@ pub struct IStreamVtbl {
         ) -> HRESULT,
     >,
     pub UnlockRegion: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             libOffset: ULARGE_INTEGER,
             cb: ULARGE_INTEGER,
@@ -936,14 +842,14 @@ pub struct IStreamVtbl {
         ) -> HRESULT,
     >,
     pub Stat: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             pstatstg: *mut STATSTG,
             grfStatFlag: DWORD,
         ) -> HRESULT,
     >,
     pub Clone: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IStream,
             ppstm: *mut *mut IStream,
         ) -> HRESULT,
@@ -962,10 +868,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         concat!("Alignment of ", stringify!(IStreamVtbl))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).QueryInterface as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, QueryInterface),
         0usize,
         concat!(
             "Offset of field: ",
@@ -975,9 +878,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).AddRef as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, AddRef),
         8usize,
         concat!(
             "Offset of field: ",
@@ -987,9 +888,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Release as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Release),
         16usize,
         concat!(
             "Offset of field: ",
@@ -999,9 +898,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Read as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Read),
         24usize,
         concat!(
             "Offset of field: ",
@@ -1011,9 +908,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Write as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Write),
         32usize,
         concat!(
             "Offset of field: ",
@@ -1023,9 +918,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Seek as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Seek),
         40usize,
         concat!(
             "Offset of field: ",
@@ -1035,9 +928,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).SetSize as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, SetSize),
         48usize,
         concat!(
             "Offset of field: ",
@@ -1047,9 +938,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).CopyTo as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, CopyTo),
         56usize,
         concat!(
             "Offset of field: ",
@@ -1059,9 +948,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Commit as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Commit),
         64usize,
         concat!(
             "Offset of field: ",
@@ -1071,9 +958,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Revert as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Revert),
         72usize,
         concat!(
             "Offset of field: ",
@@ -1083,10 +968,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).LockRegion as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, LockRegion),
         80usize,
         concat!(
             "Offset of field: ",
@@ -1096,10 +978,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).UnlockRegion as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, UnlockRegion),
         88usize,
         concat!(
             "Offset of field: ",
@@ -1109,9 +988,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Stat as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Stat),
         96usize,
         concat!(
             "Offset of field: ",
@@ -1121,9 +998,7 @@ fn bindgen_test_layout_IStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStreamVtbl>())).Clone as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStreamVtbl, Clone),
         104usize,
         concat!(
             "Offset of field: ",
@@ -1151,9 +1026,7 @@ fn bindgen_test_layout_IStream() {
         concat!("Alignment of ", stringify!(IStream))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IStream>())).lpVtbl as *const _ as usize
-        },
+        ::std::mem::offset_of!(IStream, lpVtbl),
         0usize,
         concat!(
             "Offset of field: ",
@@ -1170,20 +1043,20 @@ impl Default for IStream {
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct IInitializeWithStreamVtbl {
     pub QueryInterface: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IInitializeWithStream,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub AddRef: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IInitializeWithStream) -> ULONG,
+        unsafe extern "system" fn(This: *mut IInitializeWithStream) -> ULONG,
     >,
     pub Release: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IInitializeWithStream) -> ULONG,
+        unsafe extern "system" fn(This: *mut IInitializeWithStream) -> ULONG,
     >,
     pub Initialize: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IInitializeWithStream,
             pstream: *mut IStream,
             grfMode: DWORD,
@@ -1203,10 +1076,7 @@ fn bindgen_test_layout_IInitializeWithStreamVtbl() {
         concat!("Alignment of ", stringify!(IInitializeWithStreamVtbl))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IInitializeWithStreamVtbl>())).QueryInterface
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IInitializeWithStreamVtbl, QueryInterface),
         0usize,
         concat!(
             "Offset of field: ",
@@ -1216,10 +1086,7 @@ fn bindgen_test_layout_IInitializeWithStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IInitializeWithStreamVtbl>())).AddRef
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IInitializeWithStreamVtbl, AddRef),
         8usize,
         concat!(
             "Offset of field: ",
@@ -1229,10 +1096,7 @@ fn bindgen_test_layout_IInitializeWithStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IInitializeWithStreamVtbl>())).Release
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IInitializeWithStreamVtbl, Release),
         16usize,
         concat!(
             "Offset of field: ",
@@ -1242,10 +1106,7 @@ fn bindgen_test_layout_IInitializeWithStreamVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IInitializeWithStreamVtbl>())).Initialize
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IInitializeWithStreamVtbl, Initialize),
         24usize,
         concat!(
             "Offset of field: ",
@@ -1273,10 +1134,7 @@ fn bindgen_test_layout_IInitializeWithStream() {
         concat!("Alignment of ", stringify!(IInitializeWithStream))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IInitializeWithStream>())).lpVtbl as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IInitializeWithStream, lpVtbl),
         0usize,
         concat!(
             "Offset of field: ",
@@ -1289,6 +1147,435 @@ fn bindgen_test_layout_IInitializeWithStream() {
 impl Default for IInitializeWithStream {
     fn default() -> Self { unsafe { ::std::mem::zeroed() } }
 }
+pub type LPWSTR = *mut WCHAR;
+pub type LPCWSTR = *const WCHAR;
+pub type SFGAOF = ULONG;
+pub type SICHINTF = DWORD;
+pub const _SIGDN_SIGDN_NORMALDISPLAY: _SIGDN = 0;
+pub const _SIGDN_SIGDN_PARENTRELATIVEPARSING: _SIGDN = -2147385343;
+pub const _SIGDN_SIGDN_DESKTOPABSOLUTEPARSING: _SIGDN = -2147319808;
+pub const _SIGDN_SIGDN_PARENTRELATIVEEDITING: _SIGDN = -2147282943;
+pub const _SIGDN_SIGDN_DESKTOPABSOLUTEEDITING: _SIGDN = -2147172352;
+pub const _SIGDN_SIGDN_FILESYSPATH: _SIGDN = -2147123200;
+pub const _SIGDN_SIGDN_URL: _SIGDN = -2147057664;
+pub const _SIGDN_SIGDN_PARENTRELATIVEFORADDRESSBAR: _SIGDN = -2146975743;
+pub const _SIGDN_SIGDN_PARENTRELATIVE: _SIGDN = -2146959359;
+pub const _SIGDN_SIGDN_PARENTRELATIVEFORUI: _SIGDN = -2146877439;
+pub type _SIGDN = ctypes::c_int;
+pub use self::_SIGDN as SIGDN;
+#[repr(C)]
+#[repr(align(8))]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct IBindCtx {
+    pub _bindgen_opaque_blob: u64,
+}
+#[test]
+fn bindgen_test_layout_IBindCtx() {
+    assert_eq!(
+        ::std::mem::size_of::<IBindCtx>(),
+        8usize,
+        concat!("Size of: ", stringify!(IBindCtx))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IBindCtx>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IBindCtx))
+    );
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct IShellItemVtbl {
+    pub QueryInterface: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            riid: *const IID,
+            ppvObject: *mut *mut ctypes::c_void,
+        ) -> HRESULT,
+    >,
+    pub AddRef: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IShellItem) -> ULONG,
+    >,
+    pub Release: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IShellItem) -> ULONG,
+    >,
+    pub BindToHandler: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            pbc: *mut IBindCtx,
+            bhid: *const GUID,
+            riid: *const IID,
+            ppv: *mut *mut ctypes::c_void,
+        ) -> HRESULT,
+    >,
+    pub GetParent: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            ppsi: *mut *mut IShellItem,
+        ) -> HRESULT,
+    >,
+    pub GetDisplayName: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            sigdnName: SIGDN,
+            ppszName: *mut LPWSTR,
+        ) -> HRESULT,
+    >,
+    pub GetAttributes: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            sfgaoMask: SFGAOF,
+            psfgaoAttribs: *mut SFGAOF,
+        ) -> HRESULT,
+    >,
+    pub Compare: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IShellItem,
+            psi: *mut IShellItem,
+            hint: SICHINTF,
+            piOrder: *mut ctypes::c_int,
+        ) -> HRESULT,
+    >,
+}
+#[test]
+fn bindgen_test_layout_IShellItemVtbl() {
+    assert_eq!(
+        ::std::mem::size_of::<IShellItemVtbl>(),
+        64usize,
+        concat!("Size of: ", stringify!(IShellItemVtbl))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IShellItemVtbl>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IShellItemVtbl))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, QueryInterface),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(QueryInterface)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, AddRef),
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(AddRef)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, Release),
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(Release)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, BindToHandler),
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(BindToHandler)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, GetParent),
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(GetParent)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, GetDisplayName),
+        40usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(GetDisplayName)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, GetAttributes),
+        48usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(GetAttributes)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItemVtbl, Compare),
+        56usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItemVtbl),
+            "::",
+            stringify!(Compare)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IShellItem {
+    pub lpVtbl: *mut IShellItemVtbl,
+}
+#[test]
+fn bindgen_test_layout_IShellItem() {
+    assert_eq!(
+        ::std::mem::size_of::<IShellItem>(),
+        8usize,
+        concat!("Size of: ", stringify!(IShellItem))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IShellItem>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IShellItem))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IShellItem, lpVtbl),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IShellItem),
+            "::",
+            stringify!(lpVtbl)
+        )
+    );
+}
+impl Default for IShellItem {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct IInitializeWithFileVtbl {
+    pub QueryInterface: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IInitializeWithFile,
+            riid: *const IID,
+            ppvObject: *mut *mut ctypes::c_void,
+        ) -> HRESULT,
+    >,
+    pub AddRef: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IInitializeWithFile) -> ULONG,
+    >,
+    pub Release: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IInitializeWithFile) -> ULONG,
+    >,
+    pub Initialize: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IInitializeWithFile,
+            pszFilePath: LPCWSTR,
+            grfMode: DWORD,
+        ) -> HRESULT,
+    >,
+}
+#[test]
+fn bindgen_test_layout_IInitializeWithFileVtbl() {
+    assert_eq!(
+        ::std::mem::size_of::<IInitializeWithFileVtbl>(),
+        32usize,
+        concat!("Size of: ", stringify!(IInitializeWithFileVtbl))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IInitializeWithFileVtbl>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IInitializeWithFileVtbl))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithFileVtbl, QueryInterface),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithFileVtbl),
+            "::",
+            stringify!(QueryInterface)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithFileVtbl, AddRef),
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithFileVtbl),
+            "::",
+            stringify!(AddRef)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithFileVtbl, Release),
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithFileVtbl),
+            "::",
+            stringify!(Release)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithFileVtbl, Initialize),
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithFileVtbl),
+            "::",
+            stringify!(Initialize)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IInitializeWithFile {
+    pub lpVtbl: *mut IInitializeWithFileVtbl,
+}
+#[test]
+fn bindgen_test_layout_IInitializeWithFile() {
+    assert_eq!(
+        ::std::mem::size_of::<IInitializeWithFile>(),
+        8usize,
+        concat!("Size of: ", stringify!(IInitializeWithFile))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IInitializeWithFile>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IInitializeWithFile))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithFile, lpVtbl),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithFile),
+            "::",
+            stringify!(lpVtbl)
+        )
+    );
+}
+impl Default for IInitializeWithFile {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct IInitializeWithItemVtbl {
+    pub QueryInterface: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IInitializeWithItem,
+            riid: *const IID,
+            ppvObject: *mut *mut ctypes::c_void,
+        ) -> HRESULT,
+    >,
+    pub AddRef: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IInitializeWithItem) -> ULONG,
+    >,
+    pub Release: ::std::option::Option<
+        unsafe extern "system" fn(This: *mut IInitializeWithItem) -> ULONG,
+    >,
+    pub Initialize: ::std::option::Option<
+        unsafe extern "system" fn(
+            This: *mut IInitializeWithItem,
+            psi: *mut IShellItem,
+            grfMode: DWORD,
+        ) -> HRESULT,
+    >,
+}
+#[test]
+fn bindgen_test_layout_IInitializeWithItemVtbl() {
+    assert_eq!(
+        ::std::mem::size_of::<IInitializeWithItemVtbl>(),
+        32usize,
+        concat!("Size of: ", stringify!(IInitializeWithItemVtbl))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IInitializeWithItemVtbl>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IInitializeWithItemVtbl))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithItemVtbl, QueryInterface),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithItemVtbl),
+            "::",
+            stringify!(QueryInterface)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithItemVtbl, AddRef),
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithItemVtbl),
+            "::",
+            stringify!(AddRef)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithItemVtbl, Release),
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithItemVtbl),
+            "::",
+            stringify!(Release)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithItemVtbl, Initialize),
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithItemVtbl),
+            "::",
+            stringify!(Initialize)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IInitializeWithItem {
+    pub lpVtbl: *mut IInitializeWithItemVtbl,
+}
+#[test]
+fn bindgen_test_layout_IInitializeWithItem() {
+    assert_eq!(
+        ::std::mem::size_of::<IInitializeWithItem>(),
+        8usize,
+        concat!("Size of: ", stringify!(IInitializeWithItem))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<IInitializeWithItem>(),
+        8usize,
+        concat!("Alignment of ", stringify!(IInitializeWithItem))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(IInitializeWithItem, lpVtbl),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(IInitializeWithItem),
+            "::",
+            stringify!(lpVtbl)
+        )
+    );
+}
+impl Default for IInitializeWithItem {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
 pub const WTS_ALPHATYPE_WTSAT_UNKNOWN: WTS_ALPHATYPE = 0;
 pub const WTS_ALPHATYPE_WTSAT_RGB: WTS_ALPHATYPE = 1;
 pub const WTS_ALPHATYPE_WTSAT_ARGB: WTS_ALPHATYPE = 2;
@@ -1297,20 +1584,20 @@ pub type WTS_ALPHATYPE = i32;
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct IThumbnailProviderVtbl {
     pub QueryInterface: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IThumbnailProvider,
             riid: *const IID,
-            ppvObject: *mut *mut ::std::os::raw::c_void,
+            ppvObject: *mut *mut ctypes::c_void,
         ) -> HRESULT,
     >,
     pub AddRef: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IThumbnailProvider) -> ULONG,
+        unsafe extern "system" fn(This: *mut IThumbnailProvider) -> ULONG,
     >,
     pub Release: ::std::option::Option<
-        unsafe extern "C" fn(This: *mut IThumbnailProvider) -> ULONG,
+        unsafe extern "system" fn(This: *mut IThumbnailProvider) -> ULONG,
     >,
     pub GetThumbnail: ::std::option::Option<
-        unsafe extern "C" fn(
+        unsafe extern "system" fn(
             This: *mut IThumbnailProvider,
             cx: UINT,
             phbmp: *mut HBITMAP,
@@ -1331,10 +1618,7 @@ fn bindgen_test_layout_IThumbnailProviderVtbl() {
         concat!("Alignment of ", stringify!(IThumbnailProviderVtbl))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IThumbnailProviderVtbl>())).QueryInterface
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IThumbnailProviderVtbl, QueryInterface),
         0usize,
         concat!(
             "Offset of field: ",
@@ -1344,10 +1628,7 @@ fn bindgen_test_layout_IThumbnailProviderVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IThumbnailProviderVtbl>())).AddRef
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IThumbnailProviderVtbl, AddRef),
         8usize,
         concat!(
             "Offset of field: ",
@@ -1357,10 +1638,7 @@ fn bindgen_test_layout_IThumbnailProviderVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IThumbnailProviderVtbl>())).Release
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IThumbnailProviderVtbl, Release),
         16usize,
         concat!(
             "Offset of field: ",
@@ -1370,10 +1648,7 @@ fn bindgen_test_layout_IThumbnailProviderVtbl() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IThumbnailProviderVtbl>())).GetThumbnail
-                as *const _ as usize
-        },
+        ::std::mem::offset_of!(IThumbnailProviderVtbl, GetThumbnail),
         24usize,
         concat!(
             "Offset of field: ",
@@ -1401,10 +1676,7 @@ fn bindgen_test_layout_IThumbnailProvider() {
         concat!("Alignment of ", stringify!(IThumbnailProvider))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<IThumbnailProvider>())).lpVtbl as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(IThumbnailProvider, lpVtbl),
         0usize,
         concat!(
             "Offset of field: ",
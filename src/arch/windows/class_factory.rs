@@ -0,0 +1,366 @@
+use crate::{
+    arch::windows::{
+        guard::guard,
+        sys::{
+            IClassFactory, IClassFactoryVtbl, IUnknown, BOOL,
+            CLASS_E_NOAGGREGATION, E_NOINTERFACE, E_POINTER, E_UNEXPECTED,
+            GUID, HRESULT, S_OK, ULONG,
+        },
+        Wrapper,
+    },
+    ThumbnailProvider,
+};
+use field_offset::FieldOffset;
+use image::{GenericImageView, Pixel};
+use std::{
+    os::raw::c_void,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Keeps track of whether a DLL is still being used, so we can answer
+/// `DllCanUnloadNow()`.
+#[derive(Debug, Default)]
+pub(crate) struct ModuleCount {
+    objects: AtomicUsize,
+    locks: AtomicUsize,
+}
+
+/// The [`ModuleCount`] for the DLL this crate has been compiled into.
+pub(crate) static MODULE_COUNT: ModuleCount = ModuleCount::new();
+
+impl ModuleCount {
+    pub(crate) const fn new() -> Self {
+        ModuleCount {
+            objects: AtomicUsize::new(0),
+            locks: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn object_created(&self) {
+        self.objects.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn object_destroyed(&self) {
+        self.objects.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn lock(&self) { self.locks.fetch_add(1, Ordering::SeqCst); }
+
+    /// Release a lock, ignoring unbalanced calls so a misbehaving client
+    /// can't wrap the count around and keep the DLL loaded forever.
+    pub(crate) fn unlock(&self) {
+        let _ = self.locks.fetch_update(
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+            |locks| locks.checked_sub(1),
+        );
+    }
+
+    /// Are there no live objects or calls to `IClassFactory::LockServer()`
+    /// holding the DLL in memory?
+    pub(crate) fn can_unload(&self) -> bool {
+        self.objects.load(Ordering::SeqCst) == 0
+            && self.locks.load(Ordering::SeqCst) == 0
+    }
+}
+
+/// An implementation of [`IClassFactory`] which creates a new [`Wrapper`]
+/// around the provider returned by `F`.
+///
+/// You normally won't need to use this directly, the
+/// [`crate::thumbnail_provider_dll`] macro will create it for you.
+#[repr(C)]
+pub struct ClassFactory<F> {
+    vtable: *mut IClassFactoryVtbl,
+    ref_count: AtomicUsize,
+    create: F,
+}
+
+impl<F, P> ClassFactory<F>
+where
+    F: Fn() -> P + Send + Sync + 'static,
+    P: ThumbnailProvider + Send + Sync + 'static,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+{
+    const VTABLE: IClassFactoryVtbl = IClassFactoryVtbl {
+        QueryInterface: Some(class_factory_query_interface::<F>),
+        AddRef: Some(class_factory_add_ref::<F>),
+        Release: Some(class_factory_release::<F>),
+        CreateInstance: Some(class_factory_create_instance::<F, P>),
+        LockServer: Some(class_factory_lock_server),
+    };
+
+    /// Create a new `IClassFactory` with a reference count of 1.
+    pub fn new_class_factory(create: F) -> *mut IClassFactory {
+        MODULE_COUNT.object_created();
+
+        let boxed = Box::new(ClassFactory {
+            // See the note in Wrapper::new() about promoted constants
+            vtable: &ClassFactory::<F>::VTABLE as *const _ as *mut _,
+            ref_count: AtomicUsize::new(1),
+            create,
+        });
+
+        let raw = Box::into_raw(boxed);
+        // SAFETY: we just created this pointer from a box
+        unsafe { (*raw).as_class_factory() }
+    }
+}
+
+impl<F> ClassFactory<F> {
+    /// Get a pointer to the [`ClassFactory`] from a pointer to its
+    /// `IClassFactory` interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IClassFactory` interface of a
+    /// `ClassFactory<F>`.
+    pub unsafe fn from_class_factory(raw: *mut IClassFactory) -> *mut Self {
+        let offset = FieldOffset::new(|c: *const ClassFactory<F>| &(*c).vtable);
+        let vtable: *mut *mut IClassFactoryVtbl = &mut (*raw).lpVtbl;
+        offset.unapply_ptr_mut(vtable)
+    }
+
+    pub fn as_class_factory(&self) -> *mut IClassFactory {
+        &self.vtable as *const *mut IClassFactoryVtbl as *mut _
+    }
+}
+
+impl<F> Drop for ClassFactory<F> {
+    fn drop(&mut self) { MODULE_COUNT.object_destroyed(); }
+}
+
+unsafe extern "system" fn class_factory_query_interface<F>(
+    this: *mut IClassFactory,
+    guid: *const GUID,
+    p: *mut *mut c_void,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
+
+        let guid = &*guid;
+
+        if guid == &IUnknown::IID || guid == &IClassFactory::IID {
+            class_factory_add_ref::<F>(this);
+            *p = this as *mut c_void;
+            S_OK
+        } else {
+            *p = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    })
+}
+
+unsafe extern "system" fn class_factory_add_ref<F>(
+    this: *mut IClassFactory,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        let this = &*ClassFactory::<F>::from_class_factory(this);
+        this.ref_count.fetch_add(1, Ordering::SeqCst) as ULONG + 1
+    })
+}
+
+unsafe extern "system" fn class_factory_release<F>(
+    this: *mut IClassFactory,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        let this = ClassFactory::<F>::from_class_factory(this);
+        let count = (*this).ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
+
+        if count == 0 {
+            let _ = Box::from_raw(this);
+        }
+
+        count as ULONG
+    })
+}
+
+unsafe extern "system" fn class_factory_create_instance<F, P>(
+    this: *mut IClassFactory,
+    outer: *mut IUnknown,
+    guid: *const GUID,
+    p: *mut *mut c_void,
+) -> HRESULT
+where
+    F: Fn() -> P,
+    P: ThumbnailProvider + Send + Sync + 'static,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+{
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
+
+        *p = ptr::null_mut();
+
+        if !outer.is_null() {
+            return CLASS_E_NOAGGREGATION;
+        }
+
+        let this = &*ClassFactory::<F>::from_class_factory(this);
+        let unknown = Wrapper::new_unknown((this.create)());
+
+        // the caller gets their own reference from QueryInterface(), so we
+        // can drop the one we were given (destroying the object on failure)
+        let ret =
+            (*(*unknown).lpVtbl).QueryInterface.unwrap()(unknown, guid, p);
+        (*(*unknown).lpVtbl).Release.unwrap()(unknown);
+
+        ret
+    })
+}
+
+unsafe extern "system" fn class_factory_lock_server(
+    _this: *mut IClassFactory,
+    lock: BOOL,
+) -> HRESULT {
+    if lock != 0 {
+        MODULE_COUNT.lock();
+    } else {
+        MODULE_COUNT.unlock();
+    }
+
+    S_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arch::windows::sys::IThumbnailProvider, Dimensions};
+    use image::RgbaImage;
+    use std::io::Read;
+
+    #[test]
+    fn count_objects_and_locks() {
+        let count = ModuleCount::new();
+        assert!(count.can_unload());
+
+        count.object_created();
+        count.lock();
+        assert!(!count.can_unload());
+
+        count.object_destroyed();
+        assert!(!count.can_unload());
+
+        count.unlock();
+        assert!(count.can_unload());
+    }
+
+    #[test]
+    fn unbalanced_unlocks_are_ignored() {
+        let count = ModuleCount::new();
+
+        count.unlock();
+        assert!(count.can_unload());
+
+        count.lock();
+        count.unlock();
+        count.unlock();
+        assert!(count.can_unload());
+    }
+
+    struct DummyProvider;
+
+    impl ThumbnailProvider for DummyProvider {
+        type Error = std::io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _reader: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn create_a_thumbnail_provider() {
+        unsafe {
+            let factory = ClassFactory::new_class_factory(|| DummyProvider);
+            let create_instance = (*(*factory).lpVtbl).CreateInstance.unwrap();
+            let mut p = ptr::null_mut();
+
+            let ret = create_instance(
+                factory,
+                ptr::null_mut(),
+                &IThumbnailProvider::IID,
+                &mut p,
+            );
+
+            assert_eq!(ret, S_OK);
+            assert!(!p.is_null());
+            let thumbnail_provider = p as *mut IThumbnailProvider;
+            let wrapper = Wrapper::<DummyProvider>::from_thumbnail_provider(
+                thumbnail_provider,
+            );
+            assert!(ptr::eq(
+                (*wrapper).as_thumbnail_provider(),
+                thumbnail_provider
+            ));
+
+            // the caller owns the only reference
+            let release = (*(*thumbnail_provider).lpVtbl).Release.unwrap();
+            assert_eq!(release(thumbnail_provider), 0);
+            assert_eq!((*(*factory).lpVtbl).Release.unwrap()(factory), 0);
+        }
+    }
+
+    #[test]
+    fn unsupported_interfaces_and_aggregation_are_rejected() {
+        unsafe {
+            let factory = ClassFactory::new_class_factory(|| DummyProvider);
+            let create_instance = (*(*factory).lpVtbl).CreateInstance.unwrap();
+            let mut p = ptr::null_mut();
+
+            let ret = create_instance(
+                factory,
+                ptr::null_mut(),
+                &IClassFactory::IID,
+                &mut p,
+            );
+            assert_eq!(ret, E_NOINTERFACE);
+            assert!(p.is_null());
+
+            let ret = create_instance(
+                factory,
+                factory as *mut IUnknown,
+                &IThumbnailProvider::IID,
+                &mut p,
+            );
+            assert_eq!(ret, CLASS_E_NOAGGREGATION);
+            assert!(p.is_null());
+
+            (*(*factory).lpVtbl).Release.unwrap()(factory);
+        }
+    }
+
+    #[test]
+    fn query_the_factory_for_its_interfaces() {
+        unsafe {
+            let factory = ClassFactory::new_class_factory(|| DummyProvider);
+            let query_interface = (*(*factory).lpVtbl).QueryInterface.unwrap();
+            let release = (*(*factory).lpVtbl).Release.unwrap();
+
+            for iid in &[IUnknown::IID, IClassFactory::IID] {
+                let mut p = ptr::null_mut();
+                assert_eq!(query_interface(factory, iid, &mut p), S_OK);
+                assert!(ptr::eq(p as *mut IClassFactory, factory));
+                assert_eq!(release(factory), 1);
+            }
+
+            assert_eq!(release(factory), 0);
+        }
+    }
+}
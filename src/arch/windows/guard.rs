@@ -0,0 +1,84 @@
+//! Stopping panics from unwinding across the COM boundary.
+
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+};
+
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Call `func`, returning `on_panic` if it panics.
+///
+/// Unwinding into the caller of a COM method is undefined behaviour (and
+/// usually crashes Explorer), so every vtable entry point should go through
+/// this function.
+pub(crate) fn guard<T, F>(on_panic: T, func: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    match panic::catch_unwind(AssertUnwindSafe(func)) {
+        Ok(value) => value,
+        Err(payload) => {
+            record_panic(&*payload);
+            on_panic
+        },
+    }
+}
+
+/// The message from the most recent panic caught at the COM boundary, if
+/// there has been one.
+pub fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn record_panic(payload: &(dyn Any + Send)) {
+    let msg = panic_message(payload);
+    *LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = Some(msg);
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        String::from(*s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_are_caught_and_recorded() {
+        let got = guard(42, || -> i32 { panic!("Oops") });
+
+        assert_eq!(got, 42);
+        // other tests may panic concurrently, so we can't check the message
+        assert!(last_panic().is_some());
+    }
+
+    #[test]
+    fn values_are_passed_through() {
+        let got = guard(42, || 1 + 1);
+
+        assert_eq!(got, 2);
+    }
+
+    #[test]
+    fn panic_messages() {
+        let inputs: Vec<(Box<dyn Any + Send>, &str)> = vec![
+            (Box::new("static"), "static"),
+            (Box::new(String::from("owned")), "owned"),
+            (Box::new(42), "Box<dyn Any>"),
+        ];
+
+        for (payload, should_be) in inputs {
+            let got = panic_message(&*payload);
+
+            assert_eq!(got, should_be);
+        }
+    }
+}
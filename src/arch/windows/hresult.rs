@@ -0,0 +1,134 @@
+//! Reporting errors to COM as `HRESULT`s.
+
+use crate::{
+    arch::windows::sys::{
+        ERROR_CANCELLED, ERROR_FILE_CORRUPT, E_FAIL, E_INVALIDARG,
+        E_OUTOFMEMORY, HRESULT, HRESULT_FROM_WIN32, WTS_E_EXTRACTIONTIMEDOUT,
+        WTS_E_FAILEDEXTRACTION,
+    },
+    ThumbnailError,
+};
+use std::{error::Error, io};
+
+/// Get the `HRESULT` which best describes an error.
+///
+/// If the error (or one of its sources) is a [`ThumbnailError`] it is
+/// translated as follows, otherwise we fall back to the first [`io::Error`]
+/// in the chain or `E_FAIL`.
+///
+/// | Error                            | `HRESULT`                       |
+/// | -------------------------------- | ------------------------------- |
+/// | [`ThumbnailError::Unsupported`]  | `WTS_E_FAILEDEXTRACTION`        |
+/// | [`ThumbnailError::Corrupt`]      | `HRESULT_FROM_WIN32(ERROR_FILE_CORRUPT)` |
+/// | [`ThumbnailError::TooLarge`]     | `E_OUTOFMEMORY`                 |
+/// | [`ThumbnailError::LimitExceeded`] | `E_OUTOFMEMORY`                |
+/// | [`ThumbnailError::TimedOut`]     | `WTS_E_EXTRACTIONTIMEDOUT`      |
+/// | [`ThumbnailError::Cancelled`]    | `HRESULT_FROM_WIN32(ERROR_CANCELLED)` |
+/// | [`ThumbnailError::Io`]           | the OS error code, if there is one, otherwise `E_FAIL` |
+/// | [`ThumbnailError::Internal`]     | `E_FAIL`                        |
+pub fn get_hresult(error: &(dyn Error + 'static)) -> HRESULT {
+    if let Some(e) = ThumbnailError::find(error) {
+        return thumbnail_error_to_hresult(e);
+    }
+
+    let mut error = Some(error);
+
+    while let Some(e) = error {
+        if let Some(io_error) = e.downcast_ref::<io::Error>() {
+            return io_error_to_hresult(io_error, E_FAIL);
+        }
+
+        error = e.source();
+    }
+
+    E_FAIL
+}
+
+fn thumbnail_error_to_hresult(error: &ThumbnailError) -> HRESULT {
+    match error {
+        ThumbnailError::Unsupported(_) => WTS_E_FAILEDEXTRACTION,
+        ThumbnailError::Corrupt(_) => HRESULT_FROM_WIN32(ERROR_FILE_CORRUPT),
+        ThumbnailError::TooLarge(_) | ThumbnailError::LimitExceeded(_) => {
+            E_OUTOFMEMORY
+        },
+        ThumbnailError::TimedOut => WTS_E_EXTRACTIONTIMEDOUT,
+        ThumbnailError::Cancelled => HRESULT_FROM_WIN32(ERROR_CANCELLED),
+        ThumbnailError::Io(e) => io_error_to_hresult(e, E_FAIL),
+        ThumbnailError::Internal(_) => E_FAIL,
+    }
+}
+
+/// Translate an [`io::Error`] into the closest `HRESULT`, using `fallback`
+/// when there is no obvious equivalent.
+pub(crate) fn io_error_to_hresult(
+    error: &io::Error,
+    fallback: HRESULT,
+) -> HRESULT {
+    match error.raw_os_error() {
+        // errors from an IStream are already HRESULTs
+        Some(code) if code < 0 => code as HRESULT,
+        Some(code) => HRESULT_FROM_WIN32(code as u32),
+        None => match error.kind() {
+            io::ErrorKind::InvalidInput => E_INVALIDARG,
+            io::ErrorKind::OutOfMemory => E_OUTOFMEMORY,
+            _ => fallback,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arch::windows::sys::STG_E_READFAULT, Limit, RegistryError};
+
+    #[test]
+    fn thumbnail_errors() {
+        let inputs = vec![
+            (ThumbnailError::unsupported("PDF"), WTS_E_FAILEDEXTRACTION),
+            (ThumbnailError::corrupt("Bad header"), 0x80070570_u32 as i32),
+            (ThumbnailError::too_large("1 GB"), E_OUTOFMEMORY),
+            (
+                ThumbnailError::LimitExceeded(Limit::Pixels(42)),
+                E_OUTOFMEMORY,
+            ),
+            (ThumbnailError::TimedOut, WTS_E_EXTRACTIONTIMEDOUT),
+            (ThumbnailError::Cancelled, 0x800704C7_u32 as i32),
+            (ThumbnailError::Io(io::Error::other("Oops")), E_FAIL),
+            (ThumbnailError::internal("Bug"), E_FAIL),
+        ];
+
+        for (error, should_be) in inputs {
+            let got = get_hresult(&error);
+
+            assert_eq!(got, should_be, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn look_through_the_error_chain() {
+        let error =
+            RegistryError::AllFailed(vec![Box::new(ThumbnailError::TimedOut)]);
+
+        let got = get_hresult(&error);
+
+        assert_eq!(got, WTS_E_EXTRACTIONTIMEDOUT);
+    }
+
+    #[test]
+    fn hresults_from_a_stream_are_passed_through() {
+        let error = io::Error::from_raw_os_error(STG_E_READFAULT);
+
+        let got = get_hresult(&error);
+
+        assert_eq!(got, STG_E_READFAULT);
+    }
+
+    #[test]
+    fn unknown_errors_are_a_generic_failure() {
+        let error: Box<dyn Error> = "Oops".into();
+
+        let got = get_hresult(&*error);
+
+        assert_eq!(got, E_FAIL);
+    }
+}
@@ -1,4 +1,17 @@
+mod class_factory;
+mod guard;
+mod hresult;
+mod server;
+mod stream;
 pub mod sys;
 mod wrapper;
 
-pub use wrapper::Wrapper;
+pub use class_factory::ClassFactory;
+pub use guard::last_panic;
+pub use hresult::get_hresult;
+pub use server::{
+    dll_can_unload_now, dll_get_class_object, dll_register_server,
+    dll_unregister_server,
+};
+pub use stream::RustStream;
+pub use wrapper::{Wrapper, DEFAULT_TIMEOUT};
@@ -0,0 +1,446 @@
+//! The functions a COM server DLL needs to export.
+
+use crate::{
+    arch::{
+        registration::{RegistryOperation, ShellExtension},
+        windows::{
+            class_factory::MODULE_COUNT,
+            guard::guard,
+            sys::{
+                CLASS_E_CLASSNOTAVAILABLE, E_POINTER, E_UNEXPECTED, GUID,
+                HRESULT, S_FALSE, S_OK,
+            },
+            ClassFactory,
+        },
+    },
+    InputSources, ThumbnailProvider,
+};
+use image::{GenericImageView, Pixel};
+use std::{os::raw::c_void, ptr};
+
+/// Export the functions Windows needs to load a [`ThumbnailProvider`] from a
+/// DLL (`DllGetClassObject()`, `DllCanUnloadNow()`, `DllRegisterServer()`,
+/// and `DllUnregisterServer()`).
+///
+/// The first argument is an expression which creates a new provider, and is
+/// evaluated every time the shell asks for a thumbnail provider. The optional
+/// `name` is the friendly name `regsvr32` gives the COM class (defaulting to
+/// the name of your crate), and the optional `extensions` are the file types
+/// the provider is registered for.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use thumbnails::providers::ImageProvider;
+///
+/// thumbnails::thumbnail_provider_dll!(
+///     ImageProvider::new(),
+///     CLSID = "{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}",
+/// );
+/// ```
+///
+/// Or, with a friendly name and the file types to register:
+///
+/// ```rust,ignore
+/// use thumbnails::providers::ImageProvider;
+///
+/// thumbnails::thumbnail_provider_dll!(
+///     ImageProvider::new(),
+///     CLSID = "{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}",
+///     name = "Image Thumbnail Provider",
+///     extensions = [".png", ".jpg"],
+/// );
+/// ```
+#[macro_export]
+macro_rules! thumbnail_provider_dll {
+    (
+        $provider:expr,
+        CLSID = $clsid:literal
+        $(, name = $name:literal )?
+        $(, extensions = [ $( $extension:literal ),* $(,)? ] )?
+        $(,)?
+    ) => {
+        #[no_mangle]
+        pub unsafe extern "system" fn DllGetClassObject(
+            rclsid: *const $crate::arch::windows::sys::GUID,
+            riid: *const $crate::arch::windows::sys::IID,
+            ppv: *mut *mut ::std::os::raw::c_void,
+        ) -> $crate::arch::windows::sys::HRESULT {
+            const CLSID: $crate::arch::windows::sys::GUID =
+                $crate::arch::windows::sys::GUID::parse($clsid);
+
+            $crate::arch::windows::dll_get_class_object(
+                &CLSID,
+                || $provider,
+                rclsid,
+                riid,
+                ppv,
+            )
+        }
+
+        #[no_mangle]
+        pub extern "system" fn DllCanUnloadNow(
+        ) -> $crate::arch::windows::sys::HRESULT {
+            $crate::arch::windows::dll_can_unload_now()
+        }
+
+        #[no_mangle]
+        pub extern "system" fn DllRegisterServer(
+        ) -> $crate::arch::windows::sys::HRESULT {
+            const CLSID: $crate::arch::windows::sys::GUID =
+                $crate::arch::windows::sys::GUID::parse($clsid);
+
+            let provider = $provider;
+            // use the name we were given, falling back to the crate's name
+            let name = [$( $name, )? env!("CARGO_PKG_NAME")][0];
+
+            $crate::arch::windows::dll_register_server(
+                &CLSID,
+                name,
+                &[$( $( $extension ),* )?],
+                $crate::ThumbnailProvider::input_sources(&provider),
+            )
+        }
+
+        #[no_mangle]
+        pub extern "system" fn DllUnregisterServer(
+        ) -> $crate::arch::windows::sys::HRESULT {
+            const CLSID: $crate::arch::windows::sys::GUID =
+                $crate::arch::windows::sys::GUID::parse($clsid);
+
+            $crate::arch::windows::dll_unregister_server(
+                &CLSID,
+                &[$( $( $extension ),* )?],
+            )
+        }
+    };
+}
+
+/// The implementation of `DllGetClassObject()`, handing out a
+/// [`ClassFactory`] when asked for `clsid`.
+///
+/// # Safety
+///
+/// The pointers must be valid, as described in the docs for
+/// [`DllGetClassObject()`][msdn].
+///
+/// [msdn]: https://docs.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-dllgetclassobject
+pub unsafe fn dll_get_class_object<F, P>(
+    clsid: &GUID,
+    create: F,
+    rclsid: *const GUID,
+    riid: *const GUID,
+    ppv: *mut *mut c_void,
+) -> HRESULT
+where
+    F: Fn() -> P + Send + Sync + 'static,
+    P: ThumbnailProvider + Send + Sync + 'static,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+{
+    guard(E_UNEXPECTED, || {
+        if rclsid.is_null() || riid.is_null() || ppv.is_null() {
+            return E_POINTER;
+        }
+
+        *ppv = ptr::null_mut();
+
+        if *rclsid != *clsid {
+            return CLASS_E_CLASSNOTAVAILABLE;
+        }
+
+        let factory = ClassFactory::new_class_factory(create);
+        let ret =
+            (*(*factory).lpVtbl).QueryInterface.unwrap()(factory, riid, ppv);
+        (*(*factory).lpVtbl).Release.unwrap()(factory);
+
+        ret
+    })
+}
+
+/// The implementation of `DllCanUnloadNow()`.
+pub fn dll_can_unload_now() -> HRESULT {
+    if MODULE_COUNT.can_unload() {
+        S_OK
+    } else {
+        S_FALSE
+    }
+}
+
+/// The implementation of `DllRegisterServer()`, registering the DLL
+/// containing this crate as the thumbnail provider for `extensions`.
+///
+/// Process isolation is disabled when the provider needs a path, because
+/// Explorer won't use `IInitializeWithFile` or `IInitializeWithItem`
+/// otherwise.
+pub fn dll_register_server(
+    clsid: &GUID,
+    name: &str,
+    extensions: &[&str],
+    sources: InputSources,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        let dll_path = match registry::current_module_path() {
+            Ok(path) => path,
+            Err(e) => return e,
+        };
+
+        let mut ext = ShellExtension::new(clsid.to_string(), dll_path)
+            .name(name)
+            .extensions(extensions.iter().copied());
+        if sources.path {
+            ext = ext.disable_process_isolation();
+        }
+
+        apply(&ext.install_operations())
+    })
+}
+
+/// The implementation of `DllUnregisterServer()`, removing everything
+/// added by [`dll_register_server()`].
+pub fn dll_unregister_server(clsid: &GUID, extensions: &[&str]) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        let ext = ShellExtension::new(clsid.to_string(), String::new())
+            .extensions(extensions.iter().copied());
+
+        apply(&ext.uninstall_operations())
+    })
+}
+
+fn apply(ops: &[RegistryOperation]) -> HRESULT {
+    for op in ops {
+        if let Err(e) = registry::apply(op) {
+            return e;
+        }
+    }
+
+    S_OK
+}
+
+#[cfg(windows)]
+mod registry {
+    use crate::arch::{
+        registration::RegistryOperation,
+        windows::sys::{
+            GetModuleFileNameW, GetModuleHandleExW, RegCloseKey,
+            RegCreateKeyExW, RegDeleteTreeW, RegSetValueExW, DWORD,
+            ERROR_FILE_NOT_FOUND, ERROR_SUCCESS,
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT, HKEY,
+            HKEY_CLASSES_ROOT, HRESULT, HRESULT_FROM_WIN32, KEY_WRITE,
+            REG_DWORD, REG_OPTION_NON_VOLATILE, REG_SZ, SELFREG_E_CLASS,
+        },
+    };
+    use std::{ffi::OsStr, os::windows::ffi::OsStrExt, ptr};
+
+    fn wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+
+    /// The path to the DLL this crate was compiled into.
+    pub(super) fn current_module_path() -> Result<String, HRESULT> {
+        unsafe {
+            let mut module = ptr::null_mut();
+            // any address inside the DLL will do
+            let address = current_module_path as *const () as *const u16;
+            let ok = GetModuleHandleExW(
+                GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS
+                    | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+                address,
+                &mut module,
+            );
+            if ok == 0 {
+                return Err(SELFREG_E_CLASS);
+            }
+
+            let mut buffer = vec![0_u16; 32 * 1024];
+            let len = GetModuleFileNameW(
+                module,
+                buffer.as_mut_ptr(),
+                buffer.len() as DWORD,
+            );
+            if len == 0 {
+                return Err(SELFREG_E_CLASS);
+            }
+
+            Ok(String::from_utf16_lossy(&buffer[..len as usize]))
+        }
+    }
+
+    pub(super) fn apply(op: &RegistryOperation) -> Result<(), HRESULT> {
+        match op {
+            RegistryOperation::SetValue { key, name, data } => {
+                let data = wide(data);
+                let bytes = unsafe {
+                    std::slice::from_raw_parts(
+                        data.as_ptr() as *const u8,
+                        data.len() * 2,
+                    )
+                };
+                set_value(key, name.as_deref(), REG_SZ, bytes)
+            },
+            RegistryOperation::SetDword { key, name, data } => {
+                set_value(key, Some(name), REG_DWORD, &data.to_le_bytes())
+            },
+            RegistryOperation::DeleteKey { key } => delete_tree(key),
+        }
+    }
+
+    fn set_value(
+        subkey: &str,
+        name: Option<&str>,
+        kind: DWORD,
+        data: &[u8],
+    ) -> Result<(), HRESULT> {
+        unsafe {
+            let mut key: HKEY = ptr::null_mut();
+            let ret = RegCreateKeyExW(
+                HKEY_CLASSES_ROOT,
+                wide(subkey).as_ptr(),
+                0,
+                ptr::null_mut(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_WRITE,
+                ptr::null_mut(),
+                &mut key,
+                ptr::null_mut(),
+            );
+            if ret != ERROR_SUCCESS {
+                return Err(HRESULT_FROM_WIN32(ret as u32));
+            }
+
+            let name = name.map(wide);
+            let ret = RegSetValueExW(
+                key,
+                name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+                0,
+                kind,
+                data.as_ptr(),
+                data.len() as DWORD,
+            );
+            RegCloseKey(key);
+
+            if ret == ERROR_SUCCESS {
+                Ok(())
+            } else {
+                Err(HRESULT_FROM_WIN32(ret as u32))
+            }
+        }
+    }
+
+    fn delete_tree(key: &str) -> Result<(), HRESULT> {
+        let ret =
+            unsafe { RegDeleteTreeW(HKEY_CLASSES_ROOT, wide(key).as_ptr()) };
+
+        match ret {
+            ERROR_SUCCESS | ERROR_FILE_NOT_FOUND => Ok(()),
+            other => Err(HRESULT_FROM_WIN32(other as u32)),
+        }
+    }
+}
+
+/// There is no registry to write to when emulating COM.
+#[cfg(not(windows))]
+mod registry {
+    use crate::arch::{
+        registration::RegistryOperation,
+        windows::sys::{E_NOTIMPL, HRESULT},
+    };
+
+    pub(super) fn current_module_path() -> Result<String, HRESULT> {
+        Err(E_NOTIMPL)
+    }
+
+    pub(super) fn apply(_op: &RegistryOperation) -> Result<(), HRESULT> {
+        Err(E_NOTIMPL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{
+            registration::THUMBNAIL_HANDLER_KEY,
+            windows::sys::{IClassFactory, IThumbnailProvider, IUnknown},
+        },
+        Dimensions,
+    };
+    use image::RgbaImage;
+    use std::io::Read;
+
+    struct DummyProvider;
+
+    impl ThumbnailProvider for DummyProvider {
+        type Error = std::io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _reader: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    mod dll {
+        thumbnail_provider_dll!(
+            super::DummyProvider,
+            CLSID = "{1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e}",
+        );
+    }
+
+    const CLSID: GUID = GUID::parse("1f0e4a63-6bf1-4b8c-a4ad-1d5a2a7c3b6e");
+
+    #[test]
+    fn get_the_class_factory() {
+        unsafe {
+            let mut p = ptr::null_mut();
+
+            let ret =
+                dll::DllGetClassObject(&CLSID, &IClassFactory::IID, &mut p);
+
+            assert_eq!(ret, S_OK);
+            let factory = p as *mut IClassFactory;
+            let mut provider = ptr::null_mut();
+            let ret = (*(*factory).lpVtbl).CreateInstance.unwrap()(
+                factory,
+                ptr::null_mut(),
+                &IThumbnailProvider::IID,
+                &mut provider,
+            );
+            assert_eq!(ret, S_OK);
+
+            let provider = provider as *mut IThumbnailProvider;
+            assert_eq!((*(*provider).lpVtbl).Release.unwrap()(provider), 0);
+            assert_eq!((*(*factory).lpVtbl).Release.unwrap()(factory), 0);
+        }
+    }
+
+    #[test]
+    fn unknown_classes_are_unavailable() {
+        unsafe {
+            let mut p = ptr::null_mut();
+
+            let ret = dll::DllGetClassObject(
+                &IUnknown::IID,
+                &IClassFactory::IID,
+                &mut p,
+            );
+
+            assert_eq!(ret, CLASS_E_CLASSNOTAVAILABLE);
+            assert!(p.is_null());
+            assert_eq!(
+                dll::DllGetClassObject(&CLSID, ptr::null(), &mut p),
+                E_POINTER
+            );
+        }
+    }
+
+    #[test]
+    fn the_thumbnail_handler_key_is_the_interface_id() {
+        let should_be = format!("{{{}}}", IThumbnailProvider::IID);
+
+        assert_eq!(THUMBNAIL_HANDLER_KEY, should_be);
+    }
+}
@@ -0,0 +1,614 @@
+use crate::arch::windows::{
+    guard::guard,
+    hresult::io_error_to_hresult,
+    sys::{
+        IID_ISequentialStream, IStream, IStreamVtbl, IUnknown, DWORD,
+        E_NOINTERFACE, E_NOTIMPL, E_POINTER, E_UNEXPECTED, FAILED, GUID,
+        HRESULT, LARGE_INTEGER, STATSTG, STGM_READ, STGTY_STREAM,
+        STG_E_ACCESSDENIED, STG_E_INVALIDFUNCTION, STG_E_INVALIDPOINTER,
+        STG_E_READFAULT, STREAM_SEEK_CUR, STREAM_SEEK_END, STREAM_SEEK_SET,
+        S_OK, ULARGE_INTEGER, ULONG,
+    },
+};
+use field_offset::FieldOffset;
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    os::raw::c_void,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+/// A COM `IStream` which reads from a Rust value.
+///
+/// This lets us feed in-memory files to a [`crate::arch::windows::Wrapper`]
+/// in tests, or hand Rust data to other COM components. The stream is
+/// read-only, so `Write` and `SetSize` fail with `STG_E_ACCESSDENIED`.
+///
+/// Clones made with `IStream::Clone` share the underlying value but have
+/// their own seek pointer.
+#[repr(C)]
+pub struct RustStream<T> {
+    vtable: *mut IStreamVtbl,
+    ref_count: AtomicUsize,
+    position: AtomicU64,
+    inner: Arc<Mutex<T>>,
+}
+
+impl<T> RustStream<T>
+where
+    T: Read + Seek + Send + 'static,
+{
+    const VTABLE: IStreamVtbl = IStreamVtbl {
+        QueryInterface: Some(stream_query_interface::<T>),
+        AddRef: Some(stream_add_ref::<T>),
+        Release: Some(stream_release::<T>),
+        Read: Some(stream_read::<T>),
+        Write: Some(stream_write),
+        Seek: Some(stream_seek::<T>),
+        SetSize: Some(stream_set_size),
+        CopyTo: Some(stream_copy_to::<T>),
+        Commit: Some(stream_commit),
+        Revert: Some(stream_revert),
+        LockRegion: Some(stream_lock_region),
+        UnlockRegion: Some(stream_lock_region),
+        Stat: Some(stream_stat::<T>),
+        Clone: Some(stream_clone::<T>),
+    };
+
+    /// Create a new `IStream` with a reference count of 1.
+    pub fn new_stream(inner: T) -> *mut IStream {
+        RustStream::new_shared(Arc::new(Mutex::new(inner)), 0)
+    }
+
+    fn new_shared(inner: Arc<Mutex<T>>, position: u64) -> *mut IStream {
+        let boxed = Box::new(RustStream {
+            // See the note in Wrapper::new() about promoted constants
+            vtable: &RustStream::<T>::VTABLE as *const _ as *mut _,
+            ref_count: AtomicUsize::new(1),
+            position: AtomicU64::new(position),
+            inner,
+        });
+
+        let raw = Box::into_raw(boxed);
+        // SAFETY: we just created this pointer from a box
+        unsafe { (*raw).as_stream() }
+    }
+}
+
+impl<T> RustStream<T> {
+    /// Get a pointer to the [`RustStream`] from a pointer to its `IStream`
+    /// interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IStream` interface of a `RustStream<T>`.
+    pub unsafe fn from_stream(raw: *mut IStream) -> *mut Self {
+        let offset = FieldOffset::new(|s: *const RustStream<T>| &(*s).vtable);
+        let vtable: *mut *mut IStreamVtbl = &mut (*raw).lpVtbl;
+        offset.unapply_ptr_mut(vtable)
+    }
+
+    pub fn as_stream(&self) -> *mut IStream {
+        &self.vtable as *const *mut IStreamVtbl as *mut _
+    }
+}
+
+impl<T: Read + Seek> RustStream<T> {
+    /// Lock the underlying value and move it to this stream's seek pointer.
+    fn lock(&self) -> io::Result<MutexGuard<'_, T>> {
+        // a panic in the middle of a read doesn't leave the value in a state
+        // we can't recover from, so it's fine to ignore poisoning
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.seek(SeekFrom::Start(self.position.load(Ordering::SeqCst)))?;
+        Ok(inner)
+    }
+
+    fn read(&self, mut buffer: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.lock()?;
+        let mut bytes_read = 0;
+
+        // IStream::Read() should only return fewer bytes than requested when
+        // it reaches the end of the stream
+        while !buffer.is_empty() {
+            match inner.read(buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    bytes_read += n;
+                    buffer = &mut buffer[n..];
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.position.fetch_add(bytes_read as u64, Ordering::SeqCst);
+        Ok(bytes_read)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> io::Result<u64> {
+        let mut inner = self.lock()?;
+        let new_position = inner.seek(pos)?;
+        self.position.store(new_position, Ordering::SeqCst);
+        Ok(new_position)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        let mut inner = self.lock()?;
+        inner.seek(SeekFrom::End(0))
+    }
+}
+
+unsafe extern "system" fn stream_query_interface<T>(
+    this: *mut IStream,
+    guid: *const GUID,
+    p: *mut *mut c_void,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
+
+        let guid = &*guid;
+
+        if guid == &IUnknown::IID
+            || guid == &IID_ISequentialStream
+            || guid == &IStream::IID
+        {
+            stream_add_ref::<T>(this);
+            *p = this as *mut c_void;
+            S_OK
+        } else {
+            *p = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    })
+}
+
+unsafe extern "system" fn stream_add_ref<T>(this: *mut IStream) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+        this.ref_count.fetch_add(1, Ordering::SeqCst) as ULONG + 1
+    })
+}
+
+unsafe extern "system" fn stream_release<T>(this: *mut IStream) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        let this = RustStream::<T>::from_stream(this);
+        let count = (*this).ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
+
+        if count == 0 {
+            let _ = Box::from_raw(this);
+        }
+
+        count as ULONG
+    })
+}
+
+unsafe extern "system" fn stream_read<T: Read + Seek>(
+    this: *mut IStream,
+    buffer_ptr: *mut c_void,
+    cb: ULONG,
+    bytes_read_out: *mut ULONG,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || buffer_ptr.is_null() {
+            return STG_E_INVALIDPOINTER;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+        // the caller's buffer may be uninitialized
+        ptr::write_bytes(buffer_ptr as *mut u8, 0, cb as usize);
+        let buffer =
+            slice::from_raw_parts_mut(buffer_ptr as *mut u8, cb as usize);
+
+        let (bytes_read, ret) = match this.read(buffer) {
+            Ok(n) => (n, S_OK),
+            Err(e) => (0, io_error_to_hresult(&e, STG_E_READFAULT)),
+        };
+
+        if !bytes_read_out.is_null() {
+            *bytes_read_out = bytes_read as ULONG;
+        }
+
+        ret
+    })
+}
+
+unsafe extern "system" fn stream_write(
+    _this: *mut IStream,
+    _pv: *const c_void,
+    _cb: ULONG,
+    bytes_written_out: *mut ULONG,
+) -> HRESULT {
+    if !bytes_written_out.is_null() {
+        *bytes_written_out = 0;
+    }
+
+    STG_E_ACCESSDENIED
+}
+
+unsafe extern "system" fn stream_seek<T: Read + Seek>(
+    this: *mut IStream,
+    offset: LARGE_INTEGER,
+    origin: DWORD,
+    new_position_out: *mut ULARGE_INTEGER,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() {
+            return STG_E_INVALIDPOINTER;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+        let offset = offset.QuadPart;
+
+        let pos = match origin {
+            STREAM_SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+            STREAM_SEEK_CUR => SeekFrom::Current(offset),
+            STREAM_SEEK_END => SeekFrom::End(offset),
+            _ => return STG_E_INVALIDFUNCTION,
+        };
+
+        match this.seek(pos) {
+            Ok(new_position) => {
+                if !new_position_out.is_null() {
+                    (*new_position_out).QuadPart = new_position;
+                }
+                S_OK
+            },
+            // seeking before the start of the stream
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                STG_E_INVALIDFUNCTION
+            },
+            Err(e) => io_error_to_hresult(&e, STG_E_INVALIDFUNCTION),
+        }
+    })
+}
+
+unsafe extern "system" fn stream_set_size(
+    _this: *mut IStream,
+    _new_size: ULARGE_INTEGER,
+) -> HRESULT {
+    STG_E_ACCESSDENIED
+}
+
+unsafe extern "system" fn stream_copy_to<T: Read + Seek>(
+    this: *mut IStream,
+    destination: *mut IStream,
+    cb: ULARGE_INTEGER,
+    bytes_read_out: *mut ULARGE_INTEGER,
+    bytes_written_out: *mut ULARGE_INTEGER,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || destination.is_null() {
+            return STG_E_INVALIDPOINTER;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+        let write = match (*(*destination).lpVtbl).Write {
+            Some(w) => w,
+            None => return E_NOTIMPL,
+        };
+
+        let mut buffer = [0_u8; 8 * 1024];
+        let mut remaining = cb.QuadPart;
+        let mut total_read = 0;
+        let mut total_written = 0;
+        let mut ret = S_OK;
+
+        while remaining > 0 {
+            let chunk_size = remaining.min(buffer.len() as u64) as usize;
+            let bytes_read = match this.read(&mut buffer[..chunk_size]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    ret = io_error_to_hresult(&e, STG_E_READFAULT);
+                    break;
+                },
+            };
+            total_read += bytes_read as u64;
+            remaining -= bytes_read as u64;
+
+            let mut bytes_written = 0;
+            ret = write(
+                destination,
+                buffer.as_ptr() as *const c_void,
+                bytes_read as ULONG,
+                &mut bytes_written,
+            );
+            total_written += u64::from(bytes_written);
+
+            if FAILED(ret) {
+                break;
+            }
+        }
+
+        if !bytes_read_out.is_null() {
+            (*bytes_read_out).QuadPart = total_read;
+        }
+        if !bytes_written_out.is_null() {
+            (*bytes_written_out).QuadPart = total_written;
+        }
+
+        ret
+    })
+}
+
+unsafe extern "system" fn stream_commit(
+    _this: *mut IStream,
+    _flags: DWORD,
+) -> HRESULT {
+    // we never have pending changes
+    S_OK
+}
+
+unsafe extern "system" fn stream_revert(_this: *mut IStream) -> HRESULT { S_OK }
+
+unsafe extern "system" fn stream_lock_region(
+    _this: *mut IStream,
+    _offset: ULARGE_INTEGER,
+    _cb: ULARGE_INTEGER,
+    _lock_type: DWORD,
+) -> HRESULT {
+    // region locking isn't supported
+    STG_E_INVALIDFUNCTION
+}
+
+unsafe extern "system" fn stream_stat<T: Read + Seek>(
+    this: *mut IStream,
+    stat_out: *mut STATSTG,
+    _flags: DWORD,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || stat_out.is_null() {
+            return STG_E_INVALIDPOINTER;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+
+        let len = match this.len() {
+            Ok(len) => len,
+            Err(e) => return io_error_to_hresult(&e, STG_E_ACCESSDENIED),
+        };
+
+        let mut stat = STATSTG {
+            type_: STGTY_STREAM,
+            grfMode: STGM_READ,
+            ..Default::default()
+        };
+        stat.cbSize.QuadPart = len;
+        // the stream doesn't have a name, so pwcsName is always null and we can
+        // ignore STATFLAG_NONAME
+
+        *stat_out = stat;
+        S_OK
+    })
+}
+
+unsafe extern "system" fn stream_clone<T: Read + Seek + Send + 'static>(
+    this: *mut IStream,
+    clone_out: *mut *mut IStream,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || clone_out.is_null() {
+            return STG_E_INVALIDPOINTER;
+        }
+
+        let this = &*RustStream::<T>::from_stream(this);
+        *clone_out = RustStream::new_shared(
+            Arc::clone(&this.inner),
+            this.position.load(Ordering::SeqCst),
+        );
+
+        S_OK
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::windows::sys::{IThumbnailProvider, STATFLAG_NONAME};
+    use std::{io::Cursor, sync::atomic::AtomicBool};
+
+    unsafe fn read(stream: *mut IStream, len: usize) -> (HRESULT, Vec<u8>) {
+        let mut buffer = vec![0; len];
+        let mut bytes_read = 0;
+
+        let ret = (*(*stream).lpVtbl).Read.unwrap()(
+            stream,
+            buffer.as_mut_ptr() as *mut c_void,
+            len as ULONG,
+            &mut bytes_read,
+        );
+        buffer.truncate(bytes_read as usize);
+
+        (ret, buffer)
+    }
+
+    unsafe fn seek(stream: *mut IStream, offset: i64, origin: DWORD) -> u64 {
+        let mut dlib_move = LARGE_INTEGER::default();
+        dlib_move.QuadPart = offset;
+        let mut new_position = ULARGE_INTEGER::default();
+
+        let ret = (*(*stream).lpVtbl).Seek.unwrap()(
+            stream,
+            dlib_move,
+            origin,
+            &mut new_position,
+        );
+        assert_eq!(ret, S_OK);
+
+        new_position.QuadPart
+    }
+
+    unsafe fn release(stream: *mut IStream) -> ULONG {
+        (*(*stream).lpVtbl).Release.unwrap()(stream)
+    }
+
+    #[test]
+    fn read_the_entire_stream() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(b"Hello, World!"));
+
+            let (ret, first) = read(stream, 5);
+            assert_eq!(ret, S_OK);
+            assert_eq!(first, b"Hello");
+
+            let (ret, rest) = read(stream, 100);
+            assert_eq!(ret, S_OK);
+            assert_eq!(rest, b", World!");
+
+            assert_eq!(release(stream), 0);
+        }
+    }
+
+    #[test]
+    fn seek_from_each_origin() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(b"0123456789"));
+
+            assert_eq!(seek(stream, 3, STREAM_SEEK_SET), 3);
+            assert_eq!(read(stream, 2).1, b"34");
+            assert_eq!(seek(stream, -3, STREAM_SEEK_CUR), 2);
+            assert_eq!(read(stream, 1).1, b"2");
+            assert_eq!(seek(stream, -1, STREAM_SEEK_END), 9);
+            assert_eq!(read(stream, 5).1, b"9");
+
+            let ret = (*(*stream).lpVtbl).Seek.unwrap()(
+                stream,
+                LARGE_INTEGER::default(),
+                42,
+                ptr::null_mut(),
+            );
+            assert_eq!(ret, STG_E_INVALIDFUNCTION);
+
+            release(stream);
+        }
+    }
+
+    #[test]
+    fn stat_reports_the_length() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(vec![0; 42]));
+            seek(stream, 10, STREAM_SEEK_SET);
+            let mut stat = STATSTG::default();
+
+            let ret = (*(*stream).lpVtbl).Stat.unwrap()(
+                stream,
+                &mut stat,
+                STATFLAG_NONAME,
+            );
+
+            assert_eq!(ret, S_OK);
+            assert_eq!(stat.type_, STGTY_STREAM);
+            assert_eq!(stat.cbSize.QuadPart, 42);
+            // asking for the length doesn't move the seek pointer
+            assert_eq!(seek(stream, 0, STREAM_SEEK_CUR), 10);
+
+            release(stream);
+        }
+    }
+
+    #[test]
+    fn clones_have_their_own_seek_pointer() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(b"abcdef"));
+            assert_eq!(read(stream, 2).1, b"ab");
+
+            let mut clone = ptr::null_mut();
+            let ret = (*(*stream).lpVtbl).Clone.unwrap()(stream, &mut clone);
+            assert_eq!(ret, S_OK);
+            assert!(!clone.is_null());
+            assert!(!ptr::eq(clone, stream));
+
+            assert_eq!(read(clone, 2).1, b"cd");
+            assert_eq!(read(stream, 1).1, b"c");
+            assert_eq!(read(clone, 10).1, b"ef");
+
+            assert_eq!(release(stream), 0);
+            assert_eq!(read(clone, 10).1, b"");
+            assert_eq!(release(clone), 0);
+        }
+    }
+
+    #[test]
+    fn writing_is_denied() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(Vec::new()));
+            let mut bytes_written = 42;
+
+            let ret = (*(*stream).lpVtbl).Write.unwrap()(
+                stream,
+                b"Hello".as_ptr() as *const c_void,
+                5,
+                &mut bytes_written,
+            );
+
+            assert_eq!(ret, STG_E_ACCESSDENIED);
+            assert_eq!(bytes_written, 0);
+            release(stream);
+        }
+    }
+
+    #[test]
+    fn query_interface_for_the_stream_interfaces() {
+        unsafe {
+            let stream = RustStream::new_stream(Cursor::new(Vec::new()));
+            let query_interface = (*(*stream).lpVtbl).QueryInterface.unwrap();
+
+            for iid in &[IUnknown::IID, IID_ISequentialStream, IStream::IID] {
+                let mut p = ptr::null_mut();
+                assert_eq!(query_interface(stream, iid, &mut p), S_OK);
+                assert!(ptr::eq(p as *mut IStream, stream));
+                release(stream);
+            }
+
+            let mut p = ptr::null_mut();
+            let got = query_interface(stream, &IThumbnailProvider::IID, &mut p);
+            assert_eq!(got, E_NOINTERFACE);
+            assert!(p.is_null());
+
+            assert_eq!(release(stream), 0);
+        }
+    }
+
+    struct DropCheck(Arc<AtomicBool>);
+
+    impl Read for DropCheck {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> { Ok(0) }
+    }
+
+    impl Seek for DropCheck {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> { Ok(0) }
+    }
+
+    impl Drop for DropCheck {
+        fn drop(&mut self) { self.0.store(true, Ordering::SeqCst); }
+    }
+
+    #[test]
+    fn release_drops_the_value() {
+        unsafe {
+            let dropped = Arc::new(AtomicBool::new(false));
+            let stream =
+                RustStream::new_stream(DropCheck(Arc::clone(&dropped)));
+
+            assert_eq!((*(*stream).lpVtbl).AddRef.unwrap()(stream), 2);
+            assert_eq!(release(stream), 1);
+            assert!(!dropped.load(Ordering::SeqCst));
+
+            assert_eq!(release(stream), 0);
+            assert!(dropped.load(Ordering::SeqCst));
+        }
+    }
+}
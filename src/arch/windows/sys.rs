@@ -4,7 +4,9 @@
 //!
 //! ```text
 //!  bindgen bindings.h -o bindings.rs \
-//!     --whitelist-type 'IThumbnailProvider|IInitializeWithStream|IClassFactory'
+//!     --whitelist-type 'IThumbnailProvider|IInitializeWithStream|IInitializeWithFile|IInitializeWithItem|IClassFactory' \
+//!     --opaque-type IBindCtx \
+//!     --ctypes-prefix ctypes \
 //!     --with-derive-default \
 //!     --with-derive-partialeq \
 //!     --impl-debug
@@ -16,22 +18,308 @@
 //! ```text
 //! $header = "C:\Program Files (x86)\Windows Kits\10\Include\10.0.19041.0\um\thumbcache.h"
 //! ```
+//!
+//! Afterwards, every `extern "C"` was replaced with `extern "system"` so the
+//! vtables use the right calling convention on 32-bit Windows.
+//!
+//! The C types are taken from [`ctypes`] instead of [`std::os::raw`] so the
+//! bindings have the same layout when they are compiled on other platforms
+//! with the `com-emulation` feature.
 
-#![allow(bad_style, dead_code)]
+#![allow(bad_style, dead_code, unpredictable_function_pointer_comparisons)]
 
-use std::{
-    fmt::{self, Display, Formatter},
-    os::raw::{c_uchar, c_ulong, c_ushort},
-};
+use self::ctypes::{c_uchar, c_ulong, c_ushort, c_void};
+use std::fmt::{self, Display, Formatter};
 
 include!("bindings.rs");
 
+/// C types with the sizes they have on Windows.
+pub mod ctypes {
+    pub type c_int = i32;
+    pub type c_uint = u32;
+    pub type c_long = i32;
+    pub type c_ulong = u32;
+    pub type c_longlong = i64;
+    pub type c_ulonglong = u64;
+    pub type c_uchar = u8;
+    pub type c_ushort = u16;
+    pub use std::os::raw::c_void;
+}
+
 // we need to write these manually because they are macros.
 pub const S_OK: HRESULT = 0;
 pub const S_FALSE: HRESULT = 1;
 pub fn SUCCEEDED(hr: HRESULT) -> bool { hr >= 0 }
 pub fn FAILED(hr: HRESULT) -> bool { hr < 0 }
 
+// Error codes from winerror.h.
+pub const E_NOTIMPL: HRESULT = 0x80004001_u32 as HRESULT;
+pub const E_NOINTERFACE: HRESULT = 0x80004002_u32 as HRESULT;
+pub const E_POINTER: HRESULT = 0x80004003_u32 as HRESULT;
+pub const E_FAIL: HRESULT = 0x80004005_u32 as HRESULT;
+pub const E_UNEXPECTED: HRESULT = 0x8000FFFF_u32 as HRESULT;
+pub const E_OUTOFMEMORY: HRESULT = 0x8007000E_u32 as HRESULT;
+pub const E_INVALIDARG: HRESULT = 0x80070057_u32 as HRESULT;
+pub const WTS_E_FAILEDEXTRACTION: HRESULT = 0x8004B200_u32 as HRESULT;
+pub const WTS_E_EXTRACTIONTIMEDOUT: HRESULT = 0x8004B201_u32 as HRESULT;
+pub const CLASS_E_NOAGGREGATION: HRESULT = 0x80040110_u32 as HRESULT;
+pub const CLASS_E_CLASSNOTAVAILABLE: HRESULT = 0x80040111_u32 as HRESULT;
+pub const SELFREG_E_CLASS: HRESULT = 0x80040201_u32 as HRESULT;
+pub const ERROR_CANCELLED: u32 = 1223;
+pub const ERROR_FILE_CORRUPT: u32 = 1392;
+pub const STG_E_INVALIDFUNCTION: HRESULT = 0x80030001_u32 as HRESULT;
+pub const STG_E_ACCESSDENIED: HRESULT = 0x80030005_u32 as HRESULT;
+pub const STG_E_INVALIDPOINTER: HRESULT = 0x80030009_u32 as HRESULT;
+pub const STG_E_READFAULT: HRESULT = 0x8003001E_u32 as HRESULT;
+
+/// Equivalent to the `HRESULT_FROM_WIN32()` macro.
+pub fn HRESULT_FROM_WIN32(code: u32) -> HRESULT {
+    if code as HRESULT <= 0 {
+        code as HRESULT
+    } else {
+        ((code & 0x0000_FFFF) | 0x8007_0000) as HRESULT
+    }
+}
+
+// Declarations from objidl.h, which aren't part of the generated bindings.
+pub const STREAM_SEEK_SET: DWORD = 0;
+pub const STREAM_SEEK_CUR: DWORD = 1;
+pub const STREAM_SEEK_END: DWORD = 2;
+pub const STGTY_STREAM: DWORD = 2;
+pub const STATFLAG_DEFAULT: DWORD = 0;
+pub const STATFLAG_NONAME: DWORD = 1;
+pub const STGM_READ: DWORD = 0;
+
+// Declarations from wingdi.h, which aren't part of the generated bindings.
+pub type BITMAPINFOHEADER = crate::dib::BitmapInfoHeader;
+pub type HDC = *mut c_void;
+pub type HANDLE = *mut c_void;
+pub type HGDIOBJ = *mut c_void;
+pub const DIB_RGB_COLORS: UINT = 0;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RGBQUAD {
+    pub rgbBlue: c_uchar,
+    pub rgbGreen: c_uchar,
+    pub rgbRed: c_uchar,
+    pub rgbReserved: c_uchar,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct BITMAPINFO {
+    pub bmiHeader: BITMAPINFOHEADER,
+    pub bmiColors: [RGBQUAD; 1],
+}
+
+#[cfg(windows)]
+#[link(name = "gdi32")]
+extern "system" {
+    pub fn CreateDIBSection(
+        hdc: HDC,
+        pbmi: *const BITMAPINFO,
+        usage: UINT,
+        ppvBits: *mut *mut c_void,
+        hSection: HANDLE,
+        offset: DWORD,
+    ) -> HBITMAP;
+    pub fn DeleteObject(ho: HGDIOBJ) -> BOOL;
+}
+
+// Declarations from winreg.h and libloaderapi.h, used when registering a DLL.
+pub type HKEY = *mut c_void;
+pub type HMODULE = *mut c_void;
+pub type LSTATUS = LONG;
+pub const HKEY_CLASSES_ROOT: HKEY = 0x80000000_u32 as i32 as isize as HKEY;
+pub const KEY_WRITE: DWORD = 0x20006;
+pub const REG_OPTION_NON_VOLATILE: DWORD = 0;
+pub const REG_SZ: DWORD = 1;
+pub const REG_DWORD: DWORD = 4;
+pub const ERROR_SUCCESS: LSTATUS = 0;
+pub const ERROR_FILE_NOT_FOUND: LSTATUS = 2;
+pub const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: DWORD = 0x2;
+pub const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: DWORD = 0x4;
+
+#[cfg(windows)]
+#[link(name = "advapi32")]
+extern "system" {
+    pub fn RegCreateKeyExW(
+        hKey: HKEY,
+        lpSubKey: *const WCHAR,
+        Reserved: DWORD,
+        lpClass: *mut WCHAR,
+        dwOptions: DWORD,
+        samDesired: DWORD,
+        lpSecurityAttributes: *mut c_void,
+        phkResult: *mut HKEY,
+        lpdwDisposition: *mut DWORD,
+    ) -> LSTATUS;
+    pub fn RegSetValueExW(
+        hKey: HKEY,
+        lpValueName: *const WCHAR,
+        Reserved: DWORD,
+        dwType: DWORD,
+        lpData: *const c_uchar,
+        cbData: DWORD,
+    ) -> LSTATUS;
+    pub fn RegDeleteTreeW(hKey: HKEY, lpSubKey: *const WCHAR) -> LSTATUS;
+    pub fn RegCloseKey(hKey: HKEY) -> LSTATUS;
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    pub fn GetModuleHandleExW(
+        dwFlags: DWORD,
+        lpModuleName: *const WCHAR,
+        phModule: *mut HMODULE,
+    ) -> BOOL;
+    pub fn GetModuleFileNameW(
+        hModule: HMODULE,
+        lpFilename: *mut WCHAR,
+        nSize: DWORD,
+    ) -> DWORD;
+}
+
+#[cfg(windows)]
+#[link(name = "ole32")]
+extern "system" {
+    pub fn CoTaskMemAlloc(cb: usize) -> *mut c_void;
+    pub fn CoTaskMemFree(pv: *mut c_void);
+}
+
+#[cfg(not(windows))]
+pub use self::emulated_com::{CoTaskMemAlloc, CoTaskMemFree};
+#[cfg(not(windows))]
+pub use self::emulated_gdi::{CreateDIBSection, DeleteObject, EmulatedBitmap};
+
+/// Stand-ins for the COM task allocator, used for strings which are passed
+/// between objects.
+#[cfg(not(windows))]
+mod emulated_com {
+    use super::*;
+    use std::{
+        alloc::{self, Layout},
+        mem, ptr,
+    };
+
+    const HEADER: usize = mem::size_of::<usize>();
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(HEADER + size, mem::align_of::<usize>())
+            .unwrap()
+    }
+
+    /// An emulated version of [`CoTaskMemAlloc()`][msdn], which remembers
+    /// the allocation's size so it can be freed later.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be freed with [`CoTaskMemFree()`].
+    ///
+    /// [msdn]: https://docs.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-cotaskmemalloc
+    pub unsafe extern "system" fn CoTaskMemAlloc(cb: usize) -> *mut c_void {
+        let raw = alloc::alloc(layout(cb)) as *mut usize;
+
+        if raw.is_null() {
+            return ptr::null_mut();
+        }
+
+        raw.write(cb);
+        (raw as *mut u8).add(HEADER) as *mut c_void
+    }
+
+    /// Free memory allocated by [`CoTaskMemAlloc()`].
+    ///
+    /// # Safety
+    ///
+    /// `pv` must be null or have come from [`CoTaskMemAlloc()`] and not
+    /// already been freed.
+    pub unsafe extern "system" fn CoTaskMemFree(pv: *mut c_void) {
+        if pv.is_null() {
+            return;
+        }
+
+        let raw = (pv as *mut u8).sub(HEADER);
+        let size = (raw as *mut usize).read();
+        alloc::dealloc(raw, layout(size));
+    }
+}
+
+/// Stand-ins for the GDI functions we use, so bitmaps can be created when
+/// emulating COM on other platforms.
+#[cfg(not(windows))]
+mod emulated_gdi {
+    use super::*;
+
+    /// The object an emulated `HBITMAP` points to.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EmulatedBitmap {
+        pub header: BITMAPINFOHEADER,
+        pub pixels: Vec<u8>,
+    }
+
+    impl EmulatedBitmap {
+        /// Get the [`EmulatedBitmap`] behind a `HBITMAP` created by
+        /// [`CreateDIBSection()`].
+        ///
+        /// # Safety
+        ///
+        /// The bitmap must have been created by [`CreateDIBSection()`] and
+        /// not yet passed to [`DeleteObject()`].
+        pub unsafe fn from_raw<'a>(bitmap: HBITMAP) -> &'a EmulatedBitmap {
+            &*(bitmap as *const EmulatedBitmap)
+        }
+    }
+
+    /// An emulated version of [`CreateDIBSection()`][msdn] which allocates the
+    /// bitmap on the heap.
+    ///
+    /// # Safety
+    ///
+    /// `pbmi` and `ppvBits` must be valid pointers.
+    ///
+    /// [msdn]: https://docs.microsoft.com/en-us/windows/win32/api/wingdi/nf-wingdi-createdibsection
+    pub unsafe extern "system" fn CreateDIBSection(
+        _hdc: HDC,
+        pbmi: *const BITMAPINFO,
+        _usage: UINT,
+        ppvBits: *mut *mut c_void,
+        _hSection: HANDLE,
+        _offset: DWORD,
+    ) -> HBITMAP {
+        let header = (*pbmi).bmiHeader;
+        let row_length = header.biWidth.unsigned_abs() as usize
+            * usize::from(header.biBitCount)
+            / 8;
+        let length = row_length * header.biHeight.unsigned_abs() as usize;
+
+        let mut bitmap = Box::new(EmulatedBitmap {
+            header,
+            pixels: vec![0; length],
+        });
+        *ppvBits = bitmap.pixels.as_mut_ptr() as *mut c_void;
+
+        Box::into_raw(bitmap) as HBITMAP
+    }
+
+    /// Free a bitmap created by [`CreateDIBSection()`].
+    ///
+    /// # Safety
+    ///
+    /// The object must be a bitmap created by [`CreateDIBSection()`] which
+    /// hasn't already been deleted.
+    pub unsafe extern "system" fn DeleteObject(ho: HGDIOBJ) -> BOOL {
+        if ho.is_null() {
+            return 0;
+        }
+
+        drop(Box::from_raw(ho as *mut EmulatedBitmap));
+        1
+    }
+}
+
 impl GUID {
     pub const fn new(
         a: c_ulong,
@@ -46,6 +334,66 @@ impl GUID {
             Data4: d,
         }
     }
+
+    /// Parse a GUID in its registry format (e.g.
+    /// `"{e357fccd-a995-4576-b01f-234630154e96}"`), where the braces are
+    /// optional.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the string isn't a valid GUID, which becomes a compile
+    /// error when it is used to initialize a constant.
+    pub const fn parse(s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let start = if bytes.len() == 38 && bytes[0] == b'{' {
+            assert!(bytes[37] == b'}', "Unbalanced braces");
+            1
+        } else {
+            0
+        };
+        assert!(bytes.len() - 2 * start == 36, "A GUID has 36 characters");
+        assert!(
+            bytes[start + 8] == b'-'
+                && bytes[start + 13] == b'-'
+                && bytes[start + 18] == b'-'
+                && bytes[start + 23] == b'-',
+            "Dashes are in the wrong place"
+        );
+
+        let mut d = [0; 8];
+        d[0] = parse_hex(bytes, start + 19, 2) as c_uchar;
+        d[1] = parse_hex(bytes, start + 21, 2) as c_uchar;
+        let mut i = 0;
+        while i < 6 {
+            d[i + 2] = parse_hex(bytes, start + 24 + 2 * i, 2) as c_uchar;
+            i += 1;
+        }
+
+        GUID::new(
+            parse_hex(bytes, start, 8) as c_ulong,
+            parse_hex(bytes, start + 9, 4) as c_ushort,
+            parse_hex(bytes, start + 14, 4) as c_ushort,
+            d,
+        )
+    }
+}
+
+const fn parse_hex(bytes: &[u8], start: usize, len: usize) -> u32 {
+    let mut value = 0;
+    let mut i = start;
+
+    while i < start + len {
+        let digit = match bytes[i] {
+            b'0'..=b'9' => bytes[i] - b'0',
+            b'a'..=b'f' => bytes[i] - b'a' + 10,
+            b'A'..=b'F' => bytes[i] - b'A' + 10,
+            _ => panic!("Invalid hex digit"),
+        };
+        value = value * 16 + digit as u32;
+        i += 1;
+    }
+
+    value
 }
 
 impl Display for GUID {
@@ -86,6 +434,32 @@ impl IThumbnailProvider {
     );
 }
 
+impl IStream {
+    pub const IID: IID = GUID::new(
+        0x0000000c,
+        0x0000,
+        0x0000,
+        [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    );
+}
+
+/// The IID for `ISequentialStream`, the base interface of [`IStream`].
+pub const IID_ISequentialStream: IID = GUID::new(
+    0x0c733a30,
+    0x2a1c,
+    0x11ce,
+    [0xad, 0xe5, 0x00, 0xaa, 0x00, 0x44, 0x77, 0x3d],
+);
+
+impl IClassFactory {
+    pub const IID: IID = GUID::new(
+        0x00000001,
+        0x0000,
+        0x0000,
+        [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    );
+}
+
 impl IInitializeWithStream {
     pub const IID: IID = GUID::new(
         0xb824b49d,
@@ -95,6 +469,33 @@ impl IInitializeWithStream {
     );
 }
 
+impl IInitializeWithFile {
+    pub const IID: IID = GUID::new(
+        0xb7d14566,
+        0x0509,
+        0x4cce,
+        [0xa7, 0x1f, 0x0a, 0x55, 0x42, 0x33, 0xbd, 0x9b],
+    );
+}
+
+impl IInitializeWithItem {
+    pub const IID: IID = GUID::new(
+        0x7f73be3f,
+        0xfb79,
+        0x493c,
+        [0xa6, 0xc7, 0x7e, 0xe1, 0x4e, 0x24, 0x58, 0x41],
+    );
+}
+
+impl IShellItem {
+    pub const IID: IID = GUID::new(
+        0x43826d1e,
+        0xe718,
+        0x42ee,
+        [0xbc, 0x55, 0xa1, 0xe2, 0x61, 0xc3, 0x7b, 0xfe],
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,4 +514,52 @@ mod tests {
 
         assert_eq!(got, should_be);
     }
+
+    #[test]
+    fn parse_a_guid() {
+        let should_be = IThumbnailProvider::IID;
+
+        for input in &[
+            "e357fccd-a995-4576-b01f-234630154e96",
+            "{E357FCCD-A995-4576-B01F-234630154E96}",
+        ] {
+            let got = GUID::parse(input);
+
+            assert_eq!(got, should_be, "{}", input);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_guids_are_rejected() { GUID::parse("e357fccd-a995-4576"); }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn emulated_dib_section() {
+        unsafe {
+            let info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER::top_down_32bpp(2, 3).unwrap(),
+                ..Default::default()
+            };
+            let mut bits = std::ptr::null_mut();
+
+            let bitmap = CreateDIBSection(
+                std::ptr::null_mut(),
+                &info,
+                DIB_RGB_COLORS,
+                &mut bits,
+                std::ptr::null_mut(),
+                0,
+            );
+            assert!(!bitmap.is_null());
+            *(bits as *mut u8) = 42;
+
+            let emulated = EmulatedBitmap::from_raw(bitmap);
+            assert_eq!(emulated.header, info.bmiHeader);
+            assert_eq!(emulated.pixels.len(), 2 * 3 * 4);
+            assert_eq!(emulated.pixels[0], 42);
+
+            assert_eq!(DeleteObject(bitmap as HGDIOBJ), 1);
+        }
+    }
 }
@@ -1,32 +1,86 @@
 use crate::{
-    arch::windows::sys::{
-        IInitializeWithStream, IInitializeWithStreamVtbl, IStream,
-        IThumbnailProvider, IThumbnailProviderVtbl, IUnknown, IUnknownVtbl,
-        GUID, HBITMAP, HRESULT, SUCCEEDED, S_OK,
+    arch::windows::{
+        class_factory::MODULE_COUNT,
+        get_hresult,
+        guard::guard,
+        sys::{
+            CoTaskMemFree, CreateDIBSection, DeleteObject, IInitializeWithFile,
+            IInitializeWithFileVtbl, IInitializeWithItem,
+            IInitializeWithItemVtbl, IInitializeWithStream,
+            IInitializeWithStreamVtbl, IShellItem, IStream, IThumbnailProvider,
+            IThumbnailProviderVtbl, IUnknown, IUnknownVtbl,
+            _SIGDN_SIGDN_FILESYSPATH, BITMAPINFO, DIB_RGB_COLORS, DWORD,
+            E_NOINTERFACE, E_POINTER, E_UNEXPECTED, FAILED, GUID, HBITMAP,
+            HGDIOBJ, HRESULT, LARGE_INTEGER, LPCWSTR, STREAM_SEEK_CUR,
+            STREAM_SEEK_END, STREAM_SEEK_SET, SUCCEEDED, S_OK, ULARGE_INTEGER,
+            ULONG, WTS_ALPHATYPE,
+        },
     },
-    Dimensions, ThumbnailProvider,
+    cache::ThumbnailSize,
+    dib::Dib,
+    Dimensions, InputSources, Interruptible, ThumbnailContext,
+    ThumbnailProvider,
 };
 use field_offset::FieldOffset;
+use image::{GenericImageView, Pixel};
 use std::{
-    io::{self, Read},
-    os::raw::{c_ulong, c_void},
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    os::raw::c_void,
+    path::PathBuf,
     ptr,
-    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
 };
 
+/// How long a [`Wrapper`] will spend generating a thumbnail before giving up
+/// with `WTS_E_EXTRACTIONTIMEDOUT`, unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// A COM adapter that can be used as an [`IThumbnailProvider`].
+///
+/// The shell can initialize the wrapper with a stream
+/// ([`IInitializeWithStream`]) or a path ([`IInitializeWithFile`] and
+/// [`IInitializeWithItem`]), depending on the provider's
+/// [`ThumbnailProvider::input_sources()`].
 #[repr(C)]
 pub struct Wrapper<P> {
     unknown_vtable: *mut IUnknownVtbl,
     stream_vtable: *mut IInitializeWithStreamVtbl,
+    file_vtable: *mut IInitializeWithFileVtbl,
+    item_vtable: *mut IInitializeWithItemVtbl,
     thumbnail_vtable: *mut IThumbnailProviderVtbl,
 
     ref_count: AtomicUsize,
+    sources: InputSources,
     stream: AtomicPtr<IStream>,
+    path: Mutex<Option<PathBuf>>,
+    timeout: Option<Duration>,
     provider: P,
 }
 
-impl<P: ThumbnailProvider + Send + Sync + 'static> Wrapper<P> {
+impl<P> Wrapper<P>
+where
+    P: ThumbnailProvider + Send + Sync + 'static,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+{
+    const INITIALIZE_WITH_FILE_VTABLE: IInitializeWithFileVtbl =
+        IInitializeWithFileVtbl {
+            QueryInterface: Some(init_with_file_query_interface::<P>),
+            AddRef: Some(init_with_file_add_ref::<P>),
+            Release: Some(init_with_file_release::<P>),
+            Initialize: Some(init_with_file_initialize::<P>),
+        };
+    const INITIALIZE_WITH_ITEM_VTABLE: IInitializeWithItemVtbl =
+        IInitializeWithItemVtbl {
+            QueryInterface: Some(init_with_item_query_interface::<P>),
+            AddRef: Some(init_with_item_add_ref::<P>),
+            Release: Some(init_with_item_release::<P>),
+            Initialize: Some(init_with_item_initialize::<P>),
+        };
     const INITIALIZE_WITH_STREAM_VTABLE: IInitializeWithStreamVtbl =
         IInitializeWithStreamVtbl {
             QueryInterface: Some(init_with_stream_query_interface::<P>),
@@ -47,18 +101,42 @@ impl<P: ThumbnailProvider + Send + Sync + 'static> Wrapper<P> {
     };
 
     pub fn new(provider: P) -> Self {
+        MODULE_COUNT.object_created();
+
         Wrapper {
-            unknown_vtable: &mut Wrapper::<P>::UNKNOWN_VTABLE,
-            stream_vtable: &mut Wrapper::<P>::INITIALIZE_WITH_STREAM_VTABLE,
-            thumbnail_vtable: &mut Wrapper::<P>::THUMBNAIL_VTABLE,
+            // Note: taking a shared reference to a constant promotes it to a
+            // static, so these pointers are valid for the program's lifetime
+            unknown_vtable: &Wrapper::<P>::UNKNOWN_VTABLE as *const _ as *mut _,
+            stream_vtable: &Wrapper::<P>::INITIALIZE_WITH_STREAM_VTABLE
+                as *const _ as *mut _,
+            file_vtable: &Wrapper::<P>::INITIALIZE_WITH_FILE_VTABLE as *const _
+                as *mut _,
+            item_vtable: &Wrapper::<P>::INITIALIZE_WITH_ITEM_VTABLE as *const _
+                as *mut _,
+            thumbnail_vtable: &Wrapper::<P>::THUMBNAIL_VTABLE as *const _
+                as *mut _,
             ref_count: AtomicUsize::new(1),
+            sources: provider.input_sources(),
             stream: AtomicPtr::default(),
+            path: Mutex::new(None),
+            timeout: Some(DEFAULT_TIMEOUT),
             provider,
         }
     }
 
+    /// Set how long the provider has to generate a thumbnail, or `None` to
+    /// wait forever.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     pub fn new_unknown(provider: P) -> *mut IUnknown {
-        let boxed = Box::new(Wrapper::new(provider));
+        Wrapper::new(provider).into_unknown()
+    }
+
+    pub fn into_unknown(self) -> *mut IUnknown {
+        let boxed = Box::new(self);
 
         // SAFETY: the first item in this struct is a pointer to an IUnknown
         // vtable, meaning `*mut Self` is also a `*mut IUnknown` for all intents
@@ -72,14 +150,27 @@ impl<P: ThumbnailProvider + Send + Sync + 'static> Wrapper<P> {
 }
 
 impl<P> Wrapper<P> {
-    pub unsafe fn from_unknown<'a>(raw: *mut IUnknown) -> *mut Self {
+    /// Get a pointer to the [`Wrapper`] from a pointer to its `IUnknown`
+    /// interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IUnknown` interface of a `Wrapper<P>`.
+    pub unsafe fn from_unknown(raw: *mut IUnknown) -> *mut Self {
         let offset =
             FieldOffset::new(|w: *const Wrapper<P>| &(*w).unknown_vtable);
         let vtable: *mut *mut IUnknownVtbl = &mut (*raw).lpVtbl;
         offset.unapply_ptr_mut(vtable)
     }
 
-    pub unsafe fn from_initialize_with_stream<'a>(
+    /// Get a pointer to the [`Wrapper`] from a pointer to its
+    /// `IInitializeWithStream` interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IInitializeWithStream` interface of a
+    /// `Wrapper<P>`.
+    pub unsafe fn from_initialize_with_stream(
         raw: *mut IInitializeWithStream,
     ) -> *mut Self {
         let offset =
@@ -88,7 +179,44 @@ impl<P> Wrapper<P> {
         offset.unapply_ptr_mut(vtable)
     }
 
-    pub unsafe fn from_thumbnail_provider<'a>(
+    /// Get a pointer to the [`Wrapper`] from a pointer to its
+    /// `IInitializeWithFile` interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IInitializeWithFile` interface of a
+    /// `Wrapper<P>`.
+    pub unsafe fn from_initialize_with_file(
+        raw: *mut IInitializeWithFile,
+    ) -> *mut Self {
+        let offset = FieldOffset::new(|w: *const Wrapper<P>| &(*w).file_vtable);
+        let vtable: *mut *mut IInitializeWithFileVtbl = &mut (*raw).lpVtbl;
+        offset.unapply_ptr_mut(vtable)
+    }
+
+    /// Get a pointer to the [`Wrapper`] from a pointer to its
+    /// `IInitializeWithItem` interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IInitializeWithItem` interface of a
+    /// `Wrapper<P>`.
+    pub unsafe fn from_initialize_with_item(
+        raw: *mut IInitializeWithItem,
+    ) -> *mut Self {
+        let offset = FieldOffset::new(|w: *const Wrapper<P>| &(*w).item_vtable);
+        let vtable: *mut *mut IInitializeWithItemVtbl = &mut (*raw).lpVtbl;
+        offset.unapply_ptr_mut(vtable)
+    }
+
+    /// Get a pointer to the [`Wrapper`] from a pointer to its
+    /// `IThumbnailProvider` interface.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to the `IThumbnailProvider` interface of a
+    /// `Wrapper<P>`.
+    pub unsafe fn from_thumbnail_provider(
         raw: *mut IThumbnailProvider,
     ) -> *mut Self {
         let offset =
@@ -103,6 +231,14 @@ impl<P> Wrapper<P> {
         &self.stream_vtable as *const *mut IInitializeWithStreamVtbl as *mut _
     }
 
+    pub fn as_initialize_with_file(&self) -> *mut IInitializeWithFile {
+        &self.file_vtable as *const *mut IInitializeWithFileVtbl as *mut _
+    }
+
+    pub fn as_initialize_with_item(&self) -> *mut IInitializeWithItem {
+        &self.item_vtable as *const *mut IInitializeWithItemVtbl as *mut _
+    }
+
     pub fn as_thumbnail_provider(&self) -> *mut IThumbnailProvider {
         &self.thumbnail_vtable as *const *mut IThumbnailProviderVtbl as *mut _
     }
@@ -116,22 +252,54 @@ impl<P> Wrapper<P> {
         guid: &GUID,
         p: *mut *mut c_void,
     ) -> HRESULT {
-        eprintln!("Query interface: {}", guid);
-
-        if guid == &IUnknown::IID {
-            *p = self.as_unknown() as *mut c_void;
-            S_OK
+        let interface = if guid == &IUnknown::IID {
+            self.as_unknown() as *mut c_void
         } else if guid == &IThumbnailProvider::IID {
-            *p = self.as_thumbnail_provider() as *mut c_void;
-            S_OK
-        } else if guid == &IInitializeWithStream::IID {
-            *p = self.as_initialize_with_stream() as *mut c_void;
-            S_OK
+            self.as_thumbnail_provider() as *mut c_void
+        } else if guid == &IInitializeWithStream::IID && self.sources.stream {
+            self.as_initialize_with_stream() as *mut c_void
+        } else if guid == &IInitializeWithFile::IID && self.sources.path {
+            self.as_initialize_with_file() as *mut c_void
+        } else if guid == &IInitializeWithItem::IID && self.sources.path {
+            self.as_initialize_with_item() as *mut c_void
         } else {
-            *p = ptr::null_mut();
-            unimplemented!()
+            ptr::null_mut()
+        };
+
+        *p = interface;
+
+        if interface.is_null() {
+            E_NOINTERFACE
+        } else {
+            // the caller is given a new reference
+            self.add_ref();
+            S_OK
         }
     }
+
+    fn set_path(&self, path: PathBuf) {
+        *self.path.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+    }
+
+    fn take_path(&self) -> Option<PathBuf> {
+        self.path.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    fn add_ref(&self) -> ULONG {
+        self.ref_count.fetch_add(1, Ordering::SeqCst) as ULONG + 1
+    }
+
+    /// Decrement the reference count, destroying the [`Wrapper`] when it
+    /// reaches zero.
+    unsafe fn release(this: *mut Self) -> ULONG {
+        let count = (*this).ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
+
+        if count == 0 {
+            let _ = Box::from_raw(this);
+        }
+
+        count as ULONG
+    }
 }
 
 impl<P> Drop for Wrapper<P> {
@@ -145,206 +313,410 @@ impl<P> Drop for Wrapper<P> {
                 }
             }
         }
+
+        MODULE_COUNT.object_destroyed();
     }
 }
 
-unsafe extern "C" fn unknown_add_ref<P>(this: *mut IUnknown) -> c_ulong {
-    eprintln!("Incrementing ref count for {:#p}", this);
+// Note: every function in a vtable must go through guard() so panics can't
+// unwind into the caller, and should check its pointers instead of asserting.
 
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_unknown(this);
-    this.ref_count.fetch_add(1, Ordering::SeqCst) as c_ulong + 1
-}
+unsafe extern "system" fn unknown_add_ref<P>(this: *mut IUnknown) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
 
-unsafe extern "C" fn unknown_release<P>(this: *mut IUnknown) -> c_ulong {
-    eprintln!("Decrementing ref count for {:#p}", this);
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_unknown(this);
-    let count = this.ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
+        (*Wrapper::<P>::from_unknown(this)).add_ref()
+    })
+}
 
-    if count == 0 {
-        let _ = Box::from_raw(this as *const _ as *mut Wrapper<P>);
-    }
+unsafe extern "system" fn unknown_release<P>(this: *mut IUnknown) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
 
-    count as c_ulong
+        Wrapper::release(Wrapper::<P>::from_unknown(this))
+    })
 }
 
-unsafe extern "C" fn unknown_query_interface<P>(
+unsafe extern "system" fn unknown_query_interface<P>(
     this: *mut IUnknown,
     guid: *const GUID,
     p: *mut *mut c_void,
 ) -> HRESULT {
-    assert!(!this.is_null());
-    assert!(!guid.is_null());
-    assert!(!p.is_null());
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
 
-    let this = &mut *Wrapper::<P>::from_unknown(this);
-    this.query_interface(&*guid, p)
+        let this = &*Wrapper::<P>::from_unknown(this);
+        this.query_interface(&*guid, p)
+    })
 }
 
-unsafe extern "C" fn init_with_stream_add_ref<P>(
+unsafe extern "system" fn init_with_stream_add_ref<P>(
     this: *mut IInitializeWithStream,
-) -> c_ulong {
-    eprintln!("Incrementing ref count for {:#p}", this);
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
 
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_initialize_with_stream(this);
-    this.ref_count.fetch_add(1, Ordering::SeqCst) as c_ulong + 1
+        (*Wrapper::<P>::from_initialize_with_stream(this)).add_ref()
+    })
 }
 
-unsafe extern "C" fn init_with_stream_release<P>(
+unsafe extern "system" fn init_with_stream_release<P>(
     this: *mut IInitializeWithStream,
-) -> c_ulong {
-    eprintln!("Decrementing ref count for {:#p}", this);
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_initialize_with_stream(this);
-    let count = this.ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
-
-    if count == 0 {
-        let _ = Box::from_raw(this as *const _ as *mut Wrapper<P>);
-    }
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
 
-    count as c_ulong
+        Wrapper::release(Wrapper::<P>::from_initialize_with_stream(this))
+    })
 }
 
-unsafe extern "C" fn init_with_stream_query_interface<P>(
+unsafe extern "system" fn init_with_stream_query_interface<P>(
     this: *mut IInitializeWithStream,
     guid: *const GUID,
     p: *mut *mut c_void,
 ) -> HRESULT {
-    assert!(!this.is_null());
-    assert!(!guid.is_null());
-    assert!(!p.is_null());
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
 
-    let this = &mut *Wrapper::<P>::from_initialize_with_stream(this);
-    this.query_interface(&*guid, p)
+        let this = &*Wrapper::<P>::from_initialize_with_stream(this);
+        this.query_interface(&*guid, p)
+    })
 }
 
-unsafe extern "C" fn init_with_stream_initialize<P>(
+unsafe extern "system" fn init_with_stream_initialize<P>(
     this: *mut IInitializeWithStream,
     pstream: *mut IStream,
-    _mode: c_ulong,
+    _mode: DWORD,
 ) -> HRESULT {
-    assert!(!this.is_null());
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || pstream.is_null() {
+            return E_POINTER;
+        }
 
-    let this = &mut *Wrapper::<P>::from_initialize_with_stream(this);
+        let this = &*Wrapper::<P>::from_initialize_with_stream(this);
 
-    // we're taking ownership of the stream, bump the reference count
-    if !pstream.is_null() {
+        // we're taking ownership of the stream, bump the reference count
         if let Some(add_ref) = (*(*pstream).lpVtbl).AddRef {
             add_ref(pstream);
         }
-    }
 
-    let old_stream = this.stream.swap(pstream, Ordering::SeqCst);
+        let old_stream = this.stream.swap(pstream, Ordering::SeqCst);
+
+        if !old_stream.is_null() {
+            if let Some(release) = (*(*old_stream).lpVtbl).Release {
+                release(old_stream);
+            }
+        }
+
+        S_OK
+    })
+}
+
+unsafe extern "system" fn init_with_file_add_ref<P>(
+    this: *mut IInitializeWithFile,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        (*Wrapper::<P>::from_initialize_with_file(this)).add_ref()
+    })
+}
+
+unsafe extern "system" fn init_with_file_release<P>(
+    this: *mut IInitializeWithFile,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        Wrapper::release(Wrapper::<P>::from_initialize_with_file(this))
+    })
+}
+
+unsafe extern "system" fn init_with_file_query_interface<P>(
+    this: *mut IInitializeWithFile,
+    guid: *const GUID,
+    p: *mut *mut c_void,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
+
+        let this = &*Wrapper::<P>::from_initialize_with_file(this);
+        this.query_interface(&*guid, p)
+    })
+}
+
+unsafe extern "system" fn init_with_file_initialize<P>(
+    this: *mut IInitializeWithFile,
+    path: LPCWSTR,
+    _mode: DWORD,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || path.is_null() {
+            return E_POINTER;
+        }
+
+        let this = &*Wrapper::<P>::from_initialize_with_file(this);
+        this.set_path(path_from_wide(path));
 
-    if !old_stream.is_null() {
-        if let Some(release) = (*(*old_stream).lpVtbl).Release {
-            release(old_stream);
+        S_OK
+    })
+}
+
+unsafe extern "system" fn init_with_item_add_ref<P>(
+    this: *mut IInitializeWithItem,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        (*Wrapper::<P>::from_initialize_with_item(this)).add_ref()
+    })
+}
+
+unsafe extern "system" fn init_with_item_release<P>(
+    this: *mut IInitializeWithItem,
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        Wrapper::release(Wrapper::<P>::from_initialize_with_item(this))
+    })
+}
+
+unsafe extern "system" fn init_with_item_query_interface<P>(
+    this: *mut IInitializeWithItem,
+    guid: *const GUID,
+    p: *mut *mut c_void,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
+
+        let this = &*Wrapper::<P>::from_initialize_with_item(this);
+        this.query_interface(&*guid, p)
+    })
+}
+
+unsafe extern "system" fn init_with_item_initialize<P>(
+    this: *mut IInitializeWithItem,
+    item: *mut IShellItem,
+    _mode: DWORD,
+) -> HRESULT {
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || item.is_null() {
+            return E_POINTER;
+        }
+
+        let this = &*Wrapper::<P>::from_initialize_with_item(this);
+
+        let get_display_name = match (*(*item).lpVtbl).GetDisplayName {
+            Some(f) => f,
+            None => return E_POINTER,
+        };
+        let mut name = ptr::null_mut();
+        // items which aren't on the file system (e.g. inside a ZIP file)
+        // will fail here
+        let ret = get_display_name(item, _SIGDN_SIGDN_FILESYSPATH, &mut name);
+
+        if FAILED(ret) {
+            return ret;
+        }
+        if name.is_null() {
+            return E_UNEXPECTED;
         }
+
+        this.set_path(path_from_wide(name));
+        CoTaskMemFree(name as *mut c_void);
+
+        S_OK
+    })
+}
+
+/// Copy a null-terminated wide string into a [`PathBuf`].
+unsafe fn path_from_wide(s: *const u16) -> PathBuf {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
     }
+    let wide = std::slice::from_raw_parts(s, len);
 
-    S_OK
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+            PathBuf::from(OsString::from_wide(wide))
+        } else {
+            PathBuf::from(String::from_utf16_lossy(wide))
+        }
+    }
 }
 
-unsafe extern "C" fn thumbnail_provider_add_ref<P>(
+unsafe extern "system" fn thumbnail_provider_add_ref<P>(
     this: *mut IThumbnailProvider,
-) -> c_ulong {
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_thumbnail_provider(this);
-    this.ref_count.fetch_add(1, Ordering::SeqCst) as c_ulong + 1
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
+
+        (*Wrapper::<P>::from_thumbnail_provider(this)).add_ref()
+    })
 }
 
-unsafe extern "C" fn thumbnail_provider_release<P>(
+unsafe extern "system" fn thumbnail_provider_release<P>(
     this: *mut IThumbnailProvider,
-) -> c_ulong {
-    assert!(!this.is_null());
-    let this = &mut *Wrapper::<P>::from_thumbnail_provider(this);
-    let count = this.ref_count.fetch_sub(1, Ordering::SeqCst) - 1;
-
-    if count == 0 {
-        let _ = Box::from_raw(this as *const _ as *mut Wrapper<P>);
-    }
+) -> ULONG {
+    guard(0, || {
+        if this.is_null() {
+            return 0;
+        }
 
-    count as c_ulong
+        Wrapper::release(Wrapper::<P>::from_thumbnail_provider(this))
+    })
 }
 
-unsafe extern "C" fn thumbnail_provider_query_interface<P>(
+unsafe extern "system" fn thumbnail_provider_query_interface<P>(
     this: *mut IThumbnailProvider,
     guid: *const GUID,
     p: *mut *mut c_void,
 ) -> HRESULT {
-    assert!(!this.is_null());
-    assert!(!guid.is_null());
-    assert!(!p.is_null());
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || guid.is_null() || p.is_null() {
+            return E_POINTER;
+        }
 
-    let this = &mut *Wrapper::<P>::from_thumbnail_provider(this);
-    this.query_interface(&*guid, p)
+        let this = &*Wrapper::<P>::from_thumbnail_provider(this);
+        this.query_interface(&*guid, p)
+    })
 }
 
-unsafe extern "C" fn thumbnail_provider_get_thumbnail<P>(
+unsafe extern "system" fn thumbnail_provider_get_thumbnail<P>(
     this: *mut IThumbnailProvider,
     width: u32,
     bitmap: *mut HBITMAP,
-    alpha: *mut i32,
+    alpha: *mut WTS_ALPHATYPE,
 ) -> HRESULT
 where
     P: ThumbnailProvider,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
 {
-    assert!(!this.is_null());
-    assert!(!bitmap.is_null());
-    assert!(!alpha.is_null());
-
-    let this = &mut *Wrapper::<P>::from_thumbnail_provider(this);
-    let dims = Dimensions {
-        width,
-        height: width,
-    };
-    let stream = this.stream.swap(ptr::null_mut(), Ordering::SeqCst);
+    guard(E_UNEXPECTED, || {
+        if this.is_null() || bitmap.is_null() || alpha.is_null() {
+            return E_POINTER;
+        }
 
-    assert!(!stream.is_null());
+        *bitmap = ptr::null_mut();
+
+        let this = &*Wrapper::<P>::from_thumbnail_provider(this);
+        // the shell gives us the maximum size along either axis
+        let dims = Dimensions::square(width);
+        let stream = this.stream.swap(ptr::null_mut(), Ordering::SeqCst);
+        let mut context = ThumbnailContext::new();
+        context.size_class = ThumbnailSize::for_pixels(width);
+        context.deadline = this.timeout.map(|timeout| Instant::now() + timeout);
+
+        let result = if !stream.is_null() {
+            this.provider.get_thumbnail_with_context(
+                Interruptible::new(StreamReader(stream), &context),
+                &context,
+                dims,
+            )
+        } else if let Some(path) = this.take_path() {
+            let file = match File::open(&path) {
+                Ok(f) => f,
+                Err(e) => return get_hresult(&e),
+            };
+            context.modified = file.metadata().and_then(|m| m.modified()).ok();
+            context.path = Some(path);
+
+            this.provider.get_thumbnail_with_context(
+                BufReader::new(Interruptible::new(file, &context)),
+                &context,
+                dims,
+            )
+        } else {
+            // we haven't been initialized yet
+            return E_UNEXPECTED;
+        };
 
-    let reader = StreamReader(stream);
+        let thumbnail = match result {
+            Ok(img) => img,
+            Err(e) => return get_hresult(&e),
+        };
 
-    let thumbnail = match this.provider.get_thumbnail(reader, dims) {
-        Ok(img) => img,
-        Err(e) => {
-            *bitmap = ptr::null_mut();
-            return get_hresult(&e);
-        },
-    };
+        let dib = match Dib::from_image(&thumbnail) {
+            Ok(dib) => dib,
+            Err(e) => return get_hresult(&e),
+        };
 
-    match to_bitmap(thumbnail) {
-        Ok(t) => {
-            *bitmap = t;
-            S_OK
-        },
-        Err(e) => {
-            *bitmap = ptr::null_mut();
-            get_hresult(&e)
-        },
-    }
+        match to_bitmap(&dib) {
+            Ok(t) => {
+                *bitmap = t;
+                *alpha = dib.alpha_type() as WTS_ALPHATYPE;
+                S_OK
+            },
+            Err(e) => get_hresult(&e),
+        }
+    })
 }
 
-fn to_bitmap<I>(_image: I) -> Result<HBITMAP, io::Error> { unimplemented!() }
-
-fn get_hresult<E>(error: &E) -> HRESULT
-where
-    E: std::error::Error + 'static,
-{
-    let mut error: Option<&dyn std::error::Error> = Some(error);
-
-    while let Some(e) = error {
-        if let Some(code) =
-            e.downcast_ref::<io::Error>().and_then(|e| e.raw_os_error())
-        {
-            return code as HRESULT;
+/// Copy a [`Dib`] into a new `HBITMAP`.
+fn to_bitmap(dib: &Dib) -> Result<HBITMAP, io::Error> {
+    let info = BITMAPINFO {
+        bmiHeader: *dib.header(),
+        ..Default::default()
+    };
+    let mut bits = ptr::null_mut();
+
+    unsafe {
+        let bitmap = CreateDIBSection(
+            ptr::null_mut(),
+            &info,
+            DIB_RGB_COLORS,
+            &mut bits,
+            ptr::null_mut(),
+            0,
+        );
+
+        if bitmap.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        if bits.is_null() {
+            let error = io::Error::last_os_error();
+            DeleteObject(bitmap as HGDIOBJ);
+            return Err(error);
         }
 
-        error = e.source();
-    }
+        let pixels = dib.pixels();
+        ptr::copy_nonoverlapping(
+            pixels.as_ptr(),
+            bits as *mut u8,
+            pixels.len(),
+        );
 
-    unimplemented!()
+        Ok(bitmap)
+    }
 }
 
 struct StreamReader(*mut IStream);
@@ -366,9 +738,37 @@ impl Read for StreamReader {
             );
 
             if SUCCEEDED(ret) {
-                return Ok(bytes_read as usize);
+                Ok(bytes_read as usize)
             } else {
-                return Err(io::Error::from_raw_os_error(ret as _));
+                Err(io::Error::from_raw_os_error(ret as _))
+            }
+        }
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        unsafe {
+            let seek = match (*(*self.0).lpVtbl).Seek {
+                Some(s) => s,
+                None => return Err(io::ErrorKind::InvalidInput.into()),
+            };
+
+            let (offset, origin) = match pos {
+                SeekFrom::Start(offset) => (offset as i64, STREAM_SEEK_SET),
+                SeekFrom::Current(offset) => (offset, STREAM_SEEK_CUR),
+                SeekFrom::End(offset) => (offset, STREAM_SEEK_END),
+            };
+            let mut dlib_move = LARGE_INTEGER::default();
+            dlib_move.QuadPart = offset;
+            let mut new_position = ULARGE_INTEGER::default();
+
+            let ret = seek(self.0, dlib_move, origin, &mut new_position);
+
+            if SUCCEEDED(ret) {
+                Ok(new_position.QuadPart)
+            } else {
+                Err(io::Error::from_raw_os_error(ret as _))
             }
         }
     }
@@ -387,8 +787,24 @@ impl Drop for StreamReader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(windows))]
+    use crate::arch::windows::sys::EmulatedBitmap;
+    use crate::{
+        arch::windows::{
+            sys::{
+                CoTaskMemAlloc, DeleteObject, IShellItemVtbl, E_INVALIDARG,
+                HGDIOBJ, LPWSTR, SIGDN, WTS_E_EXTRACTIONTIMEDOUT,
+            },
+            RustStream,
+        },
+        dib::AlphaType,
+    };
     use image::RgbaImage;
-    use std::sync::{atomic::AtomicBool, Arc};
+    use std::{
+        io::Cursor,
+        path::Path,
+        sync::{atomic::AtomicBool, Arc},
+    };
 
     struct DropCheck(Arc<AtomicBool>);
 
@@ -423,7 +839,10 @@ mod tests {
             assert_eq!(current_ref_count, 1);
 
             let release = (*(*wrapper).lpVtbl).Release.unwrap();
-            assert_eq!(release as usize, unknown_release::<DropCheck> as usize);
+            assert_eq!(
+                release as usize,
+                unknown_release::<DropCheck> as *const () as usize
+            );
             assert_eq!(release(wrapper), 0);
 
             let was_actually_released = deleted.load(Ordering::SeqCst);
@@ -456,7 +875,7 @@ mod tests {
             assert!(!got.is_null());
             assert!(ptr::eq(got, wrapper as *mut Wrapper<DummyProvider>));
 
-            let _ = Box::from(got);
+            let _ = Box::from_raw(got);
         }
     }
 
@@ -469,7 +888,7 @@ mod tests {
             let query_interface = (*(*wrapper).lpVtbl).QueryInterface.unwrap();
             assert_eq!(
                 query_interface as usize,
-                unknown_query_interface::<DummyProvider> as usize
+                unknown_query_interface::<DummyProvider> as *const () as usize
             );
 
             let mut place = ptr::null_mut();
@@ -499,4 +918,522 @@ mod tests {
             let _ = Box::from_raw(wrapper as *mut Wrapper<DummyProvider>);
         }
     }
+
+    #[test]
+    fn query_interface_for_every_supported_interface() {
+        unsafe {
+            let deleted = Arc::new(AtomicBool::new(false));
+            let unknown = Wrapper::new_unknown(DropCheck(Arc::clone(&deleted)));
+            let wrapper = &*Wrapper::<DropCheck>::from_unknown(unknown);
+            let query_interface = (*(*unknown).lpVtbl).QueryInterface.unwrap();
+
+            let mut thumbnail_provider = ptr::null_mut();
+            let result = query_interface(
+                unknown,
+                &IThumbnailProvider::IID,
+                &mut thumbnail_provider,
+            );
+            assert_eq!(result, S_OK);
+            assert!(ptr::eq(
+                thumbnail_provider as *mut IThumbnailProvider,
+                wrapper.as_thumbnail_provider()
+            ));
+
+            let mut initialize_with_stream = ptr::null_mut();
+            let result = query_interface(
+                unknown,
+                &IInitializeWithStream::IID,
+                &mut initialize_with_stream,
+            );
+            assert_eq!(result, S_OK);
+            let initialize_with_stream =
+                initialize_with_stream as *mut IInitializeWithStream;
+            assert!(ptr::eq(
+                Wrapper::<DropCheck>::from_initialize_with_stream(
+                    initialize_with_stream
+                ),
+                wrapper
+            ));
+
+            // every interface shares the same reference count, and each
+            // QueryInterface() call added a reference
+            let thumbnail_provider =
+                thumbnail_provider as *mut IThumbnailProvider;
+            let add_ref = (*(*thumbnail_provider).lpVtbl).AddRef.unwrap();
+            assert_eq!(add_ref(thumbnail_provider), 4);
+            let release = (*(*initialize_with_stream).lpVtbl).Release.unwrap();
+            assert_eq!(release(initialize_with_stream), 3);
+            let release = (*(*thumbnail_provider).lpVtbl).Release.unwrap();
+            assert_eq!(release(thumbnail_provider), 2);
+            assert_eq!(release(thumbnail_provider), 1);
+            assert!(!deleted.load(Ordering::SeqCst));
+
+            let release = (*(*unknown).lpVtbl).Release.unwrap();
+            assert_eq!(release(unknown), 0);
+            assert!(deleted.load(Ordering::SeqCst));
+        }
+    }
+
+    /// A provider which generates a `n x 1` image, where `n` is the number of
+    /// bytes in the input.
+    struct ByteCounter;
+
+    impl ThumbnailProvider for ByteCounter {
+        type Error = std::io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            mut reader: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            let mut buffer = Vec::new();
+            reader.read_to_end(&mut buffer)?;
+
+            Ok(RgbaImage::from_pixel(
+                buffer.len() as u32,
+                1,
+                image::Rgba([0, 0, 0, 255]),
+            ))
+        }
+    }
+
+    #[test]
+    fn generate_a_thumbnail_from_an_in_memory_stream() {
+        unsafe {
+            let unknown = Wrapper::new_unknown(ByteCounter);
+            let wrapper = &*Wrapper::<ByteCounter>::from_unknown(unknown);
+            let stream = RustStream::new_stream(Cursor::new(vec![0_u8; 42]));
+
+            let initialize_with_stream = wrapper.as_initialize_with_stream();
+            let initialize =
+                (*(*initialize_with_stream).lpVtbl).Initialize.unwrap();
+            assert_eq!(initialize(initialize_with_stream, stream, 0), S_OK);
+            // the wrapper holds its own reference to the stream
+            assert_eq!((*(*stream).lpVtbl).Release.unwrap()(stream), 1);
+
+            let thumbnail_provider = wrapper.as_thumbnail_provider();
+            let get_thumbnail =
+                (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+            let mut bitmap = ptr::null_mut();
+            let mut alpha = 0;
+            let ret =
+                get_thumbnail(thumbnail_provider, 256, &mut bitmap, &mut alpha);
+
+            assert_eq!(ret, S_OK);
+            assert!(!bitmap.is_null());
+            assert_eq!(alpha, AlphaType::Rgb as WTS_ALPHATYPE);
+            #[cfg(not(windows))]
+            {
+                let emulated = EmulatedBitmap::from_raw(bitmap);
+                assert_eq!(emulated.header.biWidth, 42);
+                assert_eq!(emulated.header.biHeight, -1);
+            }
+
+            DeleteObject(bitmap as HGDIOBJ);
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    #[test]
+    fn stream_reader_can_seek() {
+        let stream = RustStream::new_stream(Cursor::new(b"0123456789"));
+        let mut reader = StreamReader(stream);
+
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut buffer = [0; 2];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"78");
+
+        assert_eq!(reader.seek(SeekFrom::Current(-5)).unwrap(), 4);
+        assert_eq!(reader.seek(SeekFrom::Start(1)).unwrap(), 1);
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"12");
+    }
+
+    #[test]
+    fn unknown_interfaces_are_rejected() {
+        unsafe {
+            let wrapper = Wrapper::new_unknown(DummyProvider);
+            let query_interface = (*(*wrapper).lpVtbl).QueryInterface.unwrap();
+            let unknown_iid = GUID::new(1, 2, 3, [4; 8]);
+            let mut place = ptr::null_mut();
+
+            let got = query_interface(wrapper, &unknown_iid, &mut place);
+
+            assert_eq!(got, E_NOINTERFACE);
+            assert!(place.is_null());
+            let _ = Box::from_raw(wrapper as *mut Wrapper<DummyProvider>);
+        }
+    }
+
+    #[test]
+    fn null_pointers_are_rejected() {
+        unsafe {
+            let unknown = Wrapper::new_unknown(DummyProvider);
+            let wrapper = &*Wrapper::<DummyProvider>::from_unknown(unknown);
+            let query_interface = (*(*unknown).lpVtbl).QueryInterface.unwrap();
+            let thumbnail_provider = wrapper.as_thumbnail_provider();
+            let get_thumbnail =
+                (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+            let initialize_with_stream = wrapper.as_initialize_with_stream();
+            let initialize =
+                (*(*initialize_with_stream).lpVtbl).Initialize.unwrap();
+            let mut alpha = 0;
+
+            assert_eq!(
+                query_interface(unknown, &IUnknown::IID, ptr::null_mut()),
+                E_POINTER
+            );
+            assert_eq!(
+                query_interface(unknown, ptr::null(), &mut ptr::null_mut()),
+                E_POINTER
+            );
+            assert_eq!(
+                get_thumbnail(
+                    thumbnail_provider,
+                    42,
+                    ptr::null_mut(),
+                    &mut alpha
+                ),
+                E_POINTER
+            );
+            assert_eq!(
+                initialize(initialize_with_stream, ptr::null_mut(), 0),
+                E_POINTER
+            );
+            assert_eq!(
+                (*(*unknown).lpVtbl).AddRef.unwrap()(ptr::null_mut()),
+                0
+            );
+
+            let _ = Box::from_raw(unknown as *mut Wrapper<DummyProvider>);
+        }
+    }
+
+    #[test]
+    fn get_thumbnail_before_initializing() {
+        unsafe {
+            let unknown = Wrapper::new_unknown(DummyProvider);
+            let wrapper = &*Wrapper::<DummyProvider>::from_unknown(unknown);
+            let thumbnail_provider = wrapper.as_thumbnail_provider();
+            let get_thumbnail =
+                (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+            let mut bitmap = ptr::null_mut();
+            let mut alpha = 0;
+
+            let got =
+                get_thumbnail(thumbnail_provider, 42, &mut bitmap, &mut alpha);
+
+            assert_eq!(got, E_UNEXPECTED);
+            let _ = Box::from_raw(unknown as *mut Wrapper<DummyProvider>);
+        }
+    }
+
+    struct Panics;
+
+    impl ThumbnailProvider for Panics {
+        type Error = std::io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            _reader: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            panic!("The provider panicked")
+        }
+    }
+
+    #[test]
+    fn panics_become_e_unexpected() {
+        unsafe {
+            let unknown = Wrapper::new_unknown(Panics);
+            let wrapper = &*Wrapper::<Panics>::from_unknown(unknown);
+            let stream = RustStream::new_stream(Cursor::new(Vec::new()));
+            let initialize_with_stream = wrapper.as_initialize_with_stream();
+            (*(*initialize_with_stream).lpVtbl).Initialize.unwrap()(
+                initialize_with_stream,
+                stream,
+                0,
+            );
+            (*(*stream).lpVtbl).Release.unwrap()(stream);
+            let thumbnail_provider = wrapper.as_thumbnail_provider();
+            let get_thumbnail =
+                (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+            let mut bitmap = ptr::null_mut();
+            let mut alpha = 0;
+
+            let got =
+                get_thumbnail(thumbnail_provider, 42, &mut bitmap, &mut alpha);
+
+            assert_eq!(got, E_UNEXPECTED);
+            assert!(bitmap.is_null());
+            assert!(crate::arch::windows::last_panic().is_some());
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    #[test]
+    fn give_up_when_the_timeout_elapses() {
+        unsafe {
+            let unknown = Wrapper::new(ByteCounter)
+                .with_timeout(Some(Duration::from_secs(0)))
+                .into_unknown();
+            let wrapper = &*Wrapper::<ByteCounter>::from_unknown(unknown);
+            let stream = RustStream::new_stream(Cursor::new(vec![0_u8; 42]));
+            let initialize_with_stream = wrapper.as_initialize_with_stream();
+            (*(*initialize_with_stream).lpVtbl).Initialize.unwrap()(
+                initialize_with_stream,
+                stream,
+                0,
+            );
+            (*(*stream).lpVtbl).Release.unwrap()(stream);
+            let thumbnail_provider = wrapper.as_thumbnail_provider();
+            let get_thumbnail =
+                (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+            let mut bitmap = ptr::null_mut();
+            let mut alpha = 0;
+
+            let got =
+                get_thumbnail(thumbnail_provider, 42, &mut bitmap, &mut alpha);
+
+            // the provider doesn't know about timeouts, but reading from the
+            // stream fails once the deadline has passed
+            assert_eq!(got, WTS_E_EXTRACTIONTIMEDOUT);
+            assert!(bitmap.is_null());
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    /// A provider which needs a path, recording the one it was given.
+    #[derive(Default)]
+    struct NeedsPath(Mutex<Option<PathBuf>>);
+
+    impl ThumbnailProvider for NeedsPath {
+        type Error = std::io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            _reader: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            unimplemented!()
+        }
+
+        fn input_sources(&self) -> InputSources { InputSources::PATH }
+
+        fn get_thumbnail_with_context<R>(
+            &self,
+            _input: R,
+            context: &ThumbnailContext,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            assert_eq!(context.size_class, Some(ThumbnailSize::Normal));
+            *self.0.lock().unwrap() = context.path.clone();
+            Ok(RgbaImage::new(1, 1))
+        }
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.to_str()
+            .unwrap()
+            .encode_utf16()
+            .chain(Some(0))
+            .collect()
+    }
+
+    /// Call `GetThumbnail()` and return the path the provider was given.
+    unsafe fn recorded_path(
+        wrapper: &Wrapper<NeedsPath>,
+    ) -> (HRESULT, Option<PathBuf>) {
+        let thumbnail_provider = wrapper.as_thumbnail_provider();
+        let get_thumbnail =
+            (*(*thumbnail_provider).lpVtbl).GetThumbnail.unwrap();
+        let mut bitmap = ptr::null_mut();
+        let mut alpha = 0;
+
+        let ret =
+            get_thumbnail(thumbnail_provider, 42, &mut bitmap, &mut alpha);
+
+        if !bitmap.is_null() {
+            DeleteObject(bitmap as HGDIOBJ);
+        }
+        (ret, wrapper.inner().0.lock().unwrap().take())
+    }
+
+    #[test]
+    fn only_answer_for_the_initializers_a_provider_accepts() {
+        unsafe {
+            let inputs: Vec<(*mut IUnknown, bool)> = vec![
+                (Wrapper::new_unknown(DummyProvider), false),
+                (Wrapper::new_unknown(NeedsPath::default()), true),
+            ];
+
+            for (unknown, wants_path) in inputs {
+                let query_interface =
+                    (*(*unknown).lpVtbl).QueryInterface.unwrap();
+                let release = (*(*unknown).lpVtbl).Release.unwrap();
+                let iids = [
+                    (IInitializeWithStream::IID, !wants_path),
+                    (IInitializeWithFile::IID, wants_path),
+                    (IInitializeWithItem::IID, wants_path),
+                ];
+
+                for (iid, supported) in &iids {
+                    let mut p = ptr::null_mut();
+                    let ret = query_interface(unknown, iid, &mut p);
+
+                    if *supported {
+                        assert_eq!(ret, S_OK);
+                        release(unknown);
+                    } else {
+                        assert_eq!(ret, E_NOINTERFACE);
+                    }
+                }
+
+                assert_eq!(release(unknown), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn initialize_with_a_file() {
+        let temp = tempfile::NamedTempFile::new().unwrap();
+        let path = temp.path();
+
+        unsafe {
+            let unknown = Wrapper::new_unknown(NeedsPath::default());
+            let wrapper = &*Wrapper::<NeedsPath>::from_unknown(unknown);
+            let initialize_with_file = wrapper.as_initialize_with_file();
+            let initialize =
+                (*(*initialize_with_file).lpVtbl).Initialize.unwrap();
+
+            let ret = initialize(initialize_with_file, wide(path).as_ptr(), 0);
+
+            assert_eq!(ret, S_OK);
+            assert_eq!(
+                recorded_path(wrapper),
+                (S_OK, Some(path.to_path_buf()))
+            );
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("missing.png");
+
+        unsafe {
+            let unknown = Wrapper::new_unknown(NeedsPath::default());
+            let wrapper = &*Wrapper::<NeedsPath>::from_unknown(unknown);
+            let initialize_with_file = wrapper.as_initialize_with_file();
+            (*(*initialize_with_file).lpVtbl).Initialize.unwrap()(
+                initialize_with_file,
+                wide(&path).as_ptr(),
+                0,
+            );
+
+            let (ret, got) = recorded_path(wrapper);
+
+            assert!(FAILED(ret));
+            assert!(got.is_none());
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    /// A bare-bones `IShellItem` which only knows its path.
+    #[repr(C)]
+    struct FakeShellItem {
+        vtable: *mut IShellItemVtbl,
+        path: Option<PathBuf>,
+    }
+
+    impl FakeShellItem {
+        const VTABLE: IShellItemVtbl = IShellItemVtbl {
+            QueryInterface: None,
+            AddRef: None,
+            Release: None,
+            BindToHandler: None,
+            GetParent: None,
+            GetDisplayName: Some(fake_shell_item_get_display_name),
+            GetAttributes: None,
+            Compare: None,
+        };
+
+        fn new(path: Option<PathBuf>) -> Self {
+            FakeShellItem {
+                vtable: &FakeShellItem::VTABLE as *const _ as *mut _,
+                path,
+            }
+        }
+
+        fn as_shell_item(&mut self) -> *mut IShellItem {
+            self as *mut FakeShellItem as *mut IShellItem
+        }
+    }
+
+    unsafe extern "system" fn fake_shell_item_get_display_name(
+        this: *mut IShellItem,
+        sigdn: SIGDN,
+        name: *mut LPWSTR,
+    ) -> HRESULT {
+        let this = &*(this as *mut FakeShellItem);
+
+        match &this.path {
+            Some(path) if sigdn == _SIGDN_SIGDN_FILESYSPATH => {
+                let wide = wide(path);
+                let buffer = CoTaskMemAlloc(wide.len() * 2) as *mut u16;
+                ptr::copy_nonoverlapping(wide.as_ptr(), buffer, wide.len());
+                *name = buffer;
+                S_OK
+            },
+            _ => E_INVALIDARG,
+        }
+    }
+
+    #[test]
+    fn initialize_with_a_shell_item() {
+        let temp = tempfile::NamedTempFile::new().unwrap();
+        let path = temp.path();
+        let mut item = FakeShellItem::new(Some(path.to_path_buf()));
+
+        unsafe {
+            let unknown = Wrapper::new_unknown(NeedsPath::default());
+            let wrapper = &*Wrapper::<NeedsPath>::from_unknown(unknown);
+            let initialize_with_item = wrapper.as_initialize_with_item();
+            let initialize =
+                (*(*initialize_with_item).lpVtbl).Initialize.unwrap();
+
+            let ret = initialize(initialize_with_item, item.as_shell_item(), 0);
+
+            assert_eq!(ret, S_OK);
+            assert_eq!(
+                recorded_path(wrapper),
+                (S_OK, Some(path.to_path_buf()))
+            );
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
+
+    #[test]
+    fn shell_items_must_be_on_the_file_system() {
+        let mut item = FakeShellItem::new(None);
+
+        unsafe {
+            let unknown = Wrapper::new_unknown(NeedsPath::default());
+            let wrapper = &*Wrapper::<NeedsPath>::from_unknown(unknown);
+            let initialize_with_item = wrapper.as_initialize_with_item();
+            let initialize =
+                (*(*initialize_with_item).lpVtbl).Initialize.unwrap();
+
+            let ret = initialize(initialize_with_item, item.as_shell_item(), 0);
+
+            assert_eq!(ret, E_INVALIDARG);
+            assert_eq!(recorded_path(wrapper), (E_UNEXPECTED, None));
+            assert_eq!((*(*unknown).lpVtbl).Release.unwrap()(unknown), 0);
+        }
+    }
 }
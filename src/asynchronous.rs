@@ -0,0 +1,527 @@
+//! Generating thumbnails from within an async runtime.
+//!
+//! The [`AsyncThumbnailProvider`] trait is the async counterpart to
+//! [`ThumbnailProvider`], and the [`SpawnBlocking`] and [`BlockOn`] adapters
+//! let a provider written for one be used wherever the other is expected.
+
+use crate::{
+    Dimensions, Limit, Limits, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use image::GenericImageView;
+use std::{
+    error::Error,
+    future::Future,
+    io::{self, Cursor, Read, Seek},
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    runtime::{Builder, Handle, Runtime},
+    sync::Semaphore,
+    task,
+};
+
+/// An owned, type-erased [`Future`] which can be sent between threads.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Something which can generate thumbnails without blocking the current
+/// thread.
+pub trait AsyncThumbnailProvider: Send + Sync {
+    type Thumbnail: GenericImageView + Send;
+    type Error: Error + Send + Sync + 'static;
+
+    fn get_thumbnail<'a, R>(
+        &'a self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+    where
+        R: AsyncRead + Unpin + Send + 'a;
+
+    /// Generate a thumbnail using whatever the caller knows about the input.
+    ///
+    /// The default implementation ignores the context and defers to
+    /// [`AsyncThumbnailProvider::get_thumbnail()`].
+    fn get_thumbnail_with_context<'a, R>(
+        &'a self,
+        input: R,
+        context: &'a ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        let _ = context;
+        self.get_thumbnail(input, desired_dimensions)
+    }
+}
+
+/// Run a [`ThumbnailProvider`] on tokio's blocking thread pool so it can be
+/// used as an [`AsyncThumbnailProvider`].
+///
+/// The input is read into memory before being handed to the provider, and at
+/// most `max_concurrency` thumbnails will be generated at a time.
+///
+/// # Examples
+///
+/// ```rust
+/// use thumbnails::{
+///     providers::ImageProvider, AsyncThumbnailProvider, Dimensions,
+///     SpawnBlocking,
+/// };
+///
+/// # tokio::runtime::Builder::new_current_thread()
+/// #     .build()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let provider = SpawnBlocking::new(ImageProvider::default(), 4);
+///
+/// let input: &[u8] = b"definitely not an image";
+/// let got = provider.get_thumbnail(input, Dimensions::square(128)).await;
+///
+/// assert!(got.is_err());
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct SpawnBlocking<P> {
+    provider: Arc<P>,
+    permits: Arc<Semaphore>,
+}
+
+impl<P> SpawnBlocking<P> {
+    /// Wrap a provider, allowing it to generate up to `max_concurrency`
+    /// thumbnails in parallel.
+    ///
+    /// # Panics
+    ///
+    /// If `max_concurrency` is zero.
+    pub fn new(provider: P, max_concurrency: usize) -> Self {
+        assert!(
+            max_concurrency > 0,
+            "The concurrency limit must be non-zero"
+        );
+
+        SpawnBlocking {
+            provider: Arc::new(provider),
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+        }
+    }
+
+    pub fn provider(&self) -> &P { &self.provider }
+}
+
+impl<P> SpawnBlocking<P>
+where
+    P: ThumbnailProvider + 'static,
+    P::Thumbnail: Send + 'static,
+{
+    async fn run<R>(
+        &self,
+        input: R,
+        context: ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<P::Thumbnail, ThumbnailError>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        // Acquire the permit before buffering the input so the limit also
+        // bounds how much memory is in use
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(ThumbnailError::internal)?;
+
+        let max_input_bytes =
+            context.limits.max_input_bytes.unwrap_or(u64::MAX);
+        let mut buffer = Vec::new();
+        input
+            .take(max_input_bytes.saturating_add(1))
+            .read_to_end(&mut buffer)
+            .await?;
+        if buffer.len() as u64 > max_input_bytes {
+            return Err(Limit::InputBytes(max_input_bytes).into());
+        }
+
+        let provider = Arc::clone(&self.provider);
+        let outcome = task::spawn_blocking(move || {
+            // the permit is only released once the provider is done, even if
+            // the caller stops waiting for us
+            let _permit = permit;
+            provider.get_thumbnail_with_context(
+                Cursor::new(buffer),
+                &context,
+                desired_dimensions,
+            )
+        })
+        .await;
+
+        match outcome {
+            Ok(result) => result.map_err(into_thumbnail_error),
+            Err(e) if e.is_cancelled() => Err(ThumbnailError::Cancelled),
+            Err(e) => Err(ThumbnailError::internal(e)),
+        }
+    }
+}
+
+impl<P> AsyncThumbnailProvider for SpawnBlocking<P>
+where
+    P: ThumbnailProvider + 'static,
+    P::Thumbnail: Send + 'static,
+{
+    type Error = ThumbnailError;
+    type Thumbnail = P::Thumbnail;
+
+    fn get_thumbnail<'a, R>(
+        &'a self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        Box::pin(self.run(input, ThumbnailContext::new(), desired_dimensions))
+    }
+
+    fn get_thumbnail_with_context<'a, R>(
+        &'a self,
+        input: R,
+        context: &'a ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+    where
+        R: AsyncRead + Unpin + Send + 'a,
+    {
+        Box::pin(self.run(input, context.clone(), desired_dimensions))
+    }
+}
+
+/// Block the current thread on an [`AsyncThumbnailProvider`] so it can be
+/// used as a normal [`ThumbnailProvider`] (e.g. in a
+/// [`ProviderRegistry`][crate::ProviderRegistry], the `thumbnails` program,
+/// or the Windows shell extension).
+///
+/// This must not be used from within an async context.
+#[derive(Debug)]
+pub struct BlockOn<P> {
+    provider: P,
+    executor: Executor,
+}
+
+impl<P> BlockOn<P> {
+    /// Wrap a provider, driving it with a new single-threaded runtime.
+    pub fn new(provider: P) -> Result<Self, io::Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+
+        Ok(BlockOn {
+            provider,
+            executor: Executor::Owned(runtime),
+        })
+    }
+
+    /// Wrap a provider, driving it with an existing runtime.
+    pub fn with_handle(provider: P, handle: Handle) -> Self {
+        BlockOn {
+            provider,
+            executor: Executor::Shared(handle),
+        }
+    }
+
+    pub fn provider(&self) -> &P { &self.provider }
+}
+
+impl<P: AsyncThumbnailProvider> ThumbnailProvider for BlockOn<P> {
+    type Error = ThumbnailError;
+    type Thumbnail = P::Thumbnail;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        let mut buffer = Vec::new();
+        Limits::default().reader(input).read_to_end(&mut buffer)?;
+
+        self.executor
+            .block_on(
+                self.provider
+                    .get_thumbnail(Cursor::new(buffer), desired_dimensions),
+            )
+            .map_err(into_thumbnail_error)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let mut buffer = Vec::new();
+        context.limits.reader(input).read_to_end(&mut buffer)?;
+
+        self.executor
+            .block_on(self.provider.get_thumbnail_with_context(
+                Cursor::new(buffer),
+                context,
+                desired_dimensions,
+            ))
+            .map_err(into_thumbnail_error)
+    }
+}
+
+#[derive(Debug)]
+enum Executor {
+    Owned(Runtime),
+    Shared(Handle),
+}
+
+impl Executor {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        match self {
+            // a current-thread runtime's timers and I/O are only driven by
+            // Runtime::block_on(), not Handle::block_on()
+            Executor::Owned(runtime) => runtime.block_on(future),
+            Executor::Shared(handle) => handle.block_on(future),
+        }
+    }
+}
+
+fn into_thumbnail_error<E>(e: E) -> ThumbnailError
+where
+    E: Error + Send + Sync + 'static,
+{
+    ThumbnailError::from(Box::new(e) as Box<dyn Error + Send + Sync>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Hint, ProviderRegistry};
+    use image::{Rgba, RgbaImage};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    /// A provider which generates a solid image using the first byte of its
+    /// input, keeping track of how many calls are running at once.
+    #[derive(Default)]
+    struct Solid {
+        running: AtomicUsize,
+        most_running: AtomicUsize,
+    }
+
+    impl ThumbnailProvider for Solid {
+        type Error = ThumbnailError;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            mut input: R,
+            desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error>
+        where
+            R: Read,
+        {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_running.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            let mut buffer = [0];
+            input
+                .read_exact(&mut buffer)
+                .map_err(|_| ThumbnailError::unsupported("empty input"))?;
+
+            Ok(RgbaImage::from_pixel(
+                desired_dimensions.width,
+                desired_dimensions.height,
+                Rgba([buffer[0], 0, 0, 0xff]),
+            ))
+        }
+    }
+
+    /// An async provider which reports the length of its input and file name
+    /// using the red and green channels.
+    struct Lengths;
+
+    impl AsyncThumbnailProvider for Lengths {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<'a, R>(
+            &'a self,
+            input: R,
+            desired_dimensions: Dimensions,
+        ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+        where
+            R: AsyncRead + Unpin + Send + 'a,
+        {
+            Box::pin(lengths(input, None, desired_dimensions))
+        }
+
+        fn get_thumbnail_with_context<'a, R>(
+            &'a self,
+            input: R,
+            context: &'a ThumbnailContext,
+            desired_dimensions: Dimensions,
+        ) -> BoxFuture<'a, Result<Self::Thumbnail, Self::Error>>
+        where
+            R: AsyncRead + Unpin + Send + 'a,
+        {
+            Box::pin(lengths(input, context.file_name(), desired_dimensions))
+        }
+    }
+
+    async fn lengths<R>(
+        mut input: R,
+        file_name: Option<&str>,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, io::Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        task::yield_now().await;
+        let mut buffer = Vec::new();
+        input.read_to_end(&mut buffer).await?;
+        let name_length = file_name.map(str::len).unwrap_or(0);
+
+        Ok(RgbaImage::from_pixel(
+            desired_dimensions.width,
+            desired_dimensions.height,
+            Rgba([buffer.len() as u8, name_length as u8, 0, 0xff]),
+        ))
+    }
+
+    #[test]
+    fn run_a_sync_provider_in_the_background() {
+        let provider = SpawnBlocking::new(Solid::default(), 1);
+        let input: &[u8] = &[42];
+
+        let got = runtime()
+            .block_on(provider.get_thumbnail(input, Dimensions::square(4)))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (4, 4));
+        assert_eq!(got.get_pixel(0, 0), &Rgba([42, 0, 0, 0xff]));
+    }
+
+    #[test]
+    fn the_number_of_concurrent_calls_is_limited() {
+        let provider = Arc::new(SpawnBlocking::new(Solid::default(), 2));
+
+        runtime().block_on(async {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let provider = Arc::clone(&provider);
+                    tokio::spawn(async move {
+                        let input: &[u8] = &[1];
+                        provider
+                            .get_thumbnail(input, Dimensions::square(1))
+                            .await
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                handle.await.unwrap().unwrap();
+            }
+        });
+
+        let most_running =
+            provider.provider().most_running.load(Ordering::SeqCst);
+        assert!(most_running <= 2, "{} calls ran at once", most_running);
+    }
+
+    #[test]
+    fn thumbnail_errors_are_preserved() {
+        let provider = SpawnBlocking::new(Solid::default(), 1);
+        let input: &[u8] = &[];
+
+        let err = runtime()
+            .block_on(provider.get_thumbnail(input, Dimensions::square(4)))
+            .unwrap_err();
+
+        assert_eq!(err.exit_code(), 3);
+    }
+
+    #[test]
+    fn inputs_are_limited_before_being_buffered() {
+        let provider = SpawnBlocking::new(Solid::default(), 1);
+        let limits = Limits::none().with_max_input_bytes(4);
+        let context = ThumbnailContext::new().with_limits(limits);
+        let input: &[u8] = &[1, 2, 3, 4, 5];
+
+        let err = runtime()
+            .block_on(provider.get_thumbnail_with_context(
+                input,
+                &context,
+                Dimensions::square(4),
+            ))
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ThumbnailError::LimitExceeded(Limit::InputBytes(4))
+        ));
+    }
+
+    #[test]
+    fn block_on_an_async_provider() {
+        let provider = BlockOn::new(Lengths).unwrap();
+        let context = ThumbnailContext::new().with_path("image.png");
+
+        let got = provider
+            .get_thumbnail_with_context(
+                Cursor::new([0_u8; 5]),
+                &context,
+                Dimensions::square(2),
+            )
+            .unwrap();
+
+        assert_eq!(got.get_pixel(0, 0), &Rgba([5, 9, 0, 0xff]));
+    }
+
+    #[test]
+    fn async_providers_can_be_registered() {
+        let mut registry = ProviderRegistry::new();
+        registry
+            .register(BlockOn::new(Lengths).unwrap())
+            .extension("txt");
+        let mut input: &[u8] = b"Hello, World!";
+
+        let got = registry
+            .thumbnail_for(
+                &mut input,
+                &Hint::default().with_file_name("hello.txt"),
+                Dimensions::square(2),
+            )
+            .unwrap();
+
+        assert_eq!(got.get_pixel(0, 0), &Rgba([13, 0, 0, 0xff]));
+    }
+
+    #[test]
+    fn block_on_a_shared_runtime() {
+        let runtime = runtime();
+        let provider = BlockOn::with_handle(Lengths, runtime.handle().clone());
+
+        let got = provider
+            .get_thumbnail(&[0_u8; 3][..], Dimensions::square(2))
+            .unwrap();
+
+        assert_eq!(got.get_pixel(0, 0), &Rgba([3, 0, 0, 0xff]));
+    }
+}
@@ -0,0 +1,329 @@
+//! A thumbnailer which follows the freedesktop.org calling convention, so it
+//! can be used by file managers like Nautilus or Dolphin.
+
+use image::RgbaImage;
+use png::{BitDepth, ColorType, Encoder};
+use std::{
+    env,
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+use thumbnails::{
+    cache::{self, ThumbnailSize},
+    Dimensions, ProviderRegistry, ThumbnailContext, ThumbnailError,
+};
+
+const USAGE: &str = "\
+Usage:
+    thumbnails [-s <size>] <input> <output>
+    thumbnails thumbnailer [--exec <command>] [-o <file>]
+    thumbnails --help
+
+Generate a thumbnail for <input> (a path or file:// URI) and save it to
+<output> as a PNG, or print a .thumbnailer entry listing the supported MIME
+types.
+
+Options:
+    -s, --size <size>       The maximum width and height [default: 128]
+    --exec <command>        The command used in the thumbnailer entry
+                            [default: thumbnails]
+    -o, --output <file>     Write the thumbnailer entry to a file instead of
+                            stdout
+
+Exit status:
+    0   Success
+    1   Unable to read the input or write the output
+    2   Invalid arguments
+    3   The file format isn't supported
+    4   The file is corrupt
+    5   The file is too large
+    6   Timed out
+    7   Cancelled
+    8   Internal error
+";
+
+const DEFAULT_SIZE: u32 = 128;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let exit_code = match parse_args(&args) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            0
+        },
+        Ok(command) => match run(command) {
+            Ok(()) => 0,
+            Err(e) => {
+                report_error(&*e);
+                ThumbnailError::find(&*e)
+                    .map(ThumbnailError::exit_code)
+                    .unwrap_or(1)
+            },
+        },
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            eprintln!();
+            eprint!("{}", USAGE);
+            2
+        },
+    };
+
+    process::exit(exit_code);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Thumbnail {
+        size: u32,
+        input: String,
+        output: PathBuf,
+    },
+    Thumbnailer {
+        exec: String,
+        output: Option<PathBuf>,
+    },
+    Help,
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+
+    match args.split_first() {
+        Some((first, rest)) if first == "thumbnailer" => {
+            parse_thumbnailer_args(rest)
+        },
+        _ => parse_thumbnail_args(args),
+    }
+}
+
+fn parse_thumbnail_args(args: &[String]) -> Result<Command, String> {
+    let mut size = DEFAULT_SIZE;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = if arg == "-s" || arg == "--size" {
+            args.next().ok_or("The size is missing")?
+        } else if let Some(value) = arg.strip_prefix("--size=") {
+            value
+        } else if let Some(value) =
+            arg.strip_prefix("-s").filter(|v| !v.is_empty())
+        {
+            value
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            return Err(format!("Unknown argument, \"{}\"", arg));
+        } else {
+            positional.push(arg);
+            continue;
+        };
+
+        size = match value.parse() {
+            Ok(s) if s > 0 => s,
+            _ => return Err(format!("\"{}\" isn't a valid size", value)),
+        };
+    }
+
+    match positional.as_slice() {
+        [input, output] => Ok(Command::Thumbnail {
+            size,
+            input: input.to_string(),
+            output: PathBuf::from(output),
+        }),
+        _ => Err(String::from("Expected an input and an output")),
+    }
+}
+
+fn parse_thumbnailer_args(args: &[String]) -> Result<Command, String> {
+    let mut exec = String::from(env!("CARGO_BIN_NAME"));
+    let mut output = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exec" => {
+                exec = args.next().ok_or("The command is missing")?.clone();
+            },
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(
+                    args.next().ok_or("The file is missing")?,
+                ));
+            },
+            other => return Err(format!("Unknown argument, \"{}\"", other)),
+        }
+    }
+
+    Ok(Command::Thumbnailer { exec, output })
+}
+
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    let registry = ProviderRegistry::with_builtin_providers();
+
+    match command {
+        Command::Thumbnail {
+            size,
+            input,
+            output,
+        } => {
+            let path = input_path(&input)?;
+            let mut context = ThumbnailContext::new().with_path(&path);
+            context.size_class = ThumbnailSize::for_pixels(size);
+
+            let thumbnail = registry
+                .thumbnail_for_context(
+                    &path,
+                    &context,
+                    Dimensions::square(size),
+                )
+                .map_err(ThumbnailError::from)?;
+            save_png(&output, &thumbnail)?;
+        },
+        Command::Thumbnailer { exec, output } => {
+            let entry = thumbnailer_entry(&exec, &registry.mime_types());
+
+            match output {
+                Some(path) => {
+                    File::create(path)?.write_all(entry.as_bytes())?
+                },
+                None => io::stdout().write_all(entry.as_bytes())?,
+            }
+        },
+        Command::Help => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// File managers may pass us either a path or a URI.
+fn input_path(input: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if input.starts_with("file://") {
+        cache::path_from_file_uri(input).ok_or_else(|| {
+            format!("\"{}\" isn't a valid file URI", input).into()
+        })
+    } else if input.contains("://") {
+        Err(format!("Only local files are supported, not \"{}\"", input).into())
+    } else {
+        Ok(PathBuf::from(input))
+    }
+}
+
+/// Generate the contents of a `*.thumbnailer` file.
+fn thumbnailer_entry(exec: &str, mime_types: &[&str]) -> String {
+    let mut entry = String::from("[Thumbnailer Entry]\n");
+    entry.push_str(&format!("TryExec={}\n", exec));
+    entry.push_str(&format!("Exec={} -s %s %u %o\n", exec));
+    entry.push_str("MimeType=");
+    for mime_type in mime_types {
+        entry.push_str(mime_type);
+        entry.push(';');
+    }
+    entry.push('\n');
+
+    entry
+}
+
+fn save_png(path: &Path, image: &RgbaImage) -> Result<(), Box<dyn Error>> {
+    let (width, height) = image.dimensions();
+    let mut encoder =
+        Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn report_error(e: &dyn Error) {
+    eprintln!("Error: {}", e);
+
+    let mut source = e.source();
+    while let Some(cause) = source {
+        eprintln!("\tCaused by: {}", cause);
+        source = cause.source();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn thumbnailer_calling_convention() {
+        let inputs = vec![
+            (vec!["in.png", "out.png"], DEFAULT_SIZE),
+            (vec!["-s", "256", "in.png", "out.png"], 256),
+            (vec!["in.png", "--size", "64", "out.png"], 64),
+            (vec!["-s512", "in.png", "out.png"], 512),
+            (vec!["--size=32", "in.png", "out.png"], 32),
+        ];
+
+        for (arguments, size) in inputs {
+            let got = parse_args(&args(&arguments)).unwrap();
+
+            assert_eq!(
+                got,
+                Command::Thumbnail {
+                    size,
+                    input: String::from("in.png"),
+                    output: PathBuf::from("out.png"),
+                },
+                "{:?}",
+                arguments
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let inputs = vec![
+            vec![],
+            vec!["in.png"],
+            vec!["-s", "0", "in.png", "out.png"],
+            vec!["-s", "big", "in.png", "out.png"],
+            vec!["--verbose", "in.png", "out.png"],
+            vec!["thumbnailer", "--exec"],
+        ];
+
+        for arguments in inputs {
+            assert!(parse_args(&args(&arguments)).is_err(), "{:?}", arguments);
+        }
+    }
+
+    #[test]
+    fn generate_a_thumbnailer_entry() {
+        let got = thumbnailer_entry(
+            "/usr/bin/thumbnails",
+            &["image/gif", "image/png"],
+        );
+
+        assert_eq!(
+            got,
+            "[Thumbnailer Entry]\n\
+             TryExec=/usr/bin/thumbnails\n\
+             Exec=/usr/bin/thumbnails -s %s %u %o\n\
+             MimeType=image/gif;image/png;\n"
+        );
+    }
+
+    #[test]
+    fn inputs_can_be_paths_or_uris() {
+        assert_eq!(
+            input_path("file:///tmp/a%20b.png").unwrap(),
+            PathBuf::from("/tmp/a b.png")
+        );
+        assert_eq!(input_path("a b.png").unwrap(), PathBuf::from("a b.png"));
+        assert!(input_path("smb://server/share/a.png").is_err());
+    }
+}
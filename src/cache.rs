@@ -0,0 +1,891 @@
+//! A thumbnail cache following the freedesktop.org [Thumbnail Managing
+//! Standard][spec].
+//!
+//! [spec]: https://specifications.freedesktop.org/thumbnail-spec/latest/
+
+use crate::{
+    dynamic::to_rgba, Dimensions, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use image::{GenericImageView, Pixel, RgbaImage};
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter, Write as _},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::UNIX_EPOCH,
+};
+
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+const SIZE_KEY: &str = "Thumb::Size";
+const MIME_TYPE_KEY: &str = "Thumb::Mimetype";
+const SOFTWARE_KEY: &str = "Software";
+
+/// The standard thumbnail sizes, each stored in its own directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ThumbnailSize {
+    /// 128x128 pixels.
+    Normal,
+    /// 256x256 pixels.
+    Large,
+    /// 512x512 pixels.
+    XLarge,
+    /// 1024x1024 pixels.
+    XXLarge,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 4] = [
+        ThumbnailSize::Normal,
+        ThumbnailSize::Large,
+        ThumbnailSize::XLarge,
+        ThumbnailSize::XXLarge,
+    ];
+
+    /// The maximum width and height of a thumbnail of this size.
+    pub fn pixels(self) -> u32 {
+        match self {
+            ThumbnailSize::Normal => 128,
+            ThumbnailSize::Large => 256,
+            ThumbnailSize::XLarge => 512,
+            ThumbnailSize::XXLarge => 1024,
+        }
+    }
+
+    pub fn directory_name(self) -> &'static str {
+        match self {
+            ThumbnailSize::Normal => "normal",
+            ThumbnailSize::Large => "large",
+            ThumbnailSize::XLarge => "x-large",
+            ThumbnailSize::XXLarge => "xx-large",
+        }
+    }
+
+    /// The smallest standard size which is at least `pixels` wide, if any.
+    pub fn for_pixels(pixels: u32) -> Option<ThumbnailSize> {
+        ThumbnailSize::ALL
+            .iter()
+            .copied()
+            .find(|size| size.pixels() >= pixels)
+    }
+
+    pub fn dimensions(self) -> Dimensions { Dimensions::square(self.pixels()) }
+}
+
+/// A cache of thumbnails stored on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailCache {
+    root: PathBuf,
+    application: String,
+}
+
+impl ThumbnailCache {
+    /// Create a cache stored in the `root` directory, where `application` is
+    /// the name used when recording failures (e.g. `"my-app-1.0"`).
+    pub fn new<P, S>(root: P, application: S) -> Self
+    where
+        P: Into<PathBuf>,
+        S: Into<String>,
+    {
+        ThumbnailCache {
+            root: root.into(),
+            application: application.into(),
+        }
+    }
+
+    /// Use the current user's cache directory, `$XDG_CACHE_HOME/thumbnails`
+    /// or `$HOME/.cache/thumbnails`.
+    pub fn for_current_user<S: Into<String>>(application: S) -> Option<Self> {
+        let cache_home = env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| Path::new(&home).join(".cache"))
+            })?;
+
+        Some(ThumbnailCache::new(
+            cache_home.join("thumbnails"),
+            application,
+        ))
+    }
+
+    pub fn root(&self) -> &Path { &self.root }
+
+    pub fn application(&self) -> &str { &self.application }
+
+    /// Where the thumbnail for a URI would be stored.
+    pub fn thumbnail_path(&self, uri: &str, size: ThumbnailSize) -> PathBuf {
+        self.root
+            .join(size.directory_name())
+            .join(thumbnail_file_name(uri))
+    }
+
+    /// Where a failed attempt to thumbnail a URI would be recorded.
+    pub fn failure_path(&self, uri: &str) -> PathBuf {
+        self.root
+            .join("fail")
+            .join(&self.application)
+            .join(thumbnail_file_name(uri))
+    }
+
+    /// Look up a valid thumbnail for a file, returning `None` if it isn't in
+    /// the cache or the original file has been modified since.
+    pub fn lookup(
+        &self,
+        path: &Path,
+        size: ThumbnailSize,
+    ) -> io::Result<Option<RgbaImage>> {
+        let source = Source::for_path(path)?;
+        let thumbnail_path = self.thumbnail_path(&source.uri, size);
+
+        match read_entry(&thumbnail_path) {
+            Ok((entry, image)) if source.is_described_by(&entry) => {
+                Ok(Some(image))
+            },
+            // missing, corrupt, or stale entries are all cache misses
+            _ => Ok(None),
+        }
+    }
+
+    /// Has this application previously failed to generate a thumbnail for the
+    /// current version of a file?
+    pub fn has_failed(&self, path: &Path) -> io::Result<bool> {
+        let source = Source::for_path(path)?;
+
+        match read_entry(&self.failure_path(&source.uri)) {
+            Ok((entry, _)) => Ok(source.is_described_by(&entry)),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Get the thumbnail for a file, using the `provider` to generate (and
+    /// save) it when there is no valid entry in the cache.
+    ///
+    /// If the provider fails, a failure entry is recorded so subsequent calls
+    /// return [`CacheError::PreviouslyFailed`] until the file changes. Errors
+    /// which may go away by themselves (see [`ThumbnailError::is_permanent()`])
+    /// aren't recorded.
+    pub fn get_or_generate<P>(
+        &self,
+        provider: &P,
+        path: &Path,
+        mime_type: Option<&str>,
+        size: ThumbnailSize,
+    ) -> Result<RgbaImage, CacheError<P::Error>>
+    where
+        P: ThumbnailProvider,
+        <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+    {
+        if let Some(thumbnail) = self.lookup(path, size)? {
+            return Ok(thumbnail);
+        }
+
+        if self.has_failed(path)? {
+            return Err(CacheError::PreviouslyFailed);
+        }
+
+        let mut source = Source::for_path(path)?;
+        source.mime_type = mime_type.map(String::from);
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut context = ThumbnailContext::new()
+            .with_path(path)
+            .with_size_class(size);
+        context.mime_type = source.mime_type.clone();
+        context.modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        match provider.get_thumbnail_with_context(
+            reader,
+            &context,
+            size.dimensions(),
+        ) {
+            Ok(thumbnail) => {
+                let thumbnail = to_rgba(&thumbnail);
+                write_entry(
+                    &self.thumbnail_path(&source.uri, size),
+                    &source,
+                    &thumbnail,
+                )?;
+                Ok(thumbnail)
+            },
+            Err(e) => {
+                // there's no point remembering failures which might not
+                // happen next time
+                let is_permanent = ThumbnailError::find(&e)
+                    .map(ThumbnailError::is_permanent)
+                    .unwrap_or(true);
+
+                if is_permanent {
+                    write_entry(
+                        &self.failure_path(&source.uri),
+                        &source,
+                        &RgbaImage::new(1, 1),
+                    )?;
+                }
+
+                Err(CacheError::Provider(e))
+            },
+        }
+    }
+
+    /// Save a thumbnail to the cache.
+    pub fn store<I>(
+        &self,
+        path: &Path,
+        mime_type: Option<&str>,
+        size: ThumbnailSize,
+        thumbnail: &I,
+    ) -> io::Result<()>
+    where
+        I: GenericImageView,
+        I::Pixel: Pixel<Subpixel = u8>,
+    {
+        let mut source = Source::for_path(path)?;
+        source.mime_type = mime_type.map(String::from);
+
+        write_entry(
+            &self.thumbnail_path(&source.uri, size),
+            &source,
+            &to_rgba(thumbnail),
+        )
+    }
+
+    /// Record that generating a thumbnail for the current version of a file
+    /// failed.
+    pub fn record_failure(
+        &self,
+        path: &Path,
+        mime_type: Option<&str>,
+    ) -> io::Result<()> {
+        let mut source = Source::for_path(path)?;
+        source.mime_type = mime_type.map(String::from);
+
+        write_entry(
+            &self.failure_path(&source.uri),
+            &source,
+            &RgbaImage::new(1, 1),
+        )
+    }
+
+    /// Remove every cached thumbnail and failure entry for a file.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let uri = file_uri(&absolute(path)?);
+        let paths = ThumbnailSize::ALL
+            .iter()
+            .map(|&size| self.thumbnail_path(&uri, size))
+            .chain(std::iter::once(self.failure_path(&uri)));
+
+        for path in paths {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The error returned by [`ThumbnailCache::get_or_generate()`].
+#[derive(Debug)]
+pub enum CacheError<E> {
+    /// Unable to access the original file or the cache.
+    Io(io::Error),
+    /// A previous attempt to generate a thumbnail for this version of the file
+    /// failed.
+    PreviouslyFailed,
+    /// The provider was unable to generate a thumbnail.
+    Provider(E),
+}
+
+impl<E: Display> Display for CacheError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Io(_) => write!(f, "Unable to access the cache"),
+            CacheError::PreviouslyFailed => write!(
+                f,
+                "A previous attempt to generate this thumbnail failed"
+            ),
+            CacheError::Provider(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E> Error for CacheError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CacheError::Io(e) => Some(e),
+            CacheError::PreviouslyFailed => None,
+            CacheError::Provider(e) => e.source(),
+        }
+    }
+}
+
+impl<E> From<io::Error> for CacheError<E> {
+    fn from(e: io::Error) -> Self { CacheError::Io(e) }
+}
+
+/// Get the canonical `file://` URI for an absolute path.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::path::Path;
+/// let uri = thumbnails::cache::file_uri(Path::new("/home/jens/My Photos/me.png"));
+///
+/// assert_eq!(uri, "file:///home/jens/My%20Photos/me.png");
+/// ```
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for &byte in path_bytes(path).iter() {
+        // The same characters GLib leaves unescaped, so our hashes match
+        // those generated by other applications
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(&byte)
+        {
+            uri.push(byte as char);
+        } else {
+            let _ = write!(uri, "%{:02X}", byte);
+        }
+    }
+
+    uri
+}
+
+/// Convert a `file://` URI back into a path.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::path::Path;
+/// let path = thumbnails::cache::path_from_file_uri("file:///tmp/My%20Photo.png");
+///
+/// assert_eq!(path.as_deref(), Some(Path::new("/tmp/My Photo.png")));
+/// ```
+pub fn path_from_file_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // skip the authority, which is normally empty or "localhost"
+    let path = &rest[rest.find('/')?..];
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut chars = path.bytes();
+
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let high = (chars.next()? as char).to_digit(16)?;
+            let low = (chars.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    path_from_bytes(bytes)
+}
+
+/// The file name a thumbnail for a URI is saved under.
+pub fn thumbnail_file_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    let path = String::from_utf8(bytes).ok()?;
+    // "file:///C:/foo" has a leading slash before the drive letter
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] => &path[1..],
+        _ => &path[..],
+    };
+
+    Some(PathBuf::from(path))
+}
+
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+/// Information about the original file, as stored in a cache entry.
+#[derive(Debug, Clone, PartialEq)]
+struct Source {
+    uri: String,
+    mtime: u64,
+    size: Option<u64>,
+    mime_type: Option<String>,
+}
+
+impl Source {
+    fn for_path(path: &Path) -> io::Result<Source> {
+        let meta = fs::metadata(path)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Ok(Source {
+            uri: file_uri(&absolute(path)?),
+            mtime,
+            size: Some(meta.len()),
+            mime_type: None,
+        })
+    }
+
+    /// Is a cache entry for the current version of this file?
+    fn is_described_by(&self, entry: &Source) -> bool {
+        // the size is optional, so only compare it when both sides know it
+        let size_matches = match (self.size, entry.size) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => true,
+        };
+
+        self.uri == entry.uri && self.mtime == entry.mtime && size_matches
+    }
+}
+
+fn read_entry(path: &Path) -> io::Result<(Source, RgbaImage)> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(
+        Transformations::EXPAND | Transformations::STRIP_16,
+    );
+    let mut reader = decoder.read_info().map_err(invalid_data)?;
+
+    let mut uri = None;
+    let mut mtime = None;
+    let mut size = None;
+    let mut mime_type = None;
+
+    for chunk in &reader.info().uncompressed_latin1_text {
+        match chunk.keyword.as_str() {
+            URI_KEY => uri = Some(chunk.text.clone()),
+            MTIME_KEY => mtime = chunk.text.trim().parse().ok(),
+            SIZE_KEY => size = chunk.text.trim().parse().ok(),
+            MIME_TYPE_KEY => mime_type = Some(chunk.text.clone()),
+            _ => {},
+        }
+    }
+
+    let source = match (uri, mtime) {
+        (Some(uri), Some(mtime)) => Source {
+            uri,
+            mtime,
+            size,
+            mime_type,
+        },
+        _ => {
+            return Err(invalid_data(
+                "The thumbnail is missing the required URI and MTime keys",
+            ))
+        },
+    };
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(invalid_data)?;
+    buffer.truncate(frame.buffer_size());
+
+    let image =
+        to_rgba_image(frame.width, frame.height, frame.color_type, buffer)
+            .ok_or_else(|| invalid_data("Unsupported thumbnail color type"))?;
+
+    Ok((source, image))
+}
+
+fn to_rgba_image(
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    pixels: Vec<u8>,
+) -> Option<RgbaImage> {
+    let rgba = match color_type {
+        ColorType::Rgba => pixels,
+        ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        ColorType::Grayscale => {
+            pixels.iter().flat_map(|&p| [p, p, p, 255]).collect()
+        },
+        // palettes are expanded by the decoder
+        ColorType::Indexed => return None,
+    };
+
+    RgbaImage::from_raw(width, height, rgba)
+}
+
+/// Atomically write a thumbnail and its metadata to disk.
+fn write_entry(
+    path: &Path,
+    source: &Source,
+    thumbnail: &RgbaImage,
+) -> io::Result<()> {
+    let parent = path.parent().expect("Entries are always in a directory");
+    create_private_dir(parent)?;
+
+    // Write to a temporary file first, so other programs never see a
+    // partially written thumbnail. The counter stops threads writing the same
+    // entry from clobbering each other's temporary file.
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = parent.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed),
+    ));
+
+    let result = write_png(&temp_path, source, thumbnail)
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+fn write_png(
+    path: &Path,
+    source: &Source,
+    thumbnail: &RgbaImage,
+) -> io::Result<()> {
+    let file = create_private_file(path)?;
+    let (width, height) = thumbnail.dimensions();
+
+    let mut encoder = Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut text = vec![
+        (URI_KEY, source.uri.clone()),
+        (MTIME_KEY, source.mtime.to_string()),
+    ];
+    if let Some(size) = source.size {
+        text.push((SIZE_KEY, size.to_string()));
+    }
+    if let Some(mime_type) = &source.mime_type {
+        text.push((MIME_TYPE_KEY, mime_type.clone()));
+    }
+    text.push((SOFTWARE_KEY, env!("CARGO_PKG_NAME").to_string()));
+
+    for (keyword, value) in text {
+        encoder
+            .add_text_chunk(keyword.to_string(), value)
+            .map_err(invalid_data)?;
+    }
+
+    let mut writer = encoder.write_header().map_err(invalid_data)?;
+    writer
+        .write_image_data(thumbnail.as_raw())
+        .map_err(invalid_data)?;
+    writer.finish().map_err(invalid_data)?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> io::Result<File> { File::create(path) }
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::{io::Read, sync::Arc, thread};
+
+    #[test]
+    fn file_name_for_a_uri() {
+        // calculated using "echo -n $uri | md5sum"
+        let uri = "file:///home/jens/photos/me.png";
+
+        let got = thumbnail_file_name(uri);
+
+        assert_eq!(got, "c6ee772d9e49320e97ec29a7eb5b1697.png");
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let got = file_uri(Path::new("/tmp/100% [draft]#1.png"));
+
+        assert_eq!(got, "file:///tmp/100%25%20%5Bdraft%5D%231.png");
+    }
+
+    #[test]
+    fn round_trip_file_uris() {
+        let path = Path::new("/tmp/100% [draft]#1.png");
+
+        let got = path_from_file_uri(&file_uri(path)).unwrap();
+
+        assert_eq!(got, path);
+        assert_eq!(
+            path_from_file_uri("file://localhost/etc/hosts").unwrap(),
+            Path::new("/etc/hosts")
+        );
+        assert!(path_from_file_uri("https://example.com/").is_none());
+        assert!(path_from_file_uri("file:///bad%zz").is_none());
+    }
+
+    struct Counting {
+        calls: AtomicUsize,
+        fail: bool,
+    }
+
+    impl Counting {
+        fn new(fail: bool) -> Self {
+            Counting {
+                calls: AtomicUsize::new(0),
+                fail,
+            }
+        }
+    }
+
+    impl ThumbnailProvider for Counting {
+        type Error = ThumbnailError;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _input: R,
+            desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            if self.fail {
+                Err(ThumbnailError::unsupported("text files"))
+            } else {
+                Ok(RgbaImage::from_pixel(
+                    desired_dimensions.width,
+                    desired_dimensions.height / 2,
+                    Rgba([1, 2, 3, 4]),
+                ))
+            }
+        }
+    }
+
+    #[test]
+    fn generate_then_reuse_a_thumbnail() {
+        let temp = tempfile::tempdir().unwrap();
+        let original = temp.path().join("original.txt");
+        fs::write(&original, "Hello, World!").unwrap();
+        let cache = ThumbnailCache::new(temp.path().join("cache"), "test");
+        let provider = Counting::new(false);
+
+        let first = cache
+            .get_or_generate(
+                &provider,
+                &original,
+                Some("text/plain"),
+                ThumbnailSize::Normal,
+            )
+            .unwrap();
+        let second = cache
+            .get_or_generate(&provider, &original, None, ThumbnailSize::Normal)
+            .unwrap();
+
+        assert_eq!(first.dimensions(), (128, 64));
+        assert_eq!(first, second);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        let uri = file_uri(&original);
+        let entry_path = cache.thumbnail_path(&uri, ThumbnailSize::Normal);
+        assert!(entry_path.starts_with(temp.path().join("cache/normal")));
+        let (entry, _) = read_entry(&entry_path).unwrap();
+        assert_eq!(entry.uri, uri);
+        assert_eq!(entry.size, Some(13));
+        assert_eq!(entry.mime_type.as_deref(), Some("text/plain"));
+    }
+
+    #[test]
+    fn concurrent_writes_to_the_same_entry() {
+        let temp = tempfile::tempdir().unwrap();
+        let original = temp.path().join("original.txt");
+        fs::write(&original, "Hello, World!").unwrap();
+        let cache =
+            Arc::new(ThumbnailCache::new(temp.path().join("cache"), "test"));
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let cache = Arc::clone(&cache);
+                let original = original.clone();
+                thread::spawn(move || {
+                    let thumbnail =
+                        RgbaImage::from_pixel(64, 64, Rgba([i, i, i, 255]));
+                    for _ in 0..10 {
+                        cache
+                            .store(
+                                &original,
+                                None,
+                                ThumbnailSize::Normal,
+                                &thumbnail,
+                            )
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let entry_path =
+            cache.thumbnail_path(&file_uri(&original), ThumbnailSize::Normal);
+        let (_, thumbnail) = read_entry(&entry_path).unwrap();
+        assert_eq!(thumbnail.dimensions(), (64, 64));
+        let leftovers =
+            fs::read_dir(entry_path.parent().unwrap()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn modified_files_are_regenerated() {
+        let temp = tempfile::tempdir().unwrap();
+        let original = temp.path().join("original.txt");
+        fs::write(&original, "Hello, World!").unwrap();
+        let cache = ThumbnailCache::new(temp.path(), "test");
+        cache
+            .store(&original, None, ThumbnailSize::Large, &RgbaImage::new(1, 1))
+            .unwrap();
+        assert!(cache
+            .lookup(&original, ThumbnailSize::Large)
+            .unwrap()
+            .is_some());
+
+        fs::write(&original, "Something longer").unwrap();
+
+        assert!(cache
+            .lookup(&original, ThumbnailSize::Large)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn failures_are_recorded() {
+        let temp = tempfile::tempdir().unwrap();
+        let original = temp.path().join("original.txt");
+        fs::write(&original, "Hello, World!").unwrap();
+        let cache = ThumbnailCache::new(temp.path(), "test-1.0");
+        let provider = Counting::new(true);
+
+        let first = cache.get_or_generate(
+            &provider,
+            &original,
+            None,
+            ThumbnailSize::Normal,
+        );
+        let second = cache.get_or_generate(
+            &provider,
+            &original,
+            None,
+            ThumbnailSize::Normal,
+        );
+
+        assert!(matches!(first, Err(CacheError::Provider(_))));
+        assert!(matches!(second, Err(CacheError::PreviouslyFailed)));
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert!(temp.path().join("fail/test-1.0").is_dir());
+
+        cache.remove(&original).unwrap();
+        assert!(!cache.has_failed(&original).unwrap());
+    }
+
+    struct TimesOut;
+
+    impl ThumbnailProvider for TimesOut {
+        type Error = ThumbnailError;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            Err(ThumbnailError::TimedOut)
+        }
+    }
+
+    #[test]
+    fn transient_failures_are_not_recorded() {
+        let temp = tempfile::tempdir().unwrap();
+        let original = temp.path().join("original.txt");
+        fs::write(&original, "Hello, World!").unwrap();
+        let cache = ThumbnailCache::new(temp.path(), "test");
+
+        let got = cache.get_or_generate(
+            &TimesOut,
+            &original,
+            None,
+            ThumbnailSize::Normal,
+        );
+
+        assert!(matches!(
+            got,
+            Err(CacheError::Provider(ThumbnailError::TimedOut))
+        ));
+        assert!(!cache.has_failed(&original).unwrap());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{cache::ThumbnailSize, Limits, ThumbnailError};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Extra information about a thumbnail request, passed to a
+/// [`ThumbnailProvider`][crate::ThumbnailProvider] alongside its input.
+///
+/// Every field is optional, so providers should treat this as a hint and
+/// fall back to looking at the input itself.
+///
+/// # Examples
+///
+/// ```rust
+/// use thumbnails::{cache::ThumbnailSize, Quality, ThumbnailContext};
+///
+/// let context = ThumbnailContext::new()
+///     .with_path("holiday.jpg")
+///     .with_mime_type("image/jpeg")
+///     .with_size_class(ThumbnailSize::Large)
+///     .with_quality(Quality::Fast);
+///
+/// assert_eq!(context.file_name(), Some("holiday.jpg"));
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ThumbnailContext {
+    /// Where the input came from.
+    pub path: Option<PathBuf>,
+    /// The input's MIME type, as declared by the caller or detected from its
+    /// contents.
+    pub mime_type: Option<String>,
+    /// The standard size bucket the thumbnail will be displayed at.
+    pub size_class: Option<ThumbnailSize>,
+    /// When the input was last modified.
+    pub modified: Option<SystemTime>,
+    pub quality: Quality,
+    /// Lets the caller abort thumbnail generation.
+    pub cancellation: CancellationToken,
+    /// When the caller will stop waiting for the thumbnail.
+    pub deadline: Option<Instant>,
+    /// How many resources the provider may use.
+    pub limits: Limits,
+}
+
+impl ThumbnailContext {
+    pub fn new() -> Self { ThumbnailContext::default() }
+
+    pub fn with_path<P: Into<PathBuf>>(self, path: P) -> Self {
+        ThumbnailContext {
+            path: Some(path.into()),
+            ..self
+        }
+    }
+
+    pub fn with_mime_type<S: Into<String>>(self, mime_type: S) -> Self {
+        ThumbnailContext {
+            mime_type: Some(mime_type.into()),
+            ..self
+        }
+    }
+
+    pub fn with_size_class(self, size_class: ThumbnailSize) -> Self {
+        ThumbnailContext {
+            size_class: Some(size_class),
+            ..self
+        }
+    }
+
+    pub fn with_modified(self, modified: SystemTime) -> Self {
+        ThumbnailContext {
+            modified: Some(modified),
+            ..self
+        }
+    }
+
+    pub fn with_quality(self, quality: Quality) -> Self {
+        ThumbnailContext { quality, ..self }
+    }
+
+    pub fn with_cancellation(self, cancellation: CancellationToken) -> Self {
+        ThumbnailContext {
+            cancellation,
+            ..self
+        }
+    }
+
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        ThumbnailContext {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    pub fn with_limits(self, limits: Limits) -> Self {
+        ThumbnailContext { limits, ..self }
+    }
+
+    /// Give up if the thumbnail isn't generated within `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Check whether the caller still wants this thumbnail.
+    ///
+    /// Long-running providers should call this regularly (e.g. between rows,
+    /// frames, or pages) and bail out with the error when it fails.
+    pub fn check(&self) -> Result<(), ThumbnailError> {
+        if self.cancellation.is_cancelled() {
+            Err(ThumbnailError::Cancelled)
+        } else if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            Err(ThumbnailError::TimedOut)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> { self.path.as_deref() }
+
+    /// The final component of [`ThumbnailContext::path`], if it is valid
+    /// UTF-8.
+    pub fn file_name(&self) -> Option<&str> {
+        self.path()
+            .and_then(|p| p.file_name())
+            .and_then(|name| name.to_str())
+    }
+}
+
+/// Whether the caller would prefer a thumbnail quickly or a thumbnail which
+/// looks good.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Quality {
+    /// Use the quickest method available, such as an embedded preview or a
+    /// low-quality resampling filter.
+    Fast,
+    /// Take the time to generate the best possible thumbnail.
+    #[default]
+    High,
+}
+
+/// A flag which can be used to ask a provider to stop what it is doing.
+///
+/// Cloning the token gives another handle to the same flag.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self { CancellationToken::default() }
+
+    pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst); }
+
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A reader which fails with [`ThumbnailError::Cancelled`] or
+/// [`ThumbnailError::TimedOut`] (wrapped in an [`io::Error`]) as soon as the
+/// caller stops waiting for a thumbnail.
+///
+/// This lets decoders which read their input incrementally be interrupted
+/// without needing to know about [`ThumbnailContext`].
+#[derive(Debug)]
+pub struct Interruptible<'a, R> {
+    inner: R,
+    context: &'a ThumbnailContext,
+}
+
+impl<'a, R> Interruptible<'a, R> {
+    pub fn new(inner: R, context: &'a ThumbnailContext) -> Self {
+        Interruptible { inner, context }
+    }
+
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<'a, R: Read> Read for Interruptible<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.context.check().map_err(io::Error::other)?;
+        self.inner.read(buf)
+    }
+}
+
+impl<'a, R: Seek> Seek for Interruptible<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_empty_context_knows_nothing() {
+        let context = ThumbnailContext::new();
+
+        assert!(context.path().is_none());
+        assert!(context.file_name().is_none());
+        assert!(context.mime_type.is_none());
+        assert_eq!(context.quality, Quality::High);
+    }
+
+    #[test]
+    fn the_file_name_comes_from_the_path() {
+        let context = ThumbnailContext::new().with_path("/tmp/photos/cat.png");
+
+        assert_eq!(context.file_name(), Some("cat.png"));
+    }
+
+    #[test]
+    fn cancelling_a_token_affects_its_clones() {
+        let token = CancellationToken::new();
+        let context = ThumbnailContext::new().with_cancellation(token.clone());
+        assert!(context.check().is_ok());
+
+        token.cancel();
+
+        assert!(matches!(context.check(), Err(ThumbnailError::Cancelled)));
+    }
+
+    #[test]
+    fn passing_the_deadline_times_out() {
+        let context = ThumbnailContext::new().with_deadline(Instant::now());
+
+        assert!(matches!(context.check(), Err(ThumbnailError::TimedOut)));
+    }
+
+    #[test]
+    fn interrupt_a_reader_part_way_through() {
+        let token = CancellationToken::new();
+        let context = ThumbnailContext::new().with_cancellation(token.clone());
+        let mut reader = Interruptible::new(&b"Hello, World!"[..], &context);
+
+        let mut buffer = [0; 5];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"Hello");
+        token.cancel();
+        let err = reader.read(&mut buffer).unwrap_err();
+
+        assert!(matches!(
+            ThumbnailError::from(err),
+            ThumbnailError::Cancelled
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! Converting images into device-independent bitmaps (DIBs), the format
+//! Windows uses for `HBITMAP`s.
+//!
+//! Nothing in this module depends on Windows, so the conversion can be tested
+//! on any platform.
+
+use crate::ThumbnailError;
+use image::{GenericImageView, Pixel};
+use std::convert::TryFrom;
+
+/// The `BI_RGB` compression mode, meaning the pixels are uncompressed.
+pub const BI_RGB: u32 = 0;
+
+/// A `BITMAPINFOHEADER` from `wingdi.h`.
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BitmapInfoHeader {
+    pub biSize: u32,
+    pub biWidth: i32,
+    pub biHeight: i32,
+    pub biPlanes: u16,
+    pub biBitCount: u16,
+    pub biCompression: u32,
+    pub biSizeImage: u32,
+    pub biXPelsPerMeter: i32,
+    pub biYPelsPerMeter: i32,
+    pub biClrUsed: u32,
+    pub biClrImportant: u32,
+}
+
+impl BitmapInfoHeader {
+    pub const SIZE: usize = std::mem::size_of::<BitmapInfoHeader>();
+
+    /// The header for a top-down, uncompressed, 32 bits-per-pixel bitmap.
+    ///
+    /// This fails if the bitmap would be too large for the header's fields.
+    pub fn top_down_32bpp(
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ThumbnailError> {
+        let too_large = || {
+            ThumbnailError::too_large(format!(
+                "A {}x{} bitmap is too large",
+                width, height
+            ))
+        };
+
+        let bi_width = i32::try_from(width).map_err(|_| too_large())?;
+        let bi_height = i32::try_from(height).map_err(|_| too_large())?;
+        let size = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(too_large)?;
+
+        Ok(BitmapInfoHeader {
+            biSize: BitmapInfoHeader::SIZE as u32,
+            biWidth: bi_width,
+            // a negative height means the first row is the top of the image
+            biHeight: -bi_height,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB,
+            biSizeImage: size,
+            ..Default::default()
+        })
+    }
+
+    /// The header's in-memory representation, as it would be written to a
+    /// `*.bmp` file.
+    pub fn to_bytes(&self) -> [u8; BitmapInfoHeader::SIZE] {
+        let mut bytes = [0; BitmapInfoHeader::SIZE];
+        let fields: [&[u8]; 11] = [
+            &self.biSize.to_le_bytes(),
+            &self.biWidth.to_le_bytes(),
+            &self.biHeight.to_le_bytes(),
+            &self.biPlanes.to_le_bytes(),
+            &self.biBitCount.to_le_bytes(),
+            &self.biCompression.to_le_bytes(),
+            &self.biSizeImage.to_le_bytes(),
+            &self.biXPelsPerMeter.to_le_bytes(),
+            &self.biYPelsPerMeter.to_le_bytes(),
+            &self.biClrUsed.to_le_bytes(),
+            &self.biClrImportant.to_le_bytes(),
+        ];
+
+        let mut offset = 0;
+        for field in fields.iter() {
+            bytes[offset..offset + field.len()].copy_from_slice(field);
+            offset += field.len();
+        }
+
+        bytes
+    }
+}
+
+/// How the alpha channel in a [`Dib`] should be interpreted, mirroring
+/// `WTS_ALPHATYPE` from `thumbcache.h`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(i32)]
+pub enum AlphaType {
+    Unknown = 0,
+    /// Every pixel is fully opaque, so the alpha channel can be ignored.
+    Rgb = 1,
+    /// The image has transparent pixels and uses premultiplied alpha.
+    Argb = 2,
+}
+
+/// A top-down, 32 bits-per-pixel device-independent bitmap using
+/// premultiplied BGRA pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dib {
+    header: BitmapInfoHeader,
+    pixels: Vec<u8>,
+    alpha_type: AlphaType,
+}
+
+impl Dib {
+    /// Convert an image into a [`Dib`], failing if it is too large to be
+    /// described by a [`BitmapInfoHeader`].
+    pub fn from_image<I>(image: &I) -> Result<Self, ThumbnailError>
+    where
+        I: GenericImageView,
+        I::Pixel: Pixel<Subpixel = u8>,
+    {
+        let (width, height) = image.dimensions();
+        let header = BitmapInfoHeader::top_down_32bpp(width, height)?;
+        let mut pixels =
+            Vec::with_capacity(width as usize * height as usize * 4);
+        let mut is_opaque = true;
+
+        for (_, _, pixel) in image.pixels() {
+            let [r, g, b, a] = pixel.to_rgba().0;
+            is_opaque &= a == u8::MAX;
+
+            pixels.extend_from_slice(&[
+                premultiply(b, a),
+                premultiply(g, a),
+                premultiply(r, a),
+                a,
+            ]);
+        }
+
+        Ok(Dib {
+            header,
+            pixels,
+            alpha_type: if is_opaque {
+                AlphaType::Rgb
+            } else {
+                AlphaType::Argb
+            },
+        })
+    }
+
+    pub fn header(&self) -> &BitmapInfoHeader { &self.header }
+
+    /// The pixel data, stored as rows of BGRA pixels starting at the top of
+    /// the image.
+    pub fn pixels(&self) -> &[u8] { &self.pixels }
+
+    pub fn alpha_type(&self) -> AlphaType { self.alpha_type }
+
+    pub fn width(&self) -> u32 { self.header.biWidth as u32 }
+
+    pub fn height(&self) -> u32 { self.header.biHeight.unsigned_abs() }
+}
+
+fn premultiply(channel: u8, alpha: u8) -> u8 {
+    // (c * a) / 255, rounded to the nearest integer
+    ((u16::from(channel) * u16::from(alpha) + 127) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+    #[test]
+    fn header_layout_matches_wingdi() {
+        let header = BitmapInfoHeader::top_down_32bpp(2, 3).unwrap();
+
+        let got = header.to_bytes();
+
+        #[rustfmt::skip]
+        let should_be = [
+            40, 0, 0, 0,          // biSize
+            2, 0, 0, 0,           // biWidth
+            0xfd, 0xff, 0xff, 0xff, // biHeight = -3
+            1, 0,                 // biPlanes
+            32, 0,                // biBitCount
+            0, 0, 0, 0,           // biCompression = BI_RGB
+            24, 0, 0, 0,          // biSizeImage
+            0, 0, 0, 0,           // biXPelsPerMeter
+            0, 0, 0, 0,           // biYPelsPerMeter
+            0, 0, 0, 0,           // biClrUsed
+            0, 0, 0, 0,           // biClrImportant
+        ];
+        assert_eq!(got, should_be);
+        assert_eq!(BitmapInfoHeader::SIZE, 40);
+    }
+
+    #[test]
+    fn oversized_bitmaps_are_rejected() {
+        let inputs = [(u32::MAX, 1), (1, 1 << 31), (1 << 16, 1 << 14)];
+
+        for &(width, height) in &inputs {
+            let err =
+                BitmapInfoHeader::top_down_32bpp(width, height).unwrap_err();
+
+            assert!(
+                matches!(err, ThumbnailError::TooLarge(_)),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn opaque_pixels_are_swizzled_to_bgra() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| {
+            Rgba([x as u8, y as u8, 10 + x as u8 + 2 * y as u8, 255])
+        });
+
+        let dib = Dib::from_image(&image).unwrap();
+
+        #[rustfmt::skip]
+        let should_be = [
+            // top row
+            10, 0, 0, 255,   11, 0, 1, 255,
+            // bottom row
+            12, 1, 0, 255,   13, 1, 1, 255,
+        ];
+        assert_eq!(dib.pixels(), &should_be[..]);
+        assert_eq!(dib.alpha_type(), AlphaType::Rgb);
+        assert_eq!((dib.width(), dib.height()), (2, 2));
+    }
+
+    #[test]
+    fn transparent_pixels_are_premultiplied() {
+        let image = RgbaImage::from_pixel(1, 1, Rgba([255, 128, 10, 128]));
+
+        let dib = Dib::from_image(&image).unwrap();
+
+        assert_eq!(dib.pixels(), &[5, 64, 128, 128]);
+        assert_eq!(dib.alpha_type(), AlphaType::Argb);
+    }
+
+    #[test]
+    fn other_pixel_types_are_converted() {
+        let image = GrayImage::from_pixel(1, 1, Luma([42]));
+
+        let dib = Dib::from_image(&image).unwrap();
+
+        assert_eq!(dib.pixels(), &[42, 42, 42, 255]);
+    }
+}
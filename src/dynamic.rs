@@ -0,0 +1,166 @@
+//! Object-safe versions of the [`ThumbnailProvider`] trait.
+
+use crate::{Dimensions, InputSources, ThumbnailContext, ThumbnailProvider};
+use image::{GenericImageView, Pixel, RgbaImage};
+use std::{
+    error::Error,
+    io::{Read, Seek},
+};
+
+/// An object-safe counterpart to [`ThumbnailProvider`].
+///
+/// This trait is automatically implemented for every [`ThumbnailProvider`]
+/// whose thumbnails use 8-bit channels, letting you store providers which are
+/// only known at runtime as a `Box<dyn DynThumbnailProvider>`.
+pub trait DynThumbnailProvider: Send + Sync {
+    fn get_thumbnail_dyn(
+        &self,
+        input: &mut dyn Read,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>>;
+
+    /// The object-safe version of
+    /// [`ThumbnailProvider::get_thumbnail_seekable()`].
+    fn get_thumbnail_dyn_seekable(
+        &self,
+        input: &mut dyn ReadSeek,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>>;
+
+    /// The object-safe version of
+    /// [`ThumbnailProvider::get_thumbnail_with_context()`].
+    fn get_thumbnail_dyn_with_context(
+        &self,
+        input: &mut dyn ReadSeek,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>>;
+
+    /// See [`ThumbnailProvider::input_sources()`].
+    fn input_sources_dyn(&self) -> InputSources;
+}
+
+/// A reader which supports random access, usable as a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<R: Read + Seek + ?Sized> ReadSeek for R {}
+
+impl<P> DynThumbnailProvider for P
+where
+    P: ThumbnailProvider,
+    <P::Thumbnail as GenericImageView>::Pixel: Pixel<Subpixel = u8>,
+{
+    fn get_thumbnail_dyn(
+        &self,
+        input: &mut dyn Read,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>> {
+        let thumbnail = self.get_thumbnail(input, desired_dimensions)?;
+        Ok(to_rgba(&thumbnail))
+    }
+
+    fn get_thumbnail_dyn_seekable(
+        &self,
+        input: &mut dyn ReadSeek,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>> {
+        let thumbnail =
+            self.get_thumbnail_seekable(input, desired_dimensions)?;
+        Ok(to_rgba(&thumbnail))
+    }
+
+    fn get_thumbnail_dyn_with_context(
+        &self,
+        input: &mut dyn ReadSeek,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, Box<dyn Error + Send + Sync>> {
+        let thumbnail = self.get_thumbnail_with_context(
+            input,
+            context,
+            desired_dimensions,
+        )?;
+        Ok(to_rgba(&thumbnail))
+    }
+
+    fn input_sources_dyn(&self) -> InputSources { self.input_sources() }
+}
+
+/// Copy an arbitrary image into a [`RgbaImage`].
+pub(crate) fn to_rgba<I>(image: &I) -> RgbaImage
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    let (width, height) = image.dimensions();
+
+    RgbaImage::from_fn(width, height, |x, y| image.get_pixel(x, y).to_rgba())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgba};
+    use std::io;
+
+    struct Solid;
+
+    impl ThumbnailProvider for Solid {
+        type Error = io::Error;
+        type Thumbnail = GrayImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            mut input: R,
+            desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error>
+        where
+            R: Read,
+        {
+            let mut buffer = [0];
+            input.read_exact(&mut buffer)?;
+
+            Ok(GrayImage::from_pixel(
+                desired_dimensions.width,
+                desired_dimensions.height,
+                Luma(buffer),
+            ))
+        }
+    }
+
+    struct AlwaysFails;
+
+    impl ThumbnailProvider for AlwaysFails {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            _input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error>
+        where
+            R: Read,
+        {
+            Err(io::Error::other("Oops"))
+        }
+    }
+
+    #[test]
+    fn store_heterogeneous_providers() {
+        let providers: Vec<Box<dyn DynThumbnailProvider>> =
+            vec![Box::new(AlwaysFails), Box::new(Solid)];
+        let dims = Dimensions::new(2, 3);
+
+        let err = providers[0]
+            .get_thumbnail_dyn(&mut &[42_u8][..], dims)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Oops");
+
+        let got = providers[1]
+            .get_thumbnail_dyn(&mut &[42_u8][..], dims)
+            .unwrap();
+        assert_eq!(got.dimensions(), (2, 3));
+        assert!(got.pixels().all(|p| *p == Rgba([42, 42, 42, 255])));
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{Limit, RegistryError};
+use image::ImageError;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+};
+
+/// The reasons a thumbnail couldn't be generated.
+///
+/// Every provider in this crate uses this error type, and it knows how it
+/// should be reported to the shell (see
+/// [`crate::arch::windows::get_hresult()`]), as a command-line exit code, and
+/// whether to record it in the freedesktop.org fail cache.
+#[derive(Debug)]
+pub enum ThumbnailError {
+    /// The file isn't in a format the provider understands.
+    Unsupported(String),
+    /// The file looks like the right format, but couldn't be decoded.
+    Corrupt(Box<dyn Error + Send + Sync>),
+    /// The file is too large to generate a thumbnail for.
+    TooLarge(String),
+    /// One of the [`Limits`][crate::Limits] was hit.
+    LimitExceeded(Limit),
+    /// Generating the thumbnail took too long.
+    TimedOut,
+    /// Thumbnail generation was cancelled by the caller.
+    Cancelled,
+    /// Unable to read the input.
+    Io(io::Error),
+    /// Something went wrong which isn't the file's fault (e.g. a bug).
+    Internal(Box<dyn Error + Send + Sync>),
+}
+
+impl ThumbnailError {
+    pub fn unsupported<S: Into<String>>(reason: S) -> Self {
+        ThumbnailError::Unsupported(reason.into())
+    }
+
+    pub fn corrupt<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        ThumbnailError::Corrupt(error.into())
+    }
+
+    pub fn too_large<S: Into<String>>(reason: S) -> Self {
+        ThumbnailError::TooLarge(reason.into())
+    }
+
+    pub fn internal<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        ThumbnailError::Internal(error.into())
+    }
+
+    /// Look through an error and its sources for a [`ThumbnailError`].
+    pub fn find<'a>(
+        error: &'a (dyn Error + 'static),
+    ) -> Option<&'a ThumbnailError> {
+        let mut error = Some(error);
+
+        while let Some(e) = error {
+            if let Some(thumbnail_error) = e.downcast_ref::<ThumbnailError>() {
+                return Some(thumbnail_error);
+            }
+
+            // io::Error::source() skips over the error it wraps
+            error = match e
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+            {
+                Some(inner) => Some(inner),
+                None => e.source(),
+            };
+        }
+
+        None
+    }
+
+    /// The exit code used by the `thumbnails` command-line program.
+    ///
+    /// | Error                             | Exit code |
+    /// | --------------------------------- | --------- |
+    /// | [`ThumbnailError::Io`]            | 1         |
+    /// | [`ThumbnailError::Unsupported`]   | 3         |
+    /// | [`ThumbnailError::Corrupt`]       | 4         |
+    /// | [`ThumbnailError::TooLarge`]      | 5         |
+    /// | [`ThumbnailError::LimitExceeded`] | 5         |
+    /// | [`ThumbnailError::TimedOut`]      | 6         |
+    /// | [`ThumbnailError::Cancelled`]     | 7         |
+    /// | [`ThumbnailError::Internal`]      | 8         |
+    ///
+    /// An exit code of `2` is reserved for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ThumbnailError::Io(_) => 1,
+            ThumbnailError::Unsupported(_) => 3,
+            ThumbnailError::Corrupt(_) => 4,
+            ThumbnailError::TooLarge(_) | ThumbnailError::LimitExceeded(_) => 5,
+            ThumbnailError::TimedOut => 6,
+            ThumbnailError::Cancelled => 7,
+            ThumbnailError::Internal(_) => 8,
+        }
+    }
+
+    /// Will trying again with the same file always give the same result?
+    ///
+    /// Only permanent failures are recorded in the freedesktop.org fail
+    /// cache, so a thumbnail which timed out or hit an I/O error will be
+    /// retried next time.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            ThumbnailError::Unsupported(_)
+            | ThumbnailError::Corrupt(_)
+            | ThumbnailError::TooLarge(_)
+            | ThumbnailError::LimitExceeded(_) => true,
+            ThumbnailError::TimedOut
+            | ThumbnailError::Cancelled
+            | ThumbnailError::Io(_)
+            | ThumbnailError::Internal(_) => false,
+        }
+    }
+}
+
+impl Display for ThumbnailError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumbnailError::Unsupported(reason) => {
+                write!(f, "The file isn't supported: {}", reason)
+            },
+            ThumbnailError::Corrupt(_) => write!(f, "The file is corrupt"),
+            ThumbnailError::TooLarge(reason) => {
+                write!(f, "The file is too large: {}", reason)
+            },
+            ThumbnailError::LimitExceeded(limit) => {
+                write!(f, "Exceeded the limit of {}", limit)
+            },
+            ThumbnailError::TimedOut => {
+                write!(f, "Timed out while generating the thumbnail")
+            },
+            ThumbnailError::Cancelled => {
+                write!(f, "Thumbnail generation was cancelled")
+            },
+            ThumbnailError::Io(_) => write!(f, "Unable to read the input"),
+            ThumbnailError::Internal(_) => {
+                write!(f, "An internal error occurred")
+            },
+        }
+    }
+}
+
+impl Error for ThumbnailError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ThumbnailError::Corrupt(e) | ThumbnailError::Internal(e) => {
+                Some(&**e)
+            },
+            ThumbnailError::Io(e) => Some(e),
+            ThumbnailError::Unsupported(_)
+            | ThumbnailError::TooLarge(_)
+            | ThumbnailError::LimitExceeded(_)
+            | ThumbnailError::TimedOut
+            | ThumbnailError::Cancelled => None,
+        }
+    }
+}
+
+impl From<io::Error> for ThumbnailError {
+    /// Wrap an [`io::Error`], unwrapping it instead if it is a
+    /// [`ThumbnailError`] which was smuggled through a reader (e.g. by
+    /// [`Interruptible`][crate::Interruptible]).
+    fn from(e: io::Error) -> Self {
+        let is_thumbnail_error = e
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ThumbnailError>());
+        if !is_thumbnail_error {
+            return ThumbnailError::Io(e);
+        }
+
+        let inner = e.into_inner().expect("checked above");
+        *inner.downcast::<ThumbnailError>().expect("checked above")
+    }
+}
+
+impl From<ImageError> for ThumbnailError {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::Decoding(_) => ThumbnailError::corrupt(e),
+            ImageError::Limits(_) => ThumbnailError::too_large(e.to_string()),
+            ImageError::Unsupported(_) => {
+                ThumbnailError::unsupported(e.to_string())
+            },
+            ImageError::IoError(e) => ThumbnailError::from(e),
+            ImageError::Encoding(_) | ImageError::Parameter(_) => {
+                ThumbnailError::internal(e)
+            },
+        }
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ThumbnailError {
+    /// Recover a [`ThumbnailError`] from a type-erased error, treating any
+    /// unknown errors as [`ThumbnailError::Internal`].
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        let e = match e.downcast::<ThumbnailError>() {
+            Ok(thumbnail_error) => return *thumbnail_error,
+            Err(e) => e,
+        };
+
+        match e.downcast::<io::Error>() {
+            Ok(io_error) => ThumbnailError::from(*io_error),
+            Err(e) => ThumbnailError::Internal(e),
+        }
+    }
+}
+
+impl From<RegistryError> for ThumbnailError {
+    /// Convert a [`RegistryError`], using the error from the highest priority
+    /// provider when they all failed.
+    fn from(e: RegistryError) -> Self {
+        match e {
+            RegistryError::NoProvider => {
+                ThumbnailError::unsupported("no provider is registered")
+            },
+            RegistryError::Io(e) => ThumbnailError::from(e),
+            RegistryError::AllFailed(errors) => match errors.into_iter().next()
+            {
+                Some(first) => ThumbnailError::from(first),
+                None => {
+                    ThumbnailError::unsupported("no provider is registered")
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_a_thumbnail_error_in_the_chain() {
+        let error =
+            RegistryError::AllFailed(vec![Box::new(ThumbnailError::TimedOut)]);
+
+        let got = ThumbnailError::find(&error);
+
+        assert!(matches!(got, Some(ThumbnailError::TimedOut)));
+        assert!(ThumbnailError::find(&io::Error::other("Oops")).is_none());
+    }
+
+    #[test]
+    fn recover_from_a_boxed_error() {
+        let boxed: Box<dyn Error + Send + Sync> =
+            Box::new(ThumbnailError::Cancelled);
+        assert!(matches!(
+            ThumbnailError::from(boxed),
+            ThumbnailError::Cancelled
+        ));
+
+        let boxed: Box<dyn Error + Send + Sync> =
+            Box::new(io::Error::other(""));
+        assert!(matches!(ThumbnailError::from(boxed), ThumbnailError::Io(_)));
+
+        let boxed: Box<dyn Error + Send + Sync> = "Oops".into();
+        assert!(matches!(
+            ThumbnailError::from(boxed),
+            ThumbnailError::Internal(_)
+        ));
+    }
+
+    #[test]
+    fn unwrap_thumbnail_errors_inside_io_errors() {
+        let wrapped = io::Error::other(ThumbnailError::TimedOut);
+
+        assert!(ThumbnailError::find(&wrapped).is_some());
+        assert!(matches!(
+            ThumbnailError::from(wrapped),
+            ThumbnailError::TimedOut
+        ));
+    }
+
+    #[test]
+    fn the_highest_priority_provider_wins() {
+        let error = RegistryError::AllFailed(vec![
+            Box::new(ThumbnailError::corrupt("bad header")),
+            Box::new(ThumbnailError::unsupported("not a PNG")),
+        ]);
+
+        let got = ThumbnailError::from(error);
+
+        assert!(matches!(got, ThumbnailError::Corrupt(_)));
+        assert_eq!(got.exit_code(), 4);
+    }
+
+    #[test]
+    fn only_permanent_failures_are_recorded() {
+        assert!(ThumbnailError::unsupported("").is_permanent());
+        assert!(ThumbnailError::corrupt("").is_permanent());
+        assert!(ThumbnailError::too_large("").is_permanent());
+        assert!(!ThumbnailError::TimedOut.is_permanent());
+        assert!(!ThumbnailError::Cancelled.is_permanent());
+        assert!(!ThumbnailError::Io(io::Error::other("")).is_permanent());
+        assert!(!ThumbnailError::internal("").is_permanent());
+    }
+}
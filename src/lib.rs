@@ -1,14 +1,160 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 pub mod arch;
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub mod asynchronous;
+pub mod cache;
+mod context;
+pub mod dib;
+mod dynamic;
+mod error;
+mod limits;
+pub mod providers;
+mod registry;
+mod resize;
+mod sniff;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncThumbnailProvider, BlockOn, SpawnBlocking};
+pub use context::{
+    CancellationToken, Interruptible, Quality, ThumbnailContext,
+};
+pub use dynamic::{DynThumbnailProvider, ReadSeek};
+pub use error::ThumbnailError;
+pub use limits::{Limit, LimitedReader, Limits};
+pub use registry::{
+    Hint, Input, Matcher, ProviderRegistry, Registration, RegistryError,
+};
+pub use resize::resize;
+pub use sniff::{sniff, SniffingReader, DEFAULT_PEEK_SIZE};
 
 use image::GenericImageView;
-use std::io::Read;
+use std::io::{Read, Seek};
 
+/// The size a thumbnail should be, and how the original image should be
+/// fitted into it.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Dimensions {
     pub width: u32,
     pub height: u32,
+    pub fit: Fit,
+}
+
+impl Dimensions {
+    /// Create a new [`Dimensions`] using the default [`Fit`].
+    pub const fn new(width: u32, height: u32) -> Self {
+        Dimensions {
+            width,
+            height,
+            fit: Fit::Max,
+        }
+    }
+
+    /// Dimensions for a thumbnail which fits within a `size x size` square.
+    pub const fn square(size: u32) -> Self { Dimensions::new(size, size) }
+
+    pub const fn with_fit(self, fit: Fit) -> Self { Dimensions { fit, ..self } }
+
+    /// Calculate the size an image with the provided dimensions should be
+    /// scaled to before any padding or cropping is applied.
+    ///
+    /// With [`Fit::Cover`] this can be far larger than the thumbnail, so
+    /// [`resize()`] crops the image before scaling it rather than allocating
+    /// an image this size.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use thumbnails::{Dimensions, Fit};
+    ///
+    /// let dims = Dimensions::new(100, 100);
+    ///
+    /// assert_eq!(dims.with_fit(Fit::Max).scaled_size(400, 200), (100, 50));
+    /// assert_eq!(dims.with_fit(Fit::Max).scaled_size(40, 20), (40, 20));
+    /// assert_eq!(dims.with_fit(Fit::Contain).scaled_size(40, 20), (100, 50));
+    /// assert_eq!(dims.with_fit(Fit::Cover).scaled_size(400, 200), (200, 100));
+    /// assert_eq!(dims.with_fit(Fit::Stretch).scaled_size(400, 200), (100, 100));
+    /// ```
+    pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if width == 0 || height == 0 {
+            return (width, height);
+        }
+
+        let horizontal = f64::from(self.width) / f64::from(width);
+        let vertical = f64::from(self.height) / f64::from(height);
+
+        let scale = match self.fit {
+            Fit::Max => horizontal.min(vertical).min(1.0),
+            Fit::Contain => horizontal.min(vertical),
+            Fit::Cover => horizontal.max(vertical),
+            Fit::Stretch => return (self.width, self.height),
+        };
+
+        let scale = |original: u32| {
+            ((f64::from(original) * scale).round() as u32).max(1)
+        };
+
+        (scale(width), scale(height))
+    }
+
+    /// Is an image with the provided dimensions big enough to make this
+    /// thumbnail without being scaled up?
+    ///
+    /// This is for picking between previews of a larger image, so
+    /// [`Fit::Max`] is treated like [`Fit::Contain`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use thumbnails::{Dimensions, Fit};
+    ///
+    /// let dims = Dimensions::new(100, 100);
+    ///
+    /// assert!(dims.is_satisfied_by(160, 120));
+    /// assert!(!dims.is_satisfied_by(80, 60));
+    /// assert!(!dims.with_fit(Fit::Max).is_satisfied_by(40, 20));
+    /// assert!(dims.with_fit(Fit::Cover).is_satisfied_by(100, 400));
+    /// ```
+    pub fn is_satisfied_by(&self, width: u32, height: u32) -> bool {
+        let (scaled_width, scaled_height) = self.scaled_to_fill(width, height);
+        scaled_width <= width && scaled_height <= height
+    }
+
+    /// Like [`Dimensions::scaled_size()`], except images are always scaled
+    /// to fill the box.
+    ///
+    /// Previews and documents are normally smaller than whatever they
+    /// represent, so [`Fit::Max`] would leave the thumbnail too small.
+    pub(crate) fn scaled_to_fill(&self, width: u32, height: u32) -> (u32, u32) {
+        let target = match self.fit {
+            Fit::Max => self.with_fit(Fit::Contain),
+            _ => *self,
+        };
+        target.scaled_size(width, height)
+    }
+}
+
+/// How an image should be fitted into the desired [`Dimensions`] when its
+/// aspect ratio is different.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Fit {
+    /// Shrink the image so it fits within the bounding box, preserving its
+    /// aspect ratio. Images which already fit are never scaled up, so the
+    /// thumbnail may be smaller than requested.
+    #[default]
+    Max,
+    /// Scale the image so it fits within the bounding box, preserving its
+    /// aspect ratio, then pad it with transparent pixels so the thumbnail is
+    /// exactly the requested size.
+    Contain,
+    /// Scale the image so it covers the entire bounding box, preserving its
+    /// aspect ratio, then crop the edges so the thumbnail is exactly the
+    /// requested size.
+    Cover,
+    /// Scale the image to exactly the requested size, ignoring its aspect
+    /// ratio.
+    Stretch,
 }
 
 pub trait ThumbnailProvider: Send + Sync {
@@ -22,4 +168,81 @@ pub trait ThumbnailProvider: Send + Sync {
     ) -> Result<Self::Thumbnail, Self::Error>
     where
         R: Read;
+
+    /// Generate a thumbnail from an input which supports random access.
+    ///
+    /// Providers for container formats (ZIP-based documents, MP4, TIFF, PDF,
+    /// etc.) should override this so they can jump straight to the data they
+    /// need instead of reading the whole file into memory. The default
+    /// implementation defers to [`ThumbnailProvider::get_thumbnail()`].
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        self.get_thumbnail(input, desired_dimensions)
+    }
+
+    /// The ways this provider is able to receive its input.
+    ///
+    /// Under Windows, this decides whether the shell initializes the provider
+    /// with a stream (`IInitializeWithStream`) or a path
+    /// (`IInitializeWithFile` and `IInitializeWithItem`).
+    fn input_sources(&self) -> InputSources { InputSources::STREAM }
+
+    /// Generate a thumbnail using whatever the caller knows about the input
+    /// (its path, MIME type, etc.).
+    ///
+    /// Providers which need to look at other files (e.g. sidecar XMP files or
+    /// the other parts of a multi-part archive) should override this and
+    /// opt into [`InputSources::path`] so [`ThumbnailContext::path`] is set.
+    /// The default implementation ignores the context and defers to
+    /// [`ThumbnailProvider::get_thumbnail_seekable()`].
+    fn get_thumbnail_with_context<R>(
+        &self,
+        input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let _ = context;
+        self.get_thumbnail_seekable(input, desired_dimensions)
+    }
+}
+
+/// The ways a [`ThumbnailProvider`] is able to receive its input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputSources {
+    /// The provider can read its input from an arbitrary stream.
+    pub stream: bool,
+    /// The provider needs to know where its input is on disk.
+    pub path: bool,
+}
+
+impl InputSources {
+    pub const PATH: InputSources = InputSources {
+        stream: false,
+        path: true,
+    };
+    pub const STREAM: InputSources = InputSources {
+        stream: true,
+        path: false,
+    };
+
+    /// Accept input from either source.
+    pub const fn or(self, other: InputSources) -> Self {
+        InputSources {
+            stream: self.stream || other.stream,
+            path: self.path || other.path,
+        }
+    }
+}
+
+impl Default for InputSources {
+    fn default() -> Self { InputSources::STREAM }
 }
@@ -0,0 +1,363 @@
+use crate::ThumbnailError;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// Upper bounds on the resources used while generating a thumbnail, so a
+/// malicious or corrupt file (e.g. a tiny PNG which claims to be 60000 x
+/// 60000 pixels) can't exhaust memory.
+///
+/// Providers should check the relevant limits before allocating, failing with
+/// [`ThumbnailError::LimitExceeded`] when one is hit.
+///
+/// # Examples
+///
+/// ```rust
+/// use thumbnails::{Limits, ThumbnailContext};
+///
+/// let limits = Limits::default()
+///     .with_max_input_bytes(16 * 1024 * 1024)
+///     .with_max_pixels(4096 * 4096);
+/// let context = ThumbnailContext::new().with_limits(limits);
+///
+/// assert!(context.limits.check_dimensions(1024, 768).is_ok());
+/// assert!(context.limits.check_dimensions(60_000, 60_000).is_err());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest input a provider will read.
+    pub max_input_bytes: Option<u64>,
+    /// The largest image (width × height) a provider will decode.
+    pub max_pixels: Option<u64>,
+    /// The largest single buffer a provider will allocate.
+    pub max_alloc: Option<u64>,
+    /// The most frames, pages or IFDs a provider will look at.
+    pub max_frames: Option<u32>,
+    /// How deeply archives (or the boxes in container formats like MP4) may
+    /// be nested inside each other.
+    pub max_depth: Option<u32>,
+}
+
+impl Limits {
+    /// Limits which allow anything.
+    pub const fn none() -> Self {
+        Limits {
+            max_input_bytes: None,
+            max_pixels: None,
+            max_alloc: None,
+            max_frames: None,
+            max_depth: None,
+        }
+    }
+
+    pub fn with_max_input_bytes(self, max_input_bytes: u64) -> Self {
+        Limits {
+            max_input_bytes: Some(max_input_bytes),
+            ..self
+        }
+    }
+
+    pub fn with_max_pixels(self, max_pixels: u64) -> Self {
+        Limits {
+            max_pixels: Some(max_pixels),
+            ..self
+        }
+    }
+
+    pub fn with_max_alloc(self, max_alloc: u64) -> Self {
+        Limits {
+            max_alloc: Some(max_alloc),
+            ..self
+        }
+    }
+
+    pub fn with_max_frames(self, max_frames: u32) -> Self {
+        Limits {
+            max_frames: Some(max_frames),
+            ..self
+        }
+    }
+
+    pub fn with_max_depth(self, max_depth: u32) -> Self {
+        Limits {
+            max_depth: Some(max_depth),
+            ..self
+        }
+    }
+
+    /// Make sure an image with these dimensions can be decoded into an RGBA
+    /// buffer.
+    pub fn check_dimensions(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<(), ThumbnailError> {
+        let pixels = u64::from(width) * u64::from(height);
+
+        if let Some(max_pixels) = self.max_pixels {
+            if pixels > max_pixels {
+                return Err(Limit::Pixels(max_pixels).into());
+            }
+        }
+
+        self.check_alloc(pixels.saturating_mul(4))
+    }
+
+    pub fn check_alloc(&self, bytes: u64) -> Result<(), ThumbnailError> {
+        match self.max_alloc {
+            Some(max_alloc) if bytes > max_alloc => {
+                Err(Limit::Allocation(max_alloc).into())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a provider may look at another frame or page, where
+    /// `frames` is how many it will have looked at.
+    pub fn check_frames(&self, frames: u32) -> Result<(), ThumbnailError> {
+        match self.max_frames {
+            Some(max_frames) if frames > max_frames => {
+                Err(Limit::Frames(max_frames).into())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a provider may open an archive (or box) nested `depth`
+    /// levels deep.
+    pub fn check_depth(&self, depth: u32) -> Result<(), ThumbnailError> {
+        match self.max_depth {
+            Some(max_depth) if depth > max_depth => {
+                Err(Limit::Depth(max_depth).into())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Wrap a reader so it fails once more than
+    /// [`Limits::max_input_bytes`] have been read.
+    pub fn reader<R>(&self, inner: R) -> LimitedReader<R> {
+        LimitedReader::new(inner, self.max_input_bytes)
+    }
+
+    /// The equivalent limits for the decoders in the [`image`] crate.
+    pub(crate) fn to_image_limits(self) -> image::io::Limits {
+        let mut limits = image::io::Limits::no_limits();
+        limits.max_alloc = self.max_alloc;
+        limits
+    }
+}
+
+impl Default for Limits {
+    /// Limits which are generous enough for any reasonable photo or
+    /// document.
+    fn default() -> Self {
+        Limits {
+            max_input_bytes: Some(1024 * 1024 * 1024),
+            max_pixels: Some(200_000_000),
+            max_alloc: Some(1024 * 1024 * 1024),
+            max_frames: Some(1000),
+            max_depth: Some(8),
+        }
+    }
+}
+
+/// Which of the [`Limits`] was hit, and its value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limit {
+    InputBytes(u64),
+    Pixels(u64),
+    Allocation(u64),
+    Frames(u32),
+    Depth(u32),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::InputBytes(bytes) => write!(f, "{} bytes of input", bytes),
+            Limit::Pixels(pixels) => write!(f, "{} pixels", pixels),
+            Limit::Allocation(bytes) => {
+                write!(f, "allocations of {} bytes", bytes)
+            },
+            Limit::Frames(frames) => write!(f, "{} frames", frames),
+            Limit::Depth(depth) => write!(f, "archives nested {} deep", depth),
+        }
+    }
+}
+
+impl From<Limit> for ThumbnailError {
+    fn from(limit: Limit) -> Self { ThumbnailError::LimitExceeded(limit) }
+}
+
+/// A reader which fails with [`Limit::InputBytes`] (wrapped in an
+/// [`io::Error`]) when the underlying reader has more than a certain number
+/// of bytes.
+///
+/// Seeking is allowed, so the limit applies to how far into the input we
+/// read rather than the total number of bytes read. Positions are measured
+/// from wherever the inner reader was when it was wrapped, so a reader which
+/// is handed over part way through a file only gets `limit` more bytes.
+#[derive(Debug)]
+pub struct LimitedReader<R> {
+    inner: R,
+    limit: Option<u64>,
+    /// How far we are from the start.
+    position: u64,
+    /// The inner reader's position when it was wrapped. This is only needed
+    /// (and only knowable) once we start seeking.
+    start: Option<u64>,
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, limit: Option<u64>) -> Self {
+        LimitedReader {
+            inner,
+            limit,
+            position: 0,
+            start: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => {
+                let bytes_read = self.inner.read(buf)?;
+                self.position += bytes_read as u64;
+                return Ok(bytes_read);
+            },
+        };
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let remaining = limit.saturating_sub(self.position);
+
+        if remaining == 0 {
+            // we've read as much as we're allowed, but it's fine as long as
+            // there is nothing left
+            return match self.inner.read(&mut buf[..1])? {
+                0 => Ok(0),
+                _ => Err(io::Error::other(ThumbnailError::from(
+                    Limit::InputBytes(limit),
+                ))),
+            };
+        }
+
+        let len = if remaining < buf.len() as u64 {
+            remaining as usize
+        } else {
+            buf.len()
+        };
+        let bytes_read = self.inner.read(&mut buf[..len])?;
+        self.position += bytes_read as u64;
+
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Seek> Seek for LimitedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                // everything read so far came after the start
+                let start =
+                    self.inner.stream_position()?.saturating_sub(self.position);
+                self.start = Some(start);
+                start
+            },
+        };
+
+        let position = self.inner.seek(pos)?;
+        self.position = position.saturating_sub(start);
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn inputs_within_the_limit_are_read_in_full() {
+        let mut reader = LimitedReader::new(&b"Hello, World!"[..], Some(13));
+        let mut buffer = Vec::new();
+
+        reader.read_to_end(&mut buffer).unwrap();
+
+        assert_eq!(buffer, b"Hello, World!");
+    }
+
+    #[test]
+    fn fail_once_the_input_is_too_long() {
+        let mut reader = LimitedReader::new(&b"Hello, World!"[..], Some(5));
+        let mut buffer = Vec::new();
+
+        let err = reader.read_to_end(&mut buffer).unwrap_err();
+
+        assert!(matches!(
+            ThumbnailError::from(err),
+            ThumbnailError::LimitExceeded(Limit::InputBytes(5))
+        ));
+    }
+
+    #[test]
+    fn rereading_a_seekable_input_is_fine() {
+        let mut reader =
+            LimitedReader::new(Cursor::new(b"Hello, World!"), Some(13));
+        let mut buffer = Vec::new();
+
+        reader.read_to_end(&mut buffer).unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut buffer).unwrap();
+
+        assert_eq!(buffer.len(), 26);
+    }
+
+    #[test]
+    fn readers_handed_over_part_way_through() {
+        let mut inner = Cursor::new(vec![0; 1000]);
+        inner.set_position(990);
+        let mut reader = LimitedReader::new(inner, Some(10));
+        let mut buffer = Vec::new();
+
+        reader.read_exact(&mut [0; 5]).unwrap();
+        assert_eq!(reader.stream_position().unwrap(), 995);
+        reader.seek(SeekFrom::End(-10)).unwrap();
+        reader.read_to_end(&mut buffer).unwrap();
+
+        assert_eq!(buffer.len(), 10);
+    }
+
+    #[test]
+    fn huge_images_are_rejected_before_decoding() {
+        let limits = Limits::default();
+
+        assert!(limits.check_dimensions(4000, 3000).is_ok());
+        assert!(matches!(
+            limits.check_dimensions(60_000, 60_000),
+            Err(ThumbnailError::LimitExceeded(Limit::Pixels(_)))
+        ));
+        assert!(Limits::none().check_dimensions(60_000, 60_000).is_ok());
+    }
+
+    #[test]
+    fn the_rgba_buffer_counts_towards_the_allocation_limit() {
+        let limits = Limits::none().with_max_alloc(1024);
+
+        assert!(limits.check_dimensions(16, 16).is_ok());
+        assert!(matches!(
+            limits.check_dimensions(16, 17),
+            Err(ThumbnailError::LimitExceeded(Limit::Allocation(1024)))
+        ));
+    }
+}
@@ -0,0 +1,1034 @@
+use crate::{
+    providers::{get_thumbnail_buffered, ImageProvider},
+    Dimensions, Limits, ProviderRegistry, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use image::RgbaImage;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// A [`ThumbnailProvider`] which uses the cover art embedded in audio files.
+///
+/// Pictures are read from ID3v2 tags (MP3), FLAC `PICTURE` blocks, Ogg
+/// Vorbis and Opus comments, MP4 `covr` atoms and APEv2 tags. When a file
+/// contains several pictures, the front cover is preferred.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct AudioProvider {
+    decoder: ImageProvider,
+}
+
+impl AudioProvider {
+    pub fn new() -> Self { AudioProvider::default() }
+
+    /// Register an [`AudioProvider`] for all the audio formats it
+    /// understands.
+    pub fn register(registry: &mut ProviderRegistry) {
+        let mut registration = registry.register(AudioProvider::new());
+
+        for &mime_type in MIME_TYPES {
+            registration = registration.mime_type(mime_type);
+        }
+        for &extension in EXTENSIONS {
+            registration = registration.extension(extension);
+        }
+    }
+}
+
+const MIME_TYPES: &[&str] = &[
+    "audio/mpeg",
+    "audio/flac",
+    "audio/ogg",
+    "audio/opus",
+    "audio/mp4",
+    "audio/x-m4a",
+    "audio/x-ape",
+];
+const EXTENSIONS: &[&str] =
+    &["mp3", "flac", "ogg", "oga", "opus", "m4a", "m4b", "ape"];
+
+impl ThumbnailProvider for AudioProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        // APE tags are at the end of the file, so we need random access
+        get_thumbnail_buffered(self, input, desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let context = ThumbnailContext::new();
+        self.get_thumbnail_with_context(input, &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        mut input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let pictures = read_pictures(&mut input, &context.limits)?;
+        context.check()?;
+
+        let cover = best_picture(pictures).ok_or_else(|| {
+            ThumbnailError::unsupported("No cover art was found")
+        })?;
+
+        self.decoder.get_thumbnail_with_context(
+            Cursor::new(cover.data),
+            context,
+            desired_dimensions,
+        )
+    }
+}
+
+/// The picture types used by ID3 and FLAC.
+const OTHER: u32 = 0;
+const FILE_ICON: u32 = 1;
+const OTHER_FILE_ICON: u32 = 2;
+const FRONT_COVER: u32 = 3;
+const BACK_COVER: u32 = 4;
+
+/// An embedded image.
+#[derive(Debug, Clone, PartialEq)]
+struct Picture {
+    kind: u32,
+    data: Vec<u8>,
+}
+
+/// Pick the front cover if there is one, avoiding file icons.
+fn best_picture(pictures: Vec<Picture>) -> Option<Picture> {
+    pictures
+        .into_iter()
+        .filter(|picture| !picture.data.is_empty())
+        .min_by_key(|picture| match picture.kind {
+            FRONT_COVER => 0,
+            FILE_ICON | OTHER_FILE_ICON => 2,
+            _ => 1,
+        })
+}
+
+fn read_pictures<R>(
+    input: &mut R,
+    limits: &Limits,
+) -> Result<Vec<Picture>, ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let mut pictures = Vec::new();
+    let mut position = input.stream_position()?;
+    let mut magic = peek(input, position)?;
+
+    // FLAC files sometimes have an ID3 tag in front of them
+    if magic.starts_with(b"ID3") {
+        position = read_id3(input, position, limits, &mut pictures)?;
+        magic = peek(input, position)?;
+    }
+
+    if magic.starts_with(b"fLaC") {
+        read_flac(input, position + 4, limits, &mut pictures)?;
+    } else if magic.starts_with(b"OggS") {
+        read_ogg(input, position, limits, &mut pictures)?;
+    } else if &magic[4..8] == b"ftyp" {
+        let end = input.seek(SeekFrom::End(0))?;
+        read_mp4(input, position, end, b"", 0, limits, &mut pictures)?;
+    }
+
+    read_ape(input, limits, &mut pictures)?;
+
+    Ok(pictures)
+}
+
+/// Read the first few bytes at a position, padding with zeroes if the file
+/// is shorter than that.
+fn peek<R>(input: &mut R, position: u64) -> Result<[u8; 12], ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let mut magic = Vec::new();
+    input.seek(SeekFrom::Start(position))?;
+    input.take(12).read_to_end(&mut magic)?;
+
+    let mut padded = [0; 12];
+    padded[..magic.len()].copy_from_slice(&magic);
+    Ok(padded)
+}
+
+fn read_vec<R: Read>(
+    input: &mut R,
+    len: u64,
+    limits: &Limits,
+) -> Result<Vec<u8>, ThumbnailError> {
+    limits.check_alloc(len)?;
+
+    let mut buffer = vec![0; len as usize];
+    input.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Split `len` bytes off the front of a buffer.
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bytes.len() < len {
+        return None;
+    }
+
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Some(head)
+}
+
+fn take_u32_be(bytes: &mut &[u8]) -> Option<u32> {
+    let raw = take(bytes, 4)?;
+    Some(u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]))
+}
+
+fn take_u32_le(bytes: &mut &[u8]) -> Option<u32> {
+    let raw = take(bytes, 4)?;
+    Some(u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]))
+}
+
+/// Split a NUL-terminated string off the front of a buffer.
+fn take_c_string<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let end = bytes.iter().position(|&b| b == 0)?;
+    let text = take(bytes, end)?;
+    take(bytes, 1)?;
+    Some(text)
+}
+
+/// Decode the 7-bits-per-byte integers used by ID3v2.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |acc, &byte| (acc << 7) | u32::from(byte & 0x7F))
+}
+
+/// Undo ID3's unsynchronisation, where `FF 00` is written as `FF`.
+fn resynchronise(bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut previous = 0;
+
+    for &byte in bytes {
+        if !(previous == 0xFF && byte == 0x00) {
+            output.push(byte);
+        }
+        previous = byte;
+    }
+
+    output
+}
+
+/// Read the `APIC` (or `PIC` in ID3v2.2) frames from an ID3v2 tag, returning
+/// where the tag ends.
+fn read_id3<R>(
+    input: &mut R,
+    start: u64,
+    limits: &Limits,
+    pictures: &mut Vec<Picture>,
+) -> Result<u64, ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let mut header = [0; 10];
+    input.seek(SeekFrom::Start(start))?;
+    input.read_exact(&mut header)?;
+
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+    let end = start + 10 + u64::from(size) + footer;
+
+    if !(2..=4).contains(&version) {
+        return Ok(end);
+    }
+
+    let mut tag = read_vec(input, u64::from(size), limits)?;
+    if version < 4 && flags & 0x80 != 0 {
+        tag = resynchronise(&tag);
+    }
+
+    let mut frames = &tag[..];
+    if version > 2 && flags & 0x40 != 0 {
+        // skip the extended header
+        let raw = match take(&mut frames, 4) {
+            Some(raw) => raw,
+            None => return Ok(end),
+        };
+        let len = match version {
+            3 => u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]),
+            _ => syncsafe(raw).saturating_sub(4),
+        };
+        take(&mut frames, len as usize);
+    }
+
+    while let Some(frame) = Id3Frame::parse(&mut frames, version) {
+        if frame.id == b"APIC" || frame.id == b"PIC" {
+            if let Some(picture) = frame.picture() {
+                pictures.push(picture);
+            }
+        }
+    }
+
+    Ok(end)
+}
+
+struct Id3Frame<'a> {
+    id: &'a [u8],
+    version: u8,
+    body: Option<Vec<u8>>,
+}
+
+impl<'a> Id3Frame<'a> {
+    fn parse(frames: &mut &'a [u8], version: u8) -> Option<Id3Frame<'a>> {
+        let (id, size, flags) = if version == 2 {
+            let header = take(frames, 6)?;
+            let size = u32::from_be_bytes([0, header[3], header[4], header[5]]);
+            (&header[..3], size, 0)
+        } else {
+            let header = take(frames, 10)?;
+            let size = if version == 3 {
+                u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+            } else {
+                syncsafe(&header[4..8])
+            };
+            (&header[..4], size, header[9])
+        };
+
+        // we've reached the padding
+        if id[0] == 0 {
+            return None;
+        }
+
+        let mut body = take(frames, size as usize)?;
+        let body = match version {
+            3 => {
+                let is_compressed_or_encrypted = flags & 0xC0 != 0;
+                let is_grouped = flags & 0x20 != 0;
+                if is_grouped {
+                    take(&mut body, 1);
+                }
+                Some(body.to_vec()).filter(|_| !is_compressed_or_encrypted)
+            },
+            4 => {
+                let is_compressed_or_encrypted = flags & 0x0C != 0;
+                if flags & 0x40 != 0 {
+                    take(&mut body, 1);
+                }
+                if flags & 0x01 != 0 {
+                    take(&mut body, 4);
+                }
+                if flags & 0x02 != 0 {
+                    Some(resynchronise(body))
+                } else {
+                    Some(body.to_vec())
+                }
+                .filter(|_| !is_compressed_or_encrypted)
+            },
+            _ => Some(body.to_vec()),
+        };
+
+        Some(Id3Frame { id, version, body })
+    }
+
+    fn picture(&self) -> Option<Picture> {
+        let mut body = &self.body.as_ref()?[..];
+        let encoding = take(&mut body, 1)?[0];
+
+        if self.version == 2 {
+            // a three letter image format
+            take(&mut body, 3)?;
+        } else {
+            take_c_string(&mut body)?;
+        }
+        let kind = take(&mut body, 1)?[0];
+
+        // skip the description
+        match encoding {
+            // UTF-16 is terminated by two NULs
+            1 | 2 => {
+                let end = body.chunks_exact(2).position(|c| c == [0, 0])?;
+                take(&mut body, end * 2 + 2)?;
+            },
+            _ => {
+                take_c_string(&mut body)?;
+            },
+        }
+
+        Some(Picture {
+            kind: u32::from(kind),
+            data: body.to_vec(),
+        })
+    }
+}
+
+/// Read the `PICTURE` blocks from FLAC metadata, starting just after the
+/// `fLaC` marker.
+fn read_flac<R>(
+    input: &mut R,
+    start: u64,
+    limits: &Limits,
+    pictures: &mut Vec<Picture>,
+) -> Result<(), ThumbnailError>
+where
+    R: Read + Seek,
+{
+    input.seek(SeekFrom::Start(start))?;
+
+    loop {
+        let mut header = [0; 4];
+        input.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+
+        if kind == 6 {
+            let block = read_vec(input, u64::from(len), limits)?;
+            if let Some(picture) = parse_flac_picture(&block) {
+                pictures.push(picture);
+            }
+        } else {
+            input.seek(SeekFrom::Current(i64::from(len)))?;
+        }
+
+        if is_last {
+            return Ok(());
+        }
+    }
+}
+
+/// Parse a FLAC `PICTURE` block, which is also used by Vorbis comments.
+fn parse_flac_picture(mut block: &[u8]) -> Option<Picture> {
+    let kind = take_u32_be(&mut block)?;
+    let mime_type_len = take_u32_be(&mut block)?;
+    take(&mut block, mime_type_len as usize)?;
+    let description_len = take_u32_be(&mut block)?;
+    take(&mut block, description_len as usize)?;
+    // width, height, colour depth and number of colours
+    take(&mut block, 16)?;
+    let len = take_u32_be(&mut block)?;
+    let data = take(&mut block, len as usize)?;
+
+    Some(Picture {
+        kind,
+        data: data.to_vec(),
+    })
+}
+
+/// Read the comment header from the first logical stream in an Ogg file.
+fn read_ogg<R>(
+    input: &mut R,
+    start: u64,
+    limits: &Limits,
+    pictures: &mut Vec<Picture>,
+) -> Result<(), ThumbnailError>
+where
+    R: Read + Seek,
+{
+    input.seek(SeekFrom::Start(start))?;
+
+    let mut serial = None;
+    let mut packet = Vec::new();
+    let mut packets = 0;
+    let mut pages = 0;
+
+    loop {
+        pages += 1;
+        limits.check_frames(pages)?;
+
+        let mut header = [0; 27];
+        input.read_exact(&mut header)?;
+        if !header.starts_with(b"OggS") {
+            return Err(ThumbnailError::corrupt("Invalid Ogg page"));
+        }
+
+        let page_serial = u32::from_le_bytes([
+            header[14], header[15], header[16], header[17],
+        ]);
+        let mut segments = vec![0; usize::from(header[26])];
+        input.read_exact(&mut segments)?;
+
+        if *serial.get_or_insert(page_serial) != page_serial {
+            let len: u64 = segments.iter().map(|&s| u64::from(s)).sum();
+            input.seek(SeekFrom::Current(len as i64))?;
+            continue;
+        }
+
+        // packets are split into segments of 255 bytes, with a shorter
+        // segment marking the end of the packet
+        for &segment in &segments {
+            limits.check_alloc(packet.len() as u64 + u64::from(segment))?;
+            let mut bytes = read_vec(input, u64::from(segment), limits)?;
+            packet.append(&mut bytes);
+
+            if segment < 255 {
+                packets += 1;
+                // the comments are always the second packet
+                if packets == 2 {
+                    parse_comments(&packet, pictures);
+                    return Ok(());
+                }
+                packet.clear();
+            }
+        }
+    }
+}
+
+/// Parse a Vorbis or Opus comment header, looking for
+/// `METADATA_BLOCK_PICTURE` (or the older `COVERART`) comments.
+fn parse_comments(packet: &[u8], pictures: &mut Vec<Picture>) {
+    let mut body = if packet.starts_with(b"\x03vorbis") {
+        &packet[7..]
+    } else if packet.starts_with(b"OpusTags") {
+        &packet[8..]
+    } else {
+        return;
+    };
+
+    let vendor_len = match take_u32_le(&mut body) {
+        Some(len) => len as usize,
+        None => return,
+    };
+    take(&mut body, vendor_len);
+    let count = take_u32_le(&mut body).unwrap_or(0);
+
+    for _ in 0..count {
+        let comment = match take_u32_le(&mut body)
+            .and_then(|len| take(&mut body, len as usize))
+        {
+            Some(comment) => comment,
+            None => return,
+        };
+        let equals = match comment.iter().position(|&b| b == b'=') {
+            Some(equals) => equals,
+            None => continue,
+        };
+        let (key, value) = (&comment[..equals], &comment[equals + 1..]);
+
+        if key.eq_ignore_ascii_case(b"METADATA_BLOCK_PICTURE") {
+            if let Some(picture) =
+                decode_base64(value).and_then(|b| parse_flac_picture(&b))
+            {
+                pictures.push(picture);
+            }
+        } else if key.eq_ignore_ascii_case(b"COVERART") {
+            if let Some(data) = decode_base64(value) {
+                pictures.push(Picture { kind: OTHER, data });
+            }
+        }
+    }
+}
+
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+
+    for &c in text {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+
+        buffer = ((buffer << 6) | u32::from(value)) & 0xFFFF;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+/// How deeply we'll descend into the atoms in an MP4 file.
+const MAX_ATOM_DEPTH: u32 = 8;
+
+/// Walk the atoms in an MP4 file, looking for the `data` atoms inside
+/// `moov/udta/meta/ilst/covr`.
+fn read_mp4<R>(
+    input: &mut R,
+    start: u64,
+    end: u64,
+    parent: &[u8],
+    depth: u32,
+    limits: &Limits,
+    pictures: &mut Vec<Picture>,
+) -> Result<(), ThumbnailError>
+where
+    R: Read + Seek,
+{
+    limits.check_depth(depth)?;
+    if depth > MAX_ATOM_DEPTH {
+        return Ok(());
+    }
+
+    let mut position = start;
+
+    while position.saturating_add(8) <= end {
+        input.seek(SeekFrom::Start(position))?;
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        let size =
+            u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = [header[4], header[5], header[6], header[7]];
+
+        let (header_len, size) = match size {
+            0 => (8, end - position),
+            1 => {
+                let mut size = [0; 8];
+                input.read_exact(&mut size)?;
+                (16, u64::from_be_bytes(size))
+            },
+            size => (8, u64::from(size)),
+        };
+        if size < header_len || size > end - position {
+            return Err(ThumbnailError::corrupt("Invalid atom size"));
+        }
+
+        let body = position + header_len;
+        let body_end = position + size;
+        let mut recurse = |input: &mut R, start: u64| {
+            read_mp4(input, start, body_end, &kind, depth + 1, limits, pictures)
+        };
+
+        match &kind {
+            b"moov" | b"udta" | b"ilst" | b"covr" => recurse(input, body)?,
+            b"meta" => {
+                // iTunes' meta atom has a version and flags, but QuickTime's
+                // doesn't
+                let child = peek(input, body)?;
+                if &child[4..8] == b"hdlr" {
+                    recurse(input, body)?;
+                } else {
+                    recurse(input, body + 4)?;
+                }
+            },
+            // the type of image and a locale come before the image itself
+            b"data" if parent == b"covr" && body_end - body > 8 => {
+                input.seek(SeekFrom::Start(body + 8))?;
+                let data = read_vec(input, body_end - body - 8, limits)?;
+                pictures.push(Picture {
+                    kind: FRONT_COVER,
+                    data,
+                });
+            },
+            _ => {},
+        }
+
+        position = body_end;
+    }
+
+    Ok(())
+}
+
+/// Read the cover art items from an APEv2 tag at the end of the file.
+fn read_ape<R>(
+    input: &mut R,
+    limits: &Limits,
+    pictures: &mut Vec<Picture>,
+) -> Result<(), ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let end = input.seek(SeekFrom::End(0))?;
+
+    // the tag may be followed by a 128 byte ID3v1 tag
+    for &trailer in &[0, 128] {
+        if end < 32 + trailer {
+            continue;
+        }
+
+        let footer_start = end - trailer - 32;
+        let mut footer = [0; 32];
+        input.seek(SeekFrom::Start(footer_start))?;
+        input.read_exact(&mut footer)?;
+        if !footer.starts_with(b"APETAGEX") {
+            continue;
+        }
+
+        // the size includes the footer but not the header
+        let size = u32::from_le_bytes([
+            footer[12], footer[13], footer[14], footer[15],
+        ]);
+        let count = u32::from_le_bytes([
+            footer[16], footer[17], footer[18], footer[19],
+        ]);
+        let items_len = u64::from(size).saturating_sub(32);
+        if items_len > footer_start {
+            return Err(ThumbnailError::corrupt("Invalid APE tag size"));
+        }
+
+        input.seek(SeekFrom::Start(footer_start - items_len))?;
+        let items = read_vec(input, items_len, limits)?;
+        parse_ape_items(&items, count, pictures);
+
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+fn parse_ape_items(mut items: &[u8], count: u32, pictures: &mut Vec<Picture>) {
+    for _ in 0..count {
+        let item = take_u32_le(&mut items).and_then(|len| {
+            let flags = take_u32_le(&mut items)?;
+            let key = take_c_string(&mut items)?;
+            let value = take(&mut items, len as usize)?;
+            Some((flags, key, value))
+        });
+        let (flags, key, mut value) = match item {
+            Some(item) => item,
+            None => return,
+        };
+
+        let is_binary = (flags >> 1) & 0b11 == 1;
+        let key = String::from_utf8_lossy(key).to_ascii_lowercase();
+        if !is_binary || !key.starts_with("cover art") {
+            continue;
+        }
+
+        let kind = match key.as_str() {
+            "cover art (front)" => FRONT_COVER,
+            "cover art (back)" => BACK_COVER,
+            _ => OTHER,
+        };
+        // the value is a file name followed by the image
+        if take_c_string(&mut value).is_some() {
+            pictures.push(Picture {
+                kind,
+                data: value.to_vec(),
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::{Hint, Limit};
+    use image::{ImageFormat, Rgba};
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn png(width: u32, height: u32, colour: Rgba<u8>) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, colour);
+        let mut buffer = Cursor::new(Vec::new());
+        image.write_to(&mut buffer, ImageFormat::Png).unwrap();
+        buffer.into_inner()
+    }
+
+    fn thumbnail(file: &[u8]) -> RgbaImage {
+        AudioProvider::new()
+            .get_thumbnail(file, Dimensions::square(16))
+            .unwrap()
+    }
+
+    fn id3v2_3(frames: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::new();
+        for (id, frame) in frames {
+            body.extend(*id);
+            body.extend(&(frame.len() as u32).to_be_bytes());
+            body.extend(&[0, 0]);
+            body.extend(frame);
+        }
+        // some padding
+        body.extend(&[0; 16]);
+
+        let size = body.len() as u32;
+        let mut tag = b"ID3\x03\0\0".to_vec();
+        tag.extend(
+            [21, 14, 7, 0]
+                .iter()
+                .map(|&shift| ((size >> shift) & 0x7F) as u8),
+        );
+        tag.extend(body);
+        tag
+    }
+
+    fn apic(kind: u8, image: &[u8]) -> Vec<u8> {
+        let mut frame = b"\x00image/png\x00".to_vec();
+        frame.push(kind);
+        frame.extend(b"A description\x00");
+        frame.extend(image);
+        frame
+    }
+
+    #[test]
+    fn prefer_the_front_cover_in_id3_tags() {
+        let mut mp3 = id3v2_3(&[
+            (b"TIT2", b"\x00A Song".to_vec()),
+            (b"APIC", apic(BACK_COVER as u8, &png(32, 32, BLUE))),
+            (b"APIC", apic(FRONT_COVER as u8, &png(32, 32, RED))),
+        ]);
+        mp3.extend(b"\xff\xfb\x90\x64 the audio");
+
+        let got = thumbnail(&mp3);
+
+        assert_eq!(got.dimensions(), (16, 16));
+        assert_eq!(*got.get_pixel(8, 8), RED);
+    }
+
+    #[test]
+    fn utf16_descriptions() {
+        let mut frame = b"\x01image/png\x00\x03".to_vec();
+        frame.extend(&[0xFF, 0xFE, b'h', 0, b'i', 0, 0, 0]);
+        frame.extend(png(32, 32, RED));
+        let mp3 = id3v2_3(&[(b"APIC", frame)]);
+
+        assert_eq!(*thumbnail(&mp3).get_pixel(8, 8), RED);
+    }
+
+    fn flac_picture(kind: u32, image: &[u8]) -> Vec<u8> {
+        let mut block = kind.to_be_bytes().to_vec();
+        block.extend(&9_u32.to_be_bytes());
+        block.extend(b"image/png");
+        block.extend(&0_u32.to_be_bytes());
+        block.extend(&[0; 16]);
+        block.extend(&(image.len() as u32).to_be_bytes());
+        block.extend(image);
+        block
+    }
+
+    #[test]
+    fn flac_picture_blocks() {
+        let picture = flac_picture(FRONT_COVER, &png(32, 32, RED));
+        let mut flac = b"fLaC".to_vec();
+        // STREAMINFO
+        flac.extend(&[0, 0, 0, 34]);
+        flac.extend(&[0; 34]);
+        // PICTURE, and the last block
+        flac.push(0x80 | 6);
+        flac.extend(&(picture.len() as u32).to_be_bytes()[1..]);
+        flac.extend(picture);
+
+        assert_eq!(*thumbnail(&flac).get_pixel(8, 8), RED);
+    }
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+
+        for chunk in bytes.chunks(3) {
+            let buffer = chunk
+                .iter()
+                .enumerate()
+                .fold(0_u32, |acc, (i, &b)| acc | u32::from(b) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    let index = (buffer >> (18 - 6 * i)) & 0x3F;
+                    text.push(char::from(ALPHABET[index as usize]));
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+
+        text
+    }
+
+    #[test]
+    fn round_trip_base64() {
+        for text in &["", "a", "ab", "abc", "abcd"] {
+            let encoded = encode_base64(text.as_bytes());
+            assert_eq!(
+                decode_base64(encoded.as_bytes()).unwrap(),
+                text.as_bytes()
+            );
+        }
+    }
+
+    /// Put some packets into a single Ogg page.
+    fn ogg_page(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut segments = Vec::new();
+        for packet in packets {
+            segments.extend(std::iter::repeat_n(255, packet.len() / 255));
+            segments.push((packet.len() % 255) as u8);
+        }
+
+        let mut page = b"OggS\0\x02".to_vec();
+        page.extend(&[0; 8]);
+        page.extend(&1234_u32.to_le_bytes());
+        page.extend(&[0; 8]);
+        page.push(segments.len() as u8);
+        page.extend(segments);
+        page.extend(packets.concat());
+        page
+    }
+
+    fn comments(magic: &[u8], comments: &[String]) -> Vec<u8> {
+        let mut packet = magic.to_vec();
+        packet.extend(&6_u32.to_le_bytes());
+        packet.extend(b"vendor");
+        packet.extend(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            packet.extend(&(comment.len() as u32).to_le_bytes());
+            packet.extend(comment.as_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn vorbis_and_opus_comments() {
+        let picture =
+            encode_base64(&flac_picture(FRONT_COVER, &png(32, 32, RED)));
+        let tags = [
+            "TITLE=A Song".to_string(),
+            format!("METADATA_BLOCK_PICTURE={}", picture),
+        ];
+        let headers: [(&[u8], &[u8]); 2] =
+            [(b"\x01vorbis", b"\x03vorbis"), (b"OpusHead", b"OpusTags")];
+
+        for &(identification, comment) in &headers {
+            let mut ogg = ogg_page(&[identification.to_vec()]);
+            ogg.extend(ogg_page(&[comments(comment, &tags)]));
+
+            assert_eq!(*thumbnail(&ogg).get_pixel(8, 8), RED);
+        }
+    }
+
+    fn atom(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        bytes.extend(kind);
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn mp4_cover_atoms() {
+        let mut data = vec![0, 0, 0, 14, 0, 0, 0, 0];
+        data.extend(png(32, 32, RED));
+        let ilst = atom(b"ilst", &atom(b"covr", &atom(b"data", &data)));
+        let mut meta = vec![0; 4];
+        meta.extend(atom(b"hdlr", &[0; 25]));
+        meta.extend(ilst);
+
+        let mut m4a = atom(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        m4a.extend(atom(b"moov", &atom(b"udta", &atom(b"meta", &meta))));
+        m4a.extend(atom(b"mdat", &[0; 64]));
+
+        assert_eq!(*thumbnail(&m4a).get_pixel(8, 8), RED);
+    }
+
+    #[test]
+    fn respect_the_frame_and_depth_limits() {
+        let mut ogg = ogg_page(&[b"\x01vorbis".to_vec()]);
+        ogg.extend(ogg_page(&[comments(b"\x03vorbis", &[])]));
+        let mut m4a = atom(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        m4a.extend(atom(b"moov", &atom(b"udta", &[])));
+        let inputs = [
+            (ogg, Limits::none().with_max_frames(1), Limit::Frames(1)),
+            (m4a, Limits::none().with_max_depth(1), Limit::Depth(1)),
+        ];
+
+        for (file, limits, limit) in inputs.iter().cloned() {
+            let context = ThumbnailContext::new().with_limits(limits);
+
+            let err = AudioProvider::new()
+                .get_thumbnail_with_context(
+                    Cursor::new(file),
+                    &context,
+                    Dimensions::square(16),
+                )
+                .unwrap_err();
+
+            assert!(
+                matches!(err, ThumbnailError::LimitExceeded(l) if l == limit),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn ape_tags_before_an_id3v1_tag() {
+        let mut items = Vec::new();
+        for (key, value) in &[
+            (&b"Title"[..], b"A Song".to_vec()),
+            (
+                &b"Cover Art (Front)"[..],
+                [&b"cover.png\0"[..], &png(32, 32, RED)].concat(),
+            ),
+        ] {
+            items.extend(&(value.len() as u32).to_le_bytes());
+            let flags: u32 = if key.starts_with(b"Cover") { 1 << 1 } else { 0 };
+            items.extend(&flags.to_le_bytes());
+            items.extend(*key);
+            items.push(0);
+            items.extend(value);
+        }
+
+        let mut ape = b"MAC \x96\x0f".to_vec();
+        ape.extend(&[0; 64]);
+        ape.extend(&items);
+        ape.extend(b"APETAGEX");
+        ape.extend(&2000_u32.to_le_bytes());
+        ape.extend(&(items.len() as u32 + 32).to_le_bytes());
+        ape.extend(&2_u32.to_le_bytes());
+        ape.extend(&[0; 12]);
+        ape.extend(b"TAG");
+        ape.extend(&[0; 125]);
+
+        assert_eq!(*thumbnail(&ape).get_pixel(8, 8), RED);
+    }
+
+    #[test]
+    fn files_without_cover_art_are_unsupported() {
+        let mp3 = id3v2_3(&[(b"TIT2", b"\x00A Song".to_vec())]);
+
+        let err = AudioProvider::new()
+            .get_thumbnail(&mp3[..], Dimensions::square(16))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Unsupported(_)));
+    }
+
+    #[test]
+    fn truncated_extended_headers_are_unsupported() {
+        let mp3 = b"ID3\x03\x00\x40\x00\x00\x00\x02AB";
+
+        let err = AudioProvider::new()
+            .get_thumbnail(&mp3[..], Dimensions::square(16))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Unsupported(_)));
+    }
+
+    #[test]
+    fn registered_for_audio_files() {
+        let mp3 = id3v2_3(&[(b"APIC", apic(0, &png(32, 32, BLUE)))]);
+        let registry = ProviderRegistry::with_builtin_providers();
+
+        let got = registry
+            .thumbnail_for(
+                &mut &mp3[..],
+                &Hint::default().with_file_name("song.mp3"),
+                Dimensions::square(16),
+            )
+            .unwrap();
+
+        assert_eq!(*got.get_pixel(8, 8), BLUE);
+    }
+}
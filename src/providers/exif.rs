@@ -0,0 +1,381 @@
+use crate::{
+    providers::{
+        get_thumbnail_buffered,
+        tiff::{
+            self, Orientation, Tiff, JPEG_INTERCHANGE_FORMAT,
+            JPEG_INTERCHANGE_FORMAT_LENGTH, ORIENTATION,
+        },
+        ImageProvider,
+    },
+    Dimensions, Limits, ProviderRegistry, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use image::{io::Reader, ImageFormat, RgbaImage};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// A [`ThumbnailProvider`] for JPEG and TIFF photos which uses the preview
+/// embedded in their EXIF metadata when it is large enough, falling back to
+/// decoding the whole image.
+///
+/// Either way, the thumbnail is rotated according to the EXIF orientation.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ExifProvider {
+    fallback: ImageProvider,
+}
+
+impl ExifProvider {
+    pub fn new() -> Self { ExifProvider::default() }
+
+    /// Register an [`ExifProvider`] for JPEG and TIFF images, taking priority
+    /// over the [`ImageProvider`].
+    pub fn register(registry: &mut ProviderRegistry) {
+        registry
+            .register(ExifProvider::new())
+            .mime_type("image/jpeg")
+            .extension("jpg")
+            .extension("jpeg")
+            .extension("jpe")
+            .extension("jfif")
+            .priority(1);
+
+        #[cfg(feature = "tiff")]
+        registry
+            .register(ExifProvider::new())
+            .mime_type("image/tiff")
+            .extension("tif")
+            .extension("tiff")
+            .priority(1);
+    }
+}
+
+impl ThumbnailProvider for ExifProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        // the metadata can be anywhere in the file, so we need random access
+        get_thumbnail_buffered(self, input, desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let context = ThumbnailContext::new();
+        self.get_thumbnail_with_context(input, &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        mut input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let start = input.stream_position()?;
+        // broken metadata shouldn't stop us from decoding the image itself
+        let exif = Exif::read(&mut input, &context.limits).unwrap_or_default();
+        context.check()?;
+
+        // thumbnails are generated sideways and rotated afterwards
+        let dims = if exif.orientation.swaps_axes() {
+            Dimensions {
+                width: desired_dimensions.height,
+                height: desired_dimensions.width,
+                ..desired_dimensions
+            }
+        } else {
+            desired_dimensions
+        };
+
+        if let Some(preview) = exif.preview {
+            if let Some(image) = decode_preview(&preview, context, dims)? {
+                let thumbnail = crate::resize(&image, dims);
+                return Ok(exif.orientation.apply(thumbnail));
+            }
+        }
+
+        input.seek(SeekFrom::Start(start))?;
+        let thumbnail = self
+            .fallback
+            .get_thumbnail_with_context(input, context, dims)?;
+
+        Ok(exif.orientation.apply(thumbnail))
+    }
+}
+
+/// The bits of EXIF metadata we care about.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Exif {
+    pub(crate) orientation: Orientation,
+    /// The embedded JPEG thumbnail.
+    preview: Option<Vec<u8>>,
+}
+
+impl Exif {
+    /// Read the metadata from a JPEG or TIFF image.
+    pub(crate) fn read<R>(
+        input: &mut R,
+        limits: &Limits,
+    ) -> Result<Exif, ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let start = input.stream_position()?;
+        let mut header = [0; 4];
+        input.read_exact(&mut header)?;
+        input.seek(SeekFrom::Start(start))?;
+
+        if tiff::is_tiff(&header) {
+            Exif::from_tiff(Tiff::new(input)?, limits)
+        } else if header.starts_with(&[0xFF, 0xD8]) {
+            match find_exif_segment(input)? {
+                Some(segment) => {
+                    Exif::from_tiff(Tiff::new(Cursor::new(segment))?, limits)
+                },
+                None => Ok(Exif::default()),
+            }
+        } else {
+            Ok(Exif::default())
+        }
+    }
+
+    fn from_tiff<R>(
+        mut tiff: Tiff<R>,
+        limits: &Limits,
+    ) -> Result<Exif, ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let ifd0 = tiff.read_ifd(tiff.first_ifd())?;
+        let orientation = tiff
+            .tag(&ifd0, ORIENTATION)?
+            .map(Orientation::from_tag)
+            .unwrap_or_default();
+
+        // the thumbnail is always in IFD1
+        let mut preview = None;
+
+        if ifd0.next != 0 {
+            let ifd1 = tiff.read_ifd(ifd0.next)?;
+            let offset = tiff.tag(&ifd1, JPEG_INTERCHANGE_FORMAT)?;
+            let len = tiff.tag(&ifd1, JPEG_INTERCHANGE_FORMAT_LENGTH)?;
+
+            if let (Some(offset), Some(len)) = (offset, len) {
+                let jpeg = tiff.read_bytes(offset, len, limits)?;
+                if jpeg.starts_with(&[0xFF, 0xD8]) {
+                    preview = Some(jpeg);
+                }
+            }
+        }
+
+        Ok(Exif {
+            orientation,
+            preview,
+        })
+    }
+}
+
+/// Find the `APP1` segment containing EXIF metadata in a JPEG file,
+/// returning the TIFF structure inside it.
+fn find_exif_segment<R: Read>(
+    input: &mut R,
+) -> Result<Option<Vec<u8>>, ThumbnailError> {
+    let mut marker = [0; 2];
+    input.read_exact(&mut marker)?;
+
+    loop {
+        input.read_exact(&mut marker)?;
+
+        match marker {
+            // markers without a length
+            [0xFF, 0x01] | [0xFF, 0xD0..=0xD7] => continue,
+            // we've reached the image data without finding anything
+            [0xFF, 0xDA] | [0xFF, 0xD9] => return Ok(None),
+            [0xFF, _] => {},
+            _ => return Err(ThumbnailError::corrupt("Invalid JPEG marker")),
+        }
+
+        let mut len = [0; 2];
+        input.read_exact(&mut len)?;
+        let len = usize::from(u16::from_be_bytes(len)).saturating_sub(2);
+        let mut segment = vec![0; len];
+        input.read_exact(&mut segment)?;
+
+        if marker[1] == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            segment.drain(..6);
+            return Ok(Some(segment));
+        }
+    }
+}
+
+/// Decode the embedded preview, if it's big enough to generate a thumbnail
+/// without scaling it up.
+fn decode_preview(
+    jpeg: &[u8],
+    context: &ThumbnailContext,
+    desired_dimensions: Dimensions,
+) -> Result<Option<RgbaImage>, ThumbnailError> {
+    let dimensions = Reader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg)
+        .into_dimensions();
+    let (width, height) = match dimensions {
+        Ok(dimensions) => dimensions,
+        Err(_) => return Ok(None),
+    };
+
+    if !desired_dimensions.is_satisfied_by(width, height) {
+        return Ok(None);
+    }
+
+    context.limits.check_dimensions(width, height)?;
+
+    let mut reader = Reader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg);
+    reader.limits(context.limits.to_image_limits());
+
+    match reader.decode() {
+        Ok(image) => Ok(Some(image.into_rgba8())),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::providers::tiff::tests::{build_tiff, LONG, SHORT};
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// Encode a solid colour as a JPEG.
+    pub(crate) fn jpeg(width: u32, height: u32, colour: Rgba<u8>) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, colour);
+        let rgb = image::DynamicImage::ImageRgba8(image).into_rgb8();
+        let mut buffer = Cursor::new(Vec::new());
+        rgb.write_to(&mut buffer, ImageFormat::Jpeg).unwrap();
+        buffer.into_inner()
+    }
+
+    /// Insert an EXIF segment containing an orientation and embedded
+    /// preview.
+    fn with_exif(jpeg: &[u8], orientation: u32, preview: &[u8]) -> Vec<u8> {
+        let preview_len = preview.len() as u32;
+        let ifds: [&[(u16, u16, &[u32])]; 2] = [
+            &[(ORIENTATION, SHORT, &[orientation])],
+            &[
+                (JPEG_INTERCHANGE_FORMAT, LONG, &[0]),
+                (JPEG_INTERCHANGE_FORMAT_LENGTH, LONG, &[preview_len]),
+            ],
+        ];
+        // the preview goes at the end, so work out where that is
+        let offset = build_tiff(&ifds, &[]).len() as u32;
+        let ifds: [&[(u16, u16, &[u32])]; 2] = [
+            ifds[0],
+            &[
+                (JPEG_INTERCHANGE_FORMAT, LONG, &[offset]),
+                (JPEG_INTERCHANGE_FORMAT_LENGTH, LONG, &[preview_len]),
+            ],
+        ];
+        let tiff = build_tiff(&ifds, preview);
+
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend(tiff);
+
+        let mut file = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        file.extend(&(segment.len() as u16 + 2).to_be_bytes());
+        file.extend(segment);
+        file.extend(&jpeg[2..]);
+        file
+    }
+
+    /// Compare colours, allowing for JPEG artifacts.
+    pub(crate) fn is_close(actual: &Rgba<u8>, expected: Rgba<u8>) -> bool {
+        actual
+            .0
+            .iter()
+            .zip(expected.0.iter())
+            .all(|(&a, &b)| (i32::from(a) - i32::from(b)).abs() < 16)
+    }
+
+    #[test]
+    fn use_the_preview_when_it_is_large_enough() {
+        let file = with_exif(&jpeg(64, 32, RED), 1, &jpeg(32, 16, BLUE));
+
+        let got = ExifProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(16))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 8));
+        assert!(is_close(got.get_pixel(8, 4), BLUE));
+    }
+
+    #[test]
+    fn decode_the_image_when_the_preview_is_too_small() {
+        let file = with_exif(&jpeg(64, 32, RED), 1, &jpeg(32, 16, BLUE));
+
+        let got = ExifProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(48))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (48, 24));
+        assert!(is_close(got.get_pixel(24, 12), RED));
+    }
+
+    #[test]
+    fn rotate_according_to_the_orientation() {
+        let file = with_exif(&jpeg(64, 32, RED), 6, &jpeg(32, 16, BLUE));
+
+        let from_preview = ExifProvider::new()
+            .get_thumbnail(&file[..], Dimensions::new(8, 16))
+            .unwrap();
+        assert_eq!(from_preview.dimensions(), (8, 16));
+        assert!(is_close(from_preview.get_pixel(4, 8), BLUE));
+
+        let decoded = ExifProvider::new()
+            .get_thumbnail(&file[..], Dimensions::new(128, 128))
+            .unwrap();
+        assert_eq!(decoded.dimensions(), (32, 64));
+        assert!(is_close(decoded.get_pixel(16, 32), RED));
+    }
+
+    #[test]
+    fn images_without_metadata_are_decoded() {
+        let file = jpeg(64, 32, RED);
+
+        let got = ExifProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(16))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 8));
+        assert!(is_close(got.get_pixel(8, 4), RED));
+    }
+
+    #[test]
+    fn registered_ahead_of_the_image_provider() {
+        let file = with_exif(&jpeg(64, 32, RED), 8, &jpeg(32, 16, BLUE));
+        let registry = ProviderRegistry::with_builtin_providers();
+
+        let got = registry
+            .thumbnail_for(
+                &mut &file[..],
+                &crate::Hint::default().with_file_name("photo.jpg"),
+                Dimensions::square(16),
+            )
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (8, 16));
+    }
+}
@@ -0,0 +1,70 @@
+//! Built-in [`ThumbnailProvider`][crate::ThumbnailProvider] implementations.
+
+mod audio;
+#[cfg(feature = "jpeg")]
+mod exif;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod raster;
+#[cfg(feature = "jpeg")]
+mod raw;
+#[cfg(feature = "svg")]
+mod svg;
+#[cfg(feature = "jpeg")]
+mod tiff;
+
+use crate::{
+    Dimensions, ProviderRegistry, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use std::io::{Cursor, Read};
+
+pub use audio::AudioProvider;
+#[cfg(feature = "jpeg")]
+pub use exif::ExifProvider;
+#[cfg(feature = "pdf")]
+pub use pdf::PdfProvider;
+pub use raster::{ImageProvider, SupportedFormat};
+#[cfg(feature = "jpeg")]
+pub use raw::RawProvider;
+#[cfg(feature = "svg")]
+pub use svg::SvgProvider;
+
+/// Register all the providers built into this crate.
+pub fn register_builtin(registry: &mut ProviderRegistry) {
+    ImageProvider::register_formats(registry);
+    #[cfg(feature = "jpeg")]
+    ExifProvider::register(registry);
+    #[cfg(feature = "jpeg")]
+    RawProvider::register(registry);
+    #[cfg(feature = "svg")]
+    SvgProvider::register(registry);
+    #[cfg(feature = "pdf")]
+    PdfProvider::register(registry);
+    AudioProvider::register(registry);
+}
+
+/// Read the whole input into memory (respecting the default [`Limits`]) for
+/// providers which need random access.
+///
+/// [`Limits`]: crate::Limits
+fn get_thumbnail_buffered<P, R>(
+    provider: &P,
+    mut input: R,
+    desired_dimensions: Dimensions,
+) -> Result<P::Thumbnail, ThumbnailError>
+where
+    P: ThumbnailProvider<Error = ThumbnailError>,
+    R: Read,
+{
+    let context = ThumbnailContext::new();
+
+    let mut buffer = Vec::new();
+    context.limits.reader(&mut input).read_to_end(&mut buffer)?;
+
+    provider.get_thumbnail_with_context(
+        Cursor::new(buffer),
+        &context,
+        desired_dimensions,
+    )
+}
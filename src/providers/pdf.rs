@@ -0,0 +1,921 @@
+use crate::{
+    Dimensions, Limits, ProviderRegistry, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use hayro_syntax::{
+    object::{Name, Object, Rect, Stream},
+    page::{Page, Rotation},
+    Filter, LoadPdfError, Pdf,
+};
+use image::{imageops, io::Reader, ImageFormat, Rgba, RgbaImage};
+use std::{
+    borrow::Cow,
+    io::{self, Cursor, Read, Seek},
+};
+
+/// A [`ThumbnailProvider`] for PDF documents which generates a thumbnail of
+/// the first page.
+///
+/// Embedded images are used where possible, because they are much cheaper
+/// than rendering the page:
+///
+/// 1. If the page is nothing but a single image covering the whole page (e.g. a
+///    scanned document), that image is used
+/// 2. If the page has a thumbnail (its `/Thumb` entry) which is large enough,
+///    the thumbnail is used
+/// 3. Otherwise the page is rendered using [`hayro`], if the `pdf-render`
+///    feature is enabled
+///
+/// Without the `pdf-render` feature, an embedded thumbnail will be used even
+/// when it is smaller than requested.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PdfProvider;
+
+impl PdfProvider {
+    pub fn new() -> Self { PdfProvider }
+
+    /// Register a [`PdfProvider`] for PDF documents.
+    pub fn register(registry: &mut ProviderRegistry) {
+        registry
+            .register(PdfProvider::new())
+            .mime_type("application/pdf")
+            .extension("pdf");
+    }
+
+    fn thumbnail(
+        &self,
+        mut input: impl Read,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, ThumbnailError> {
+        // the cross-reference table is at the end of the file, so we need the
+        // whole thing
+        let mut document = Vec::new();
+        context
+            .limits
+            .reader(&mut input)
+            .read_to_end(&mut document)?;
+
+        let pdf = Pdf::new(document).map_err(|e| match e {
+            LoadPdfError::Decryption(_) => {
+                ThumbnailError::unsupported("Encrypted PDFs aren't supported")
+            },
+            LoadPdfError::Invalid => ThumbnailError::corrupt("Invalid PDF"),
+        })?;
+        context.check()?;
+
+        // we only ever look at the first page
+        context.limits.check_frames(1)?;
+        let page = pdf
+            .pages()
+            .first()
+            .ok_or_else(|| ThumbnailError::corrupt("The PDF has no pages"))?;
+
+        // images are drawn sideways and rotated afterwards
+        let rotation = page.rotation();
+        let dims = if swaps_axes(rotation) {
+            Dimensions {
+                width: desired_dimensions.height,
+                height: desired_dimensions.width,
+                ..desired_dimensions
+            }
+        } else {
+            desired_dimensions
+        };
+
+        if let Some(image) = page_image(page) {
+            if let Some(image) = decode_image(&image, context)? {
+                let thumbnail = crate::resize(&image, dims);
+                return Ok(rotate(thumbnail, rotation));
+            }
+        }
+        context.check()?;
+
+        let embedded = match page.raw().get::<Stream<'_>>(b"Thumb") {
+            Some(thumb) => decode_image(&thumb, context)?,
+            None => None,
+        };
+        context.check()?;
+
+        match embedded {
+            Some(image)
+                if cfg!(not(feature = "pdf-render"))
+                    || dims.is_satisfied_by(image.width(), image.height()) =>
+            {
+                let thumbnail = crate::resize(&image, dims);
+                Ok(rotate(thumbnail, rotation))
+            },
+            #[cfg(feature = "pdf-render")]
+            _ => {
+                let image = render::render(page, context, desired_dimensions)?;
+                Ok(crate::resize(&image, desired_dimensions))
+            },
+            #[cfg(not(feature = "pdf-render"))]
+            _ => Err(ThumbnailError::unsupported(
+                "The PDF has no embedded thumbnail, and rendering is disabled",
+            )),
+        }
+    }
+}
+
+impl ThumbnailProvider for PdfProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        self.thumbnail(input, &ThumbnailContext::new(), desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        self.thumbnail(input, context, desired_dimensions)
+    }
+}
+
+/// Will the page's width and height be swapped when it is displayed?
+fn swaps_axes(rotation: Rotation) -> bool {
+    matches!(rotation, Rotation::Horizontal | Rotation::FlippedHorizontal)
+}
+
+fn rotate(image: RgbaImage, rotation: Rotation) -> RgbaImage {
+    match rotation {
+        Rotation::None => image,
+        Rotation::Horizontal => imageops::rotate90(&image),
+        Rotation::Flipped => imageops::rotate180(&image),
+        Rotation::FlippedHorizontal => imageops::rotate270(&image),
+    }
+}
+
+/// Find the image drawn by a page whose content is nothing but that image
+/// stretched over the whole page, as is the case for scanned documents.
+fn page_image<'a>(page: &Page<'a>) -> Option<Stream<'a>> {
+    let mut operations = page.operations();
+    let mut image = None;
+    let mut ctm = IDENTITY;
+    let mut saved = Vec::new();
+
+    while let Some(instruction) = operations.next() {
+        match &**instruction.operator {
+            b"q" => saved.push(ctm),
+            b"Q" => ctm = saved.pop()?,
+            b"cm" => {
+                let mut matrix = [0.0; 6];
+                let mut operands = instruction.operands();
+                for value in &mut matrix {
+                    *value = match operands.next()? {
+                        Object::Number(number) => number.as_f64(),
+                        _ => return None,
+                    };
+                }
+                ctm = concatenate(matrix, ctm);
+            },
+            b"gs" => {},
+            b"Do" if image.is_none() => {
+                if !fills_page(ctm, page.media_box()) {
+                    return None;
+                }
+
+                let name = match instruction.operands().next() {
+                    Some(Object::Name(name)) => name.clone(),
+                    _ => return None,
+                };
+                let xobject = page.resources().get_x_object(&name)?;
+                let subtype = xobject.dict().get::<Name<'_>>(b"Subtype")?;
+
+                if subtype.as_str() != "Image" {
+                    return None;
+                }
+                image = Some(xobject);
+            },
+            _ => return None,
+        }
+    }
+
+    image
+}
+
+/// A transformation matrix, `[a b c d e f]`.
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Apply `matrix` on top of `ctm`, as the `cm` operator does.
+fn concatenate(matrix: Matrix, ctm: Matrix) -> Matrix {
+    let [a, b, c, d, e, f] = matrix;
+    [
+        a * ctm[0] + b * ctm[2],
+        a * ctm[1] + b * ctm[3],
+        c * ctm[0] + d * ctm[2],
+        c * ctm[1] + d * ctm[3],
+        e * ctm[0] + f * ctm[2] + ctm[4],
+        e * ctm[1] + f * ctm[3] + ctm[5],
+    ]
+}
+
+/// Would an image drawn with this transform cover the page without being
+/// rotated or flipped?
+fn fills_page(ctm: Matrix, media_box: Rect) -> bool {
+    // allow for rounding errors
+    const TOLERANCE: f64 = 1.0;
+
+    // images are drawn onto the unit square
+    let [a, b, c, d, e, f] = ctm;
+    let (x0, y0, x1, y1) = (e, f, a + e, d + f);
+
+    b == 0.0
+        && c == 0.0
+        && a > 0.0
+        && d > 0.0
+        && x0 <= media_box.x0 + TOLERANCE
+        && y0 <= media_box.y0 + TOLERANCE
+        && x1 >= media_box.x1 - TOLERANCE
+        && y1 >= media_box.y1 - TOLERANCE
+}
+
+/// Decode an image XObject or thumbnail, returning `None` if it uses a
+/// feature we don't support.
+fn decode_image(
+    stream: &Stream<'_>,
+    context: &ThumbnailContext,
+) -> Result<Option<RgbaImage>, ThumbnailError> {
+    let dict = stream.dict();
+    let (width, height) = match (dict.get::<u32>(b"Width"), dict.get(b"Height"))
+    {
+        (Some(width), Some(height)) if width > 0 && height > 0 => {
+            (width, height)
+        },
+        _ => return Ok(None),
+    };
+    if dict.get::<bool>(b"ImageMask") == Some(true) {
+        return Ok(None);
+    }
+
+    context.limits.check_dimensions(width, height)?;
+
+    let filters = stream.filters();
+
+    if filters.as_slice() == [Filter::DctDecode] {
+        let jpeg = stream.raw_data();
+        let mut reader =
+            Reader::with_format(Cursor::new(&jpeg[..]), ImageFormat::Jpeg);
+        reader.limits(context.limits.to_image_limits());
+
+        return match reader.decode() {
+            Ok(image) => Ok(Some(image.into_rgba8())),
+            Err(_) => Ok(None),
+        };
+    }
+
+    let colour_space = match dict
+        .get::<Object<'_>>(b"ColorSpace")
+        .and_then(|object| ColourSpace::from_object(object, &context.limits))
+    {
+        Some(colour_space) => colour_space,
+        None => return Ok(None),
+    };
+    let bits = dict.get::<u8>(b"BitsPerComponent").unwrap_or(8);
+
+    match decode_stream(stream, &context.limits)? {
+        Some(samples) => {
+            Ok(colour_space.to_rgba(&samples, width, height, bits))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Apply a stream's filters, returning `None` if they aren't supported.
+///
+/// [`Stream::decoded()`] has no upper bound, so a tiny FlateDecode stream
+/// could expand into gigabytes. Instead, we measure how much FlateDecode will
+/// output (without keeping it) and the other filters can only expand their
+/// input so much, so the allocation limit is checked before anything is
+/// decoded.
+fn decode_stream<'a>(
+    stream: &Stream<'a>,
+    limits: &Limits,
+) -> Result<Option<Cow<'a, [u8]>>, ThumbnailError> {
+    let data = stream.raw_data();
+    let mut len = data.len() as u64;
+
+    for (i, filter) in stream.filters().iter().enumerate() {
+        len = match filter {
+            // we can only measure the compressed data we have been given, and
+            // LZW is only found in ancient files so isn't worth the effort
+            Filter::FlateDecode if i == 0 => {
+                match inflated_len(&data, limits.max_alloc) {
+                    Some(len) => len,
+                    None => return Ok(None),
+                }
+            },
+            Filter::AsciiHexDecode => len.div_ceil(2),
+            // "z" is shorthand for 4 zeroes
+            Filter::Ascii85Decode => len.saturating_mul(4),
+            // a length byte followed by a byte to repeat up to 128 times
+            Filter::RunLengthDecode => len.saturating_mul(64),
+            _ => return Ok(None),
+        };
+        limits.check_alloc(len)?;
+    }
+
+    Ok(stream.decoded().ok())
+}
+
+/// Find out how long a FlateDecode stream is once decompressed, giving up
+/// once it is longer than `max_len`.
+fn inflated_len(data: &[u8], max_len: Option<u64>) -> Option<u64> {
+    let limit = max_len.map_or(u64::MAX, |max_len| max_len.saturating_add(1));
+    let measure = |decoder: &mut dyn Read| {
+        io::copy(&mut decoder.take(limit), &mut io::sink())
+    };
+
+    // some streams are missing the zlib header
+    measure(&mut ZlibDecoder::new(data))
+        .or_else(|_| measure(&mut DeflateDecoder::new(data)))
+        .ok()
+}
+
+/// The colour spaces we know how to convert to RGBA.
+#[derive(Debug, Clone, PartialEq)]
+enum ColourSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A palette of colours in the base colour space, with one byte per
+    /// component.
+    Indexed {
+        base: Box<ColourSpace>,
+        palette: Vec<u8>,
+    },
+}
+
+impl ColourSpace {
+    fn from_object(object: Object<'_>, limits: &Limits) -> Option<ColourSpace> {
+        let array = match object {
+            Object::Name(name) => {
+                return match name.as_str() {
+                    "DeviceGray" | "G" => Some(ColourSpace::Gray),
+                    "DeviceRGB" | "RGB" => Some(ColourSpace::Rgb),
+                    "DeviceCMYK" | "CMYK" => Some(ColourSpace::Cmyk),
+                    _ => None,
+                };
+            },
+            Object::Array(array) => array,
+            _ => return None,
+        };
+
+        let mut items = array.iter::<Object<'_>>();
+        let family = items.next()?.into_name()?;
+
+        match family.as_str() {
+            "CalGray" => Some(ColourSpace::Gray),
+            "CalRGB" => Some(ColourSpace::Rgb),
+            "ICCBased" => {
+                let profile = items.next()?.into_stream()?;
+                match profile.dict().get::<u8>(b"N")? {
+                    1 => Some(ColourSpace::Gray),
+                    3 => Some(ColourSpace::Rgb),
+                    4 => Some(ColourSpace::Cmyk),
+                    _ => None,
+                }
+            },
+            "Indexed" | "I" => {
+                let base = ColourSpace::from_object(items.next()?, limits)?;
+                if let ColourSpace::Indexed { .. } = base {
+                    return None;
+                }
+                let _max_index = items.next()?;
+                let palette = match items.next()? {
+                    Object::String(palette) => palette.as_bytes().to_vec(),
+                    Object::Stream(palette) => {
+                        decode_stream(&palette, limits).ok()??.to_vec()
+                    },
+                    _ => return None,
+                };
+
+                Some(ColourSpace::Indexed {
+                    base: Box::new(base),
+                    palette,
+                })
+            },
+            _ => None,
+        }
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            ColourSpace::Gray | ColourSpace::Indexed { .. } => 1,
+            ColourSpace::Rgb => 3,
+            ColourSpace::Cmyk => 4,
+        }
+    }
+
+    /// Convert packed samples to an image, where each row starts on a byte
+    /// boundary.
+    fn to_rgba(
+        &self,
+        samples: &[u8],
+        width: u32,
+        height: u32,
+        bits: u8,
+    ) -> Option<RgbaImage> {
+        if !matches!(bits, 1 | 2 | 4 | 8) {
+            return None;
+        }
+
+        let components = self.components();
+        let bits = usize::from(bits);
+        let row_len = (width as usize * components * bits).div_ceil(8);
+        if samples.len() < row_len * height as usize {
+            return None;
+        }
+
+        let mut image = RgbaImage::new(width, height);
+        let mut values = [0; 4];
+
+        for (y, row) in samples
+            .chunks_exact(row_len)
+            .take(height as usize)
+            .enumerate()
+        {
+            for x in 0..width as usize {
+                for (c, value) in values[..components].iter_mut().enumerate() {
+                    *value = sample(row, x * components + c, bits);
+                }
+                let colour = self.colour(&values[..components], bits);
+                image.put_pixel(x as u32, y as u32, colour);
+            }
+        }
+
+        Some(image)
+    }
+
+    fn colour(&self, values: &[u8], bits: usize) -> Rgba<u8> {
+        let max = (1_u32 << bits) - 1;
+        let scale = |value: u8| (u32::from(value) * 255 / max) as u8;
+
+        match self {
+            ColourSpace::Gray => {
+                let gray = scale(values[0]);
+                Rgba([gray, gray, gray, 255])
+            },
+            ColourSpace::Rgb => Rgba([
+                scale(values[0]),
+                scale(values[1]),
+                scale(values[2]),
+                255,
+            ]),
+            ColourSpace::Cmyk => {
+                let black = 255 - u32::from(scale(values[3]));
+                let channel = |value: u8| {
+                    ((255 - u32::from(scale(value))) * black / 255) as u8
+                };
+                Rgba([
+                    channel(values[0]),
+                    channel(values[1]),
+                    channel(values[2]),
+                    255,
+                ])
+            },
+            ColourSpace::Indexed { base, palette } => {
+                let len = base.components();
+                let start = usize::from(values[0]) * len;
+
+                match palette.get(start..start + len) {
+                    Some(entry) => base.colour(entry, 8),
+                    None => Rgba([0, 0, 0, 255]),
+                }
+            },
+        }
+    }
+}
+
+/// Read the `index`'th sample from a row of packed samples.
+fn sample(row: &[u8], index: usize, bits: usize) -> u8 {
+    if bits == 8 {
+        return row[index];
+    }
+
+    let bit = index * bits;
+    let shift = 8 - bits - bit % 8;
+    (row[bit / 8] >> shift) & ((1 << bits) - 1) as u8
+}
+
+#[cfg(feature = "pdf-render")]
+mod render {
+    use crate::{Dimensions, ThumbnailContext, ThumbnailError};
+    use hayro::{
+        hayro_interpret::InterpreterSettings,
+        vello_cpu::{color::palette::css::WHITE, peniko::ImageAlphaType},
+        PixmapSettings, RenderCache, RenderSettings,
+    };
+    use hayro_syntax::page::Page;
+    use image::RgbaImage;
+
+    /// Render a page at roughly the size of the thumbnail.
+    pub(super) fn render(
+        page: &Page<'_>,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, ThumbnailError> {
+        let (width, height) = page.render_dimensions();
+        // pages don't have a natural resolution, so always fill the box
+        let (scaled_width, scaled_height) = desired_dimensions.scaled_to_fill(
+            width.round().max(1.0) as u32,
+            height.round().max(1.0) as u32,
+        );
+
+        context
+            .limits
+            .check_dimensions(scaled_width, scaled_height)?;
+        if scaled_width > u32::from(u16::MAX)
+            || scaled_height > u32::from(u16::MAX)
+        {
+            return Err(ThumbnailError::too_large(format!(
+                "Unable to render a {}x{} page",
+                scaled_width, scaled_height
+            )));
+        }
+
+        // the renderer truncates, so aim for the middle of the last pixel
+        let settings = PixmapSettings {
+            x_scale: (scaled_width as f32 + 0.5) / width,
+            y_scale: (scaled_height as f32 + 0.5) / height,
+            bg_color: WHITE,
+        };
+        let pixmap = hayro::render(
+            page,
+            &RenderCache::new(),
+            &InterpreterSettings::default(),
+            &RenderSettings::default(),
+            &settings,
+        );
+        context.check()?;
+
+        let (width, height) = (pixmap.width(), pixmap.height());
+        let pixels = pixmap.take_rgba8(ImageAlphaType::Alpha);
+
+        RgbaImage::from_raw(width.into(), height.into(), pixels).ok_or_else(
+            || ThumbnailError::internal("The rendered page was the wrong size"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        providers::exif::tests::{is_close, jpeg},
+        CancellationToken, Hint, Limit, Limits,
+    };
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    /// Assemble a PDF from its objects, where the first object is the
+    /// catalog.
+    fn pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.7\n".to_vec();
+        let mut offsets = Vec::new();
+
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend(object);
+            pdf.extend(b"\nendobj\n");
+        }
+
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+        pdf.extend(b"0000000000 65535 f \n");
+        for offset in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+
+        pdf
+    }
+
+    fn stream(dict: &str, data: &[u8]) -> Vec<u8> {
+        let mut object =
+            format!("<< {} /Length {} >>\nstream\n", dict, data.len())
+                .into_bytes();
+        object.extend(data);
+        object.extend(b"\nendstream");
+        object
+    }
+
+    /// A PDF with a single page, where `page` is extra entries for the page
+    /// dictionary and objects 5 onwards can be referenced from it.
+    fn single_page(page: &str, content: &str, extra: &[Vec<u8>]) -> Vec<u8> {
+        let mut objects = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 100] \
+                 /Contents 4 0 R {} >>",
+                page
+            )
+            .into_bytes(),
+            stream("", content.as_bytes()),
+        ];
+        objects.extend(extra.iter().cloned());
+        pdf(&objects)
+    }
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn scanned_page(rotate: u32) -> Vec<u8> {
+        page_with_image(rotate, "q 200 0 0 100 0 0 cm /Im0 Do Q")
+    }
+
+    /// A 200x100 page which draws a 64x32 image using `content`.
+    fn page_with_image(rotate: u32, content: &str) -> Vec<u8> {
+        single_page(
+            &format!(
+                "/Rotate {} /Resources << /XObject << /Im0 5 0 R >> >>",
+                rotate
+            ),
+            content,
+            &[stream(
+                "/Type /XObject /Subtype /Image /Width 64 /Height 32 \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode",
+                &jpeg(64, 32, RED),
+            )],
+        )
+    }
+
+    #[test]
+    fn use_the_image_on_a_scanned_page() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&scanned_page(0)[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+        assert!(is_close(got.get_pixel(16, 8), RED));
+    }
+
+    #[test]
+    fn respect_the_frame_limit() {
+        let context = ThumbnailContext::new()
+            .with_limits(Limits::none().with_max_frames(0));
+
+        let err = PdfProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(scanned_page(0)),
+                &context,
+                Dimensions::square(32),
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ThumbnailError::LimitExceeded(Limit::Frames(0))
+        ));
+    }
+
+    #[test]
+    fn rotate_scanned_pages() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&scanned_page(90)[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 32));
+
+        let got = PdfProvider::new()
+            .get_thumbnail(&scanned_page(90)[..], Dimensions::new(32, 16))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (8, 16));
+    }
+
+    #[test]
+    fn the_image_must_fill_the_page() {
+        let inputs = [
+            ("q 2 0 0 2 0 0 cm q 100 0 0 50 0 0 cm /Im0 Do Q Q", true),
+            ("q 202 0 0 102 -1 -1 cm /Im0 Do Q", true),
+            ("/Im0 Do", false),
+            ("q 100 0 0 50 0 0 cm /Im0 Do Q", false),
+            ("q 2 0 0 2 0 0 cm Q q 100 0 0 50 0 0 cm /Im0 Do Q", false),
+            ("q 200 0 0 -100 0 100 cm /Im0 Do Q", false),
+            ("q 0 100 -200 0 200 0 cm /Im0 Do Q", false),
+        ];
+
+        for (content, should_use) in inputs {
+            let pdf = Pdf::new(page_with_image(0, content)).unwrap();
+            let page = &pdf.pages()[0];
+
+            assert_eq!(page_image(page).is_some(), should_use, "{}", content);
+        }
+    }
+
+    /// A page with vector content and a 4x2 thumbnail which uses an indexed
+    /// colour space.
+    fn page_with_thumbnail(rotate: u32) -> Vec<u8> {
+        let thumbnail = [0_u8, 1, 1, 1, 1, 1, 1, 0];
+        single_page(
+            &format!("/Rotate {} /Thumb 5 0 R", rotate),
+            "1 0 0 rg 0 0 200 100 re f",
+            &[stream(
+                "/Width 4 /Height 2 /BitsPerComponent 8 \
+                 /ColorSpace [/Indexed /DeviceRGB 1 <FF0000 0000FF>]",
+                &thumbnail,
+            )],
+        )
+    }
+
+    #[test]
+    fn use_the_embedded_thumbnail() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&page_with_thumbnail(0)[..], Dimensions::square(4))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (4, 2));
+        assert_eq!(*got.get_pixel(0, 0), RED);
+        assert_eq!(*got.get_pixel(1, 0), BLUE);
+        assert_eq!(*got.get_pixel(3, 1), RED);
+    }
+
+    #[test]
+    fn rotate_embedded_thumbnails() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&page_with_thumbnail(90)[..], Dimensions::square(4))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (2, 4));
+        assert_eq!(*got.get_pixel(0, 0), BLUE);
+        assert_eq!(*got.get_pixel(1, 0), RED);
+    }
+
+    #[test]
+    #[cfg(not(feature = "pdf-render"))]
+    fn small_thumbnails_are_used_when_rendering_is_disabled() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&page_with_thumbnail(0)[..], Dimensions::square(64))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (4, 2));
+    }
+
+    #[test]
+    #[cfg(not(feature = "pdf-render"))]
+    fn pages_without_images_are_unsupported_when_rendering_is_disabled() {
+        let pdf = single_page("", "1 0 0 rg 0 0 200 100 re f", &[]);
+
+        let err = PdfProvider::new()
+            .get_thumbnail(&pdf[..], Dimensions::square(64))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Unsupported(_)));
+    }
+
+    #[test]
+    #[cfg(feature = "pdf-render")]
+    fn render_the_page_when_the_thumbnail_is_too_small() {
+        let got = PdfProvider::new()
+            .get_thumbnail(&page_with_thumbnail(0)[..], Dimensions::square(64))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (64, 32));
+        assert!(is_close(got.get_pixel(32, 16), RED));
+    }
+
+    #[test]
+    #[cfg(feature = "pdf-render")]
+    fn render_pages_on_a_white_background() {
+        let pdf = single_page("", "0 0 1 rg 0 0 100 100 re f", &[]);
+
+        let got = PdfProvider::new()
+            .get_thumbnail(&pdf[..], Dimensions::new(100, 100))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (100, 50));
+        assert!(is_close(got.get_pixel(25, 25), BLUE));
+        assert!(is_close(got.get_pixel(75, 25), Rgba([255, 255, 255, 255])));
+    }
+
+    /// A page with a 4x2 red thumbnail compressed using FlateDecode, with
+    /// `padding` extra bytes at the end.
+    fn page_with_compressed_thumbnail(padding: usize) -> Vec<u8> {
+        let mut samples = [255_u8, 0, 0].repeat(8);
+        samples.resize(samples.len() + padding, 0);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&samples).unwrap();
+
+        single_page(
+            "/Thumb 5 0 R",
+            "1 0 0 rg 0 0 200 100 re f",
+            &[stream(
+                "/Width 4 /Height 2 /BitsPerComponent 8 \
+                 /ColorSpace /DeviceRGB /Filter /FlateDecode",
+                &encoder.finish().unwrap(),
+            )],
+        )
+    }
+
+    #[test]
+    fn decode_compressed_thumbnails() {
+        let got = PdfProvider::new()
+            .get_thumbnail(
+                &page_with_compressed_thumbnail(0)[..],
+                Dimensions::square(4),
+            )
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (4, 2));
+        assert_eq!(*got.get_pixel(3, 1), RED);
+    }
+
+    #[test]
+    fn compressed_streams_count_towards_the_allocation_limit() {
+        let pdf = page_with_compressed_thumbnail(1024 * 1024);
+        let context = ThumbnailContext::new()
+            .with_limits(Limits::none().with_max_alloc(64 * 1024));
+
+        let err = PdfProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(pdf),
+                &context,
+                Dimensions::square(4),
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ThumbnailError::LimitExceeded(Limit::Allocation(65536))
+        ));
+    }
+
+    #[test]
+    fn decode_packed_samples() {
+        let image = ColourSpace::Gray
+            .to_rgba(&[0b1010_0000, 0b0101_0000], 4, 2, 1)
+            .unwrap();
+
+        assert_eq!(*image.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(1, 1), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn stop_when_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let context = ThumbnailContext::new().with_cancellation(token);
+
+        let err = PdfProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(scanned_page(0)),
+                &context,
+                Dimensions::square(32),
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Cancelled));
+    }
+
+    #[test]
+    fn invalid_documents_are_corrupt() {
+        let err = PdfProvider::new()
+            .get_thumbnail(&b"%PDF-1.7\nnot really"[..], Dimensions::square(32))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Corrupt(_)));
+    }
+
+    #[test]
+    fn registered_for_pdfs() {
+        let registry = ProviderRegistry::with_builtin_providers();
+
+        let got = registry
+            .thumbnail_for(
+                &mut &scanned_page(0)[..],
+                &Hint::default().with_file_name("scan.pdf"),
+                Dimensions::square(32),
+            )
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::{
+    Dimensions, Interruptible, Limit, ProviderRegistry, ThumbnailContext,
+    ThumbnailError, ThumbnailProvider,
+};
+use image::{
+    error::LimitErrorKind, io::Reader, ImageError, ImageFormat, RgbaImage,
+};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+
+/// A [`ThumbnailProvider`] for raster images, backed by the [`image`] crate.
+///
+/// Each format is enabled by the cargo feature with the same name (e.g.
+/// `png` or `jpeg`), and [`ImageProvider::supported_formats()`] lists the
+/// formats this crate was compiled with.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ImageProvider {
+    format: Option<ImageFormat>,
+}
+
+impl ImageProvider {
+    /// Create an [`ImageProvider`] which detects the format from the image's
+    /// contents.
+    pub fn new() -> Self { ImageProvider::default() }
+
+    /// Create an [`ImageProvider`] which will always decode images as a
+    /// particular format.
+    ///
+    /// This is necessary for formats like TGA which can't be detected from
+    /// their contents.
+    pub fn with_format(format: ImageFormat) -> Self {
+        ImageProvider {
+            format: Some(format),
+        }
+    }
+
+    pub fn format(&self) -> Option<ImageFormat> { self.format }
+
+    /// The image formats which can be decoded.
+    pub fn supported_formats() -> &'static [SupportedFormat] {
+        SUPPORTED_FORMATS
+    }
+
+    /// Register a provider for each of the
+    /// [`ImageProvider::supported_formats()`], associated with the format's
+    /// MIME types and file extensions.
+    pub fn register_formats(registry: &mut ProviderRegistry) {
+        for supported in ImageProvider::supported_formats() {
+            let mut registration =
+                registry.register(ImageProvider::with_format(supported.format));
+
+            for &mime_type in supported.mime_types {
+                registration = registration.mime_type(mime_type);
+            }
+            for &extension in supported.extensions {
+                registration = registration.extension(extension);
+            }
+        }
+    }
+
+    fn reader<R>(&self, input: R) -> Result<Reader<R>, ImageError>
+    where
+        R: BufRead + Seek,
+    {
+        let mut reader = Reader::new(input);
+        match self.format {
+            Some(format) => reader.set_format(format),
+            None => reader = reader.with_guessed_format()?,
+        }
+
+        Ok(reader)
+    }
+
+    fn decode<R>(
+        &self,
+        mut input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, ThumbnailError>
+    where
+        R: BufRead + Seek,
+    {
+        let fail = |e| to_thumbnail_error(e, context);
+
+        // make sure the image isn't unreasonably large before decoding it
+        let start = input.stream_position()?;
+        let (width, height) = self
+            .reader(&mut input)
+            .and_then(Reader::into_dimensions)
+            .map_err(fail)?;
+        context.limits.check_dimensions(width, height)?;
+        input.seek(SeekFrom::Start(start))?;
+
+        let mut reader = self.reader(input).map_err(fail)?;
+        reader.limits(context.limits.to_image_limits());
+        let image = reader.decode().map_err(fail)?.into_rgba8();
+        context.check()?;
+
+        Ok(crate::resize(&image, desired_dimensions))
+    }
+}
+
+fn to_thumbnail_error(
+    error: ImageError,
+    context: &ThumbnailContext,
+) -> ThumbnailError {
+    // not every decoder passes through the error from an interrupted read
+    if let Err(e) = context.check() {
+        return e;
+    }
+
+    match (&error, context.limits.max_alloc) {
+        (ImageError::Limits(e), Some(max_alloc))
+            if e.kind() == LimitErrorKind::InsufficientMemory =>
+        {
+            Limit::Allocation(max_alloc).into()
+        },
+        _ => error.into(),
+    }
+}
+
+impl ThumbnailProvider for ImageProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        mut input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        let context = ThumbnailContext::new();
+
+        // the decoders need random access, so read everything into memory
+        let mut buffer = Vec::new();
+        context.limits.reader(&mut input).read_to_end(&mut buffer)?;
+
+        self.decode(Cursor::new(buffer), &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let context = ThumbnailContext::new();
+        self.get_thumbnail_with_context(input, &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let input = context.limits.reader(input);
+        let input = BufReader::new(Interruptible::new(input, context));
+        self.decode(input, context, desired_dimensions)
+    }
+}
+
+/// An image format which [`ImageProvider`] can decode.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SupportedFormat {
+    pub format: ImageFormat,
+    pub mime_types: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+}
+
+const SUPPORTED_FORMATS: &[SupportedFormat] = &[
+    #[cfg(feature = "png")]
+    SupportedFormat {
+        format: ImageFormat::Png,
+        mime_types: &["image/png"],
+        extensions: &["png"],
+    },
+    #[cfg(feature = "jpeg")]
+    SupportedFormat {
+        format: ImageFormat::Jpeg,
+        mime_types: &["image/jpeg"],
+        extensions: &["jpg", "jpeg", "jpe", "jfif"],
+    },
+    #[cfg(feature = "gif")]
+    SupportedFormat {
+        format: ImageFormat::Gif,
+        mime_types: &["image/gif"],
+        extensions: &["gif"],
+    },
+    #[cfg(feature = "bmp")]
+    SupportedFormat {
+        format: ImageFormat::Bmp,
+        mime_types: &["image/bmp", "image/x-bmp"],
+        extensions: &["bmp", "dib"],
+    },
+    #[cfg(feature = "tiff")]
+    SupportedFormat {
+        format: ImageFormat::Tiff,
+        mime_types: &["image/tiff"],
+        extensions: &["tif", "tiff"],
+    },
+    #[cfg(feature = "webp")]
+    SupportedFormat {
+        format: ImageFormat::WebP,
+        mime_types: &["image/webp"],
+        extensions: &["webp"],
+    },
+    #[cfg(feature = "ico")]
+    SupportedFormat {
+        format: ImageFormat::Ico,
+        mime_types: &["image/vnd.microsoft.icon", "image/x-icon"],
+        extensions: &["ico"],
+    },
+    #[cfg(feature = "tga")]
+    SupportedFormat {
+        format: ImageFormat::Tga,
+        mime_types: &["image/x-tga", "image/x-targa"],
+        extensions: &["tga", "icb", "vda", "vst"],
+    },
+    #[cfg(feature = "pnm")]
+    SupportedFormat {
+        format: ImageFormat::Pnm,
+        mime_types: &[
+            "image/x-portable-anymap",
+            "image/x-portable-bitmap",
+            "image/x-portable-graymap",
+            "image/x-portable-pixmap",
+            "image/x-portable-arbitrarymap",
+        ],
+        extensions: &["pnm", "pbm", "pgm", "ppm", "pam"],
+    },
+    #[cfg(feature = "qoi")]
+    SupportedFormat {
+        format: ImageFormat::Qoi,
+        mime_types: &["image/qoi", "image/x-qoi"],
+        extensions: &["qoi"],
+    },
+    #[cfg(feature = "hdr")]
+    SupportedFormat {
+        format: ImageFormat::Hdr,
+        mime_types: &["image/vnd.radiance"],
+        extensions: &["hdr", "pic"],
+    },
+];
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::{CancellationToken, Limits};
+    use image::Rgba;
+    use std::{io, time::Instant};
+
+    fn encode(image: &RgbaImage, format: ImageFormat) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        image.write_to(&mut buffer, format).unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn downsize_a_png() {
+        let original = RgbaImage::from_pixel(64, 32, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let dims = Dimensions::new(16, 16);
+
+        let got = ImageProvider::new().get_thumbnail(&png[..], dims).unwrap();
+
+        assert_eq!(got.dimensions(), (16, 8));
+        assert_eq!(*got.get_pixel(0, 0), Rgba([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn decode_from_a_seekable_reader() {
+        let original = RgbaImage::from_pixel(64, 32, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let dims = Dimensions::new(16, 16);
+
+        let got = ImageProvider::new()
+            .get_thumbnail_seekable(Cursor::new(png), dims)
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 8));
+    }
+
+    #[test]
+    fn small_images_are_left_alone() {
+        let original = RgbaImage::from_pixel(8, 4, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let dims = Dimensions::new(16, 16);
+
+        let got = ImageProvider::new().get_thumbnail(&png[..], dims).unwrap();
+
+        assert_eq!(got, original);
+    }
+
+    #[test]
+    #[cfg(feature = "tga")]
+    fn formats_without_magic_bytes_need_to_be_explicit() {
+        let original = RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 4]));
+        let tga = encode(&original, ImageFormat::Tga);
+        let dims = Dimensions::new(16, 16);
+
+        assert!(ImageProvider::new().get_thumbnail(&tga[..], dims).is_err());
+
+        let got = ImageProvider::with_format(ImageFormat::Tga)
+            .get_thumbnail(&tga[..], dims)
+            .unwrap();
+        assert_eq!(got, original);
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn registry_falls_back_to_the_real_format() {
+        use crate::Hint;
+
+        let original = RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 255]));
+        let png = encode(&original, ImageFormat::Png);
+        let mut registry = ProviderRegistry::new();
+        ImageProvider::register_formats(&mut registry);
+        let hint = Hint::default().with_file_name("actually-a-png.jpg");
+        let dims = Dimensions::new(16, 16);
+
+        let got = registry.thumbnail_for(&mut &png[..], &hint, dims).unwrap();
+
+        assert_eq!(got, original);
+    }
+
+    /// A reader which cancels a token once it has handed out `limit` bytes.
+    struct CancelAfter<R> {
+        inner: R,
+        limit: usize,
+        token: CancellationToken,
+    }
+
+    impl<R: Read> Read for CancelAfter<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.limit);
+            let bytes_read = self.inner.read(&mut buf[..len])?;
+            self.limit -= bytes_read;
+            if self.limit == 0 {
+                self.token.cancel();
+            }
+
+            Ok(bytes_read)
+        }
+    }
+
+    impl<R: Seek> Seek for CancelAfter<R> {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn stop_decoding_when_cancelled() {
+        let original = RgbaImage::from_pixel(256, 256, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let token = CancellationToken::new();
+        let context = ThumbnailContext::new().with_cancellation(token.clone());
+        let input = CancelAfter {
+            inner: Cursor::new(&png),
+            limit: png.len() / 2,
+            token,
+        };
+
+        let err = ImageProvider::new()
+            .get_thumbnail_with_context(
+                input,
+                &context,
+                Dimensions::new(16, 16),
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Cancelled), "{:?}", err);
+    }
+
+    #[test]
+    #[cfg(feature = "bmp")]
+    fn reject_decompression_bombs_before_decoding() {
+        // the headers for a 60000 x 60000 BMP, without any pixel data
+        let mut bomb = Vec::new();
+        bomb.extend(b"BM");
+        bomb.extend(&54_u32.to_le_bytes()); // file size
+        bomb.extend(&0_u32.to_le_bytes()); // reserved
+        bomb.extend(&54_u32.to_le_bytes()); // pixel data offset
+        bomb.extend(&40_u32.to_le_bytes()); // header size
+        bomb.extend(&60_000_i32.to_le_bytes()); // width
+        bomb.extend(&60_000_i32.to_le_bytes()); // height
+        bomb.extend(&1_u16.to_le_bytes()); // planes
+        bomb.extend(&24_u16.to_le_bytes()); // bits per pixel
+        bomb.extend(&[0; 24]); // no compression, default everything else
+
+        let err = ImageProvider::new()
+            .get_thumbnail(&bomb[..], Dimensions::new(16, 16))
+            .unwrap_err();
+
+        assert!(
+            matches!(err, ThumbnailError::LimitExceeded(Limit::Pixels(_))),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn inputs_can_be_too_long() {
+        let original = RgbaImage::from_pixel(8, 4, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let limits = Limits::none().with_max_input_bytes(png.len() as u64 / 2);
+        let context = ThumbnailContext::new().with_limits(limits);
+
+        let err = ImageProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(&png),
+                &context,
+                Dimensions::new(16, 16),
+            )
+            .unwrap_err();
+
+        assert!(
+            matches!(err, ThumbnailError::LimitExceeded(Limit::InputBytes(_))),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn give_up_after_the_deadline() {
+        let original = RgbaImage::from_pixel(8, 4, Rgba([1, 2, 3, 4]));
+        let png = encode(&original, ImageFormat::Png);
+        let context = ThumbnailContext::new().with_deadline(Instant::now());
+
+        let err = ImageProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(png),
+                &context,
+                Dimensions::new(16, 16),
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::TimedOut), "{:?}", err);
+    }
+}
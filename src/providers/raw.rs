@@ -0,0 +1,944 @@
+use crate::{
+    providers::{
+        exif::Exif,
+        get_thumbnail_buffered,
+        tiff::{
+            self, ByteOrder, Ifd, Orientation, Tiff, COMPRESSION, EXIF_IFD,
+            JPEG_INTERCHANGE_FORMAT, JPEG_INTERCHANGE_FORMAT_LENGTH,
+            MAKER_NOTE, ORIENTATION, STRIP_BYTE_COUNTS, STRIP_OFFSETS,
+            SUB_IFDS,
+        },
+    },
+    Dimensions, Limits, ProviderRegistry, ThumbnailContext, ThumbnailError,
+    ThumbnailProvider,
+};
+use image::{io::Reader, ImageFormat, RgbaImage};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+/// A [`ThumbnailProvider`] for camera RAW files (CR2, CR3, NEF, ARW, DNG, RAF
+/// and ORF) which uses the JPEG previews embedded by the camera instead of
+/// demosaicing the sensor data.
+///
+/// The smallest preview which is large enough for the requested dimensions
+/// is used, or the largest one if none of them are, and the thumbnail is
+/// rotated according to the file's orientation.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RawProvider;
+
+impl RawProvider {
+    pub fn new() -> Self { RawProvider }
+
+    /// Register a [`RawProvider`] for all the RAW formats it understands.
+    pub fn register(registry: &mut ProviderRegistry) {
+        let mut registration = registry.register(RawProvider::new());
+
+        for &mime_type in MIME_TYPES {
+            registration = registration.mime_type(mime_type);
+        }
+        for &extension in EXTENSIONS {
+            registration = registration.extension(extension);
+        }
+
+        // DNG files are valid TIFFs, so make sure we're used instead of the
+        // ImageProvider
+        registration.priority(2);
+    }
+}
+
+const MIME_TYPES: &[&str] = &[
+    "image/x-canon-cr2",
+    "image/x-canon-cr3",
+    "image/x-nikon-nef",
+    "image/x-sony-arw",
+    "image/x-adobe-dng",
+    "image/x-fuji-raf",
+    "image/x-olympus-orf",
+];
+const EXTENSIONS: &[&str] = &["cr2", "cr3", "nef", "arw", "dng", "raf", "orf"];
+
+impl ThumbnailProvider for RawProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        // previews can be anywhere in the file, so we need random access
+        get_thumbnail_buffered(self, input, desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let context = ThumbnailContext::new();
+        self.get_thumbnail_with_context(input, &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        mut input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let container = Container::read(&mut input, &context.limits)?;
+        context.check()?;
+
+        let mut previews = Vec::new();
+        for preview in container.previews {
+            if let Some(dimensions) = preview.measure(&mut input)? {
+                previews.push((preview, dimensions));
+            }
+        }
+        context.check()?;
+
+        // some formats (e.g. RAF) only record the orientation in the
+        // preview's own metadata
+        let orientation = match (container.orientation, previews.first()) {
+            (Some(orientation), _) => orientation,
+            (None, Some((preview, _))) => {
+                input.seek(SeekFrom::Start(preview.position))?;
+                Exif::read(&mut input, &context.limits)
+                    .map(|exif| exif.orientation)
+                    .unwrap_or_default()
+            },
+            (None, None) => Orientation::NORMAL,
+        };
+
+        // thumbnails are generated sideways and rotated afterwards
+        let dims = if orientation.swaps_axes() {
+            Dimensions {
+                width: desired_dimensions.height,
+                height: desired_dimensions.width,
+                ..desired_dimensions
+            }
+        } else {
+            desired_dimensions
+        };
+
+        let (preview, (width, height)) = choose_preview(&previews, dims)
+            .ok_or_else(|| {
+                ThumbnailError::unsupported("No embedded preview was found")
+            })?;
+
+        context.limits.check_dimensions(width, height)?;
+        let jpeg = preview.read(&mut input, context)?;
+        context.check()?;
+
+        let mut reader =
+            Reader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg);
+        reader.limits(context.limits.to_image_limits());
+        let image = reader.decode()?.into_rgba8();
+        context.check()?;
+
+        let thumbnail = crate::resize(&image, dims);
+        Ok(orientation.apply(thumbnail))
+    }
+}
+
+/// Pick the smallest preview which doesn't need to be scaled up, or the
+/// largest one if they're all too small.
+fn choose_preview(
+    previews: &[(Preview, (u32, u32))],
+    desired_dimensions: Dimensions,
+) -> Option<(Preview, (u32, u32))> {
+    let pixels = |&(_, (width, height)): &(Preview, (u32, u32))| {
+        u64::from(width) * u64::from(height)
+    };
+    let large_enough = |&&(_, (width, height)): &&(Preview, (u32, u32))| {
+        desired_dimensions.is_satisfied_by(width, height)
+    };
+
+    previews
+        .iter()
+        .filter(large_enough)
+        .min_by_key(|preview| pixels(preview))
+        .or_else(|| previews.iter().max_by_key(|preview| pixels(preview)))
+        .copied()
+}
+
+/// A JPEG embedded somewhere in the file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Preview {
+    /// Where the JPEG starts, relative to the start of the input.
+    position: u64,
+    len: u64,
+}
+
+impl Preview {
+    /// Get the preview's dimensions without decoding it, returning `None`
+    /// if it isn't something we can decode.
+    fn measure<R>(
+        &self,
+        input: &mut R,
+    ) -> Result<Option<(u32, u32)>, ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        input.seek(SeekFrom::Start(self.position))?;
+
+        match jpeg_dimensions(&mut input.take(self.len)) {
+            Ok(dimensions) => Ok(dimensions),
+            // the preview is truncated or points past the end of the file
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read<R>(
+        &self,
+        input: &mut R,
+        context: &ThumbnailContext,
+    ) -> Result<Vec<u8>, ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        context.limits.check_alloc(self.len)?;
+
+        input.seek(SeekFrom::Start(self.position))?;
+        let mut buffer = vec![0; self.len as usize];
+        input.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+}
+
+/// Read a JPEG's dimensions from its frame header, returning `None` if it
+/// isn't an 8-bit baseline or progressive JPEG (e.g. the lossless JPEG some
+/// cameras use for sensor data).
+fn jpeg_dimensions<R: Read>(input: &mut R) -> io::Result<Option<(u32, u32)>> {
+    let mut marker = [0; 2];
+    input.read_exact(&mut marker)?;
+    if marker != [0xFF, 0xD8] {
+        return Ok(None);
+    }
+
+    loop {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        if byte[0] != 0xFF {
+            return Ok(None);
+        }
+
+        // markers may be padded with any number of 0xFF fill bytes
+        while byte[0] == 0xFF {
+            input.read_exact(&mut byte)?;
+        }
+        let marker = byte[0];
+
+        match marker {
+            // markers without a length
+            0x01 | 0xD0..=0xD7 => continue,
+            // we've reached the image data without finding a frame header
+            0xDA | 0xD9 => return Ok(None),
+            _ => {},
+        }
+
+        let mut len = [0; 2];
+        input.read_exact(&mut len)?;
+        let len = u64::from(u16::from_be_bytes(len)).saturating_sub(2);
+
+        match marker {
+            // baseline, extended sequential and progressive
+            0xC0..=0xC2 => {
+                let mut header = [0; 5];
+                input.read_exact(&mut header)?;
+                let precision = header[0];
+                let height = u16::from_be_bytes([header[1], header[2]]);
+                let width = u16::from_be_bytes([header[3], header[4]]);
+
+                return match (precision, width, height) {
+                    (8, 1..=u16::MAX, 1..=u16::MAX) => {
+                        Ok(Some((u32::from(width), u32::from(height))))
+                    },
+                    _ => Ok(None),
+                };
+            },
+            // lossless, hierarchical and arithmetic coding
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+            _ => {
+                io::copy(&mut input.by_ref().take(len), &mut io::sink())?;
+            },
+        }
+    }
+}
+
+/// The most IFDs we'll look at, so a corrupt file can't keep us busy.
+const MAX_IFDS: usize = 64;
+/// How deeply we'll descend into the boxes in an ISO-BMFF file.
+const MAX_BOX_DEPTH: u32 = 4;
+
+/// Olympus maker note tags.
+const OLYMPUS_CAMERA_SETTINGS: u16 = 0x2020;
+const OLYMPUS_PREVIEW_START: u16 = 0x0101;
+const OLYMPUS_PREVIEW_LENGTH: u16 = 0x0102;
+
+/// The `uuid` box in a CR3's `moov` box which contains Canon's metadata and
+/// the small thumbnail.
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xC0, 0xB6, 0x87, 0x82, 0x0F, 0x11, 0xE0, 0x81, 0x11, 0xF4, 0xCE,
+    0x46, 0x2B, 0x6A, 0x48,
+];
+/// The top-level `uuid` box in a CR3 which contains the medium-sized
+/// preview.
+const PREVIEW_UUID: [u8; 16] = [
+    0xEA, 0xF4, 0x2B, 0x5E, 0x1C, 0x98, 0x4B, 0x88, 0xB9, 0xFB, 0xB7, 0xDC,
+    0x40, 0x6E, 0x4D, 0x16,
+];
+
+/// Where the previews are in a RAW file, and how it should be rotated.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Container {
+    previews: Vec<Preview>,
+    orientation: Option<Orientation>,
+}
+
+impl Container {
+    fn read<R>(
+        input: &mut R,
+        limits: &Limits,
+    ) -> Result<Container, ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let start = input.stream_position()?;
+        let mut header = [0; 16];
+        input.read_exact(&mut header)?;
+        input.seek(SeekFrom::Start(start))?;
+
+        let mut container = Container::default();
+
+        if header.starts_with(b"FUJIFILMCCD-RAW") {
+            container.read_raf(input, start)?;
+        } else if tiff::is_tiff_like(&header) {
+            container.read_tiff(&mut Tiff::new(input)?, limits)?;
+        } else if &header[4..8] == b"ftyp" {
+            let end = input.seek(SeekFrom::End(0))?;
+            container.read_boxes(input, start, end, 0, limits)?;
+        } else {
+            return Err(ThumbnailError::unsupported("Unrecognised RAW format"));
+        }
+
+        Ok(container)
+    }
+
+    /// Fujifilm's RAF files have a big-endian header with the offset and
+    /// length of a JPEG.
+    fn read_raf<R>(
+        &mut self,
+        input: &mut R,
+        start: u64,
+    ) -> Result<(), ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let mut header = [0; 8];
+        input.seek(SeekFrom::Start(start + 84))?;
+        input.read_exact(&mut header)?;
+
+        let offset =
+            u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let len =
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        self.previews.push(Preview {
+            position: start + u64::from(offset),
+            len: u64::from(len),
+        });
+
+        Ok(())
+    }
+
+    /// Walk every IFD in a TIFF-based format (CR2, NEF, ARW, DNG and ORF),
+    /// looking for JPEGs.
+    fn read_tiff<R>(
+        &mut self,
+        tiff: &mut Tiff<R>,
+        limits: &Limits,
+    ) -> Result<(), ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let first_ifd = tiff.first_ifd();
+        let mut pending = vec![first_ifd];
+        let mut visited = Vec::new();
+
+        while let Some(offset) = pending.pop() {
+            if offset == 0 || visited.contains(&offset) {
+                continue;
+            }
+            if visited.len() >= MAX_IFDS {
+                break;
+            }
+            visited.push(offset);
+            limits.check_frames(visited.len() as u32)?;
+
+            match self.read_ifd(tiff, offset, &mut pending) {
+                Ok(()) => {},
+                Err(e) if offset == first_ifd => return Err(e),
+                Err(e @ ThumbnailError::LimitExceeded(_)) => return Err(e),
+                // a broken IFD shouldn't throw away the previews we found in
+                // the others
+                Err(_) => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look for previews in an IFD, adding the IFDs it links to to
+    /// `pending`.
+    fn read_ifd<R>(
+        &mut self,
+        tiff: &mut Tiff<R>,
+        offset: u32,
+        pending: &mut Vec<u32>,
+    ) -> Result<(), ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        let ifd = tiff.read_ifd(offset)?;
+        pending.push(ifd.next);
+
+        if offset == tiff.first_ifd() {
+            self.orientation =
+                tiff.tag(&ifd, ORIENTATION)?.map(Orientation::from_tag);
+        }
+        if let Some(entry) = ifd.get(SUB_IFDS) {
+            pending.extend(tiff.values(entry).unwrap_or_default());
+        }
+        if let Some(entry) = ifd.get(EXIF_IFD) {
+            pending.extend(tiff.ifd_offset(entry).ok());
+        }
+
+        if let Some(preview) = ifd_preview(tiff, &ifd)? {
+            self.previews.push(preview);
+        }
+        if let Some(entry) = ifd.get(MAKER_NOTE).filter(|e| e.count >= 12) {
+            let start = tiff.position(tiff.data_offset(entry));
+            if let Some(preview) = olympus_preview(tiff.reader(), start)? {
+                self.previews.push(preview);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Walk the boxes in an ISO-BMFF file (CR3), looking for Canon's `THMB`
+    /// and `PRVW` previews and the `CMT1` box containing IFD0.
+    fn read_boxes<R>(
+        &mut self,
+        input: &mut R,
+        start: u64,
+        end: u64,
+        depth: u32,
+        limits: &Limits,
+    ) -> Result<(), ThumbnailError>
+    where
+        R: Read + Seek,
+    {
+        limits.check_depth(depth)?;
+        if depth > MAX_BOX_DEPTH {
+            return Ok(());
+        }
+
+        let mut position = start;
+
+        while position.saturating_add(8) <= end {
+            input.seek(SeekFrom::Start(position))?;
+            let mut header = [0; 8];
+            input.read_exact(&mut header)?;
+            let size = u32::from_be_bytes([
+                header[0], header[1], header[2], header[3],
+            ]);
+            let kind = [header[4], header[5], header[6], header[7]];
+
+            let (header_len, size) = match size {
+                0 => (8, end - position),
+                1 => {
+                    let mut size = [0; 8];
+                    input.read_exact(&mut size)?;
+                    (16, u64::from_be_bytes(size))
+                },
+                size => (8, u64::from(size)),
+            };
+            if size < header_len || size > end - position {
+                return Err(ThumbnailError::corrupt("Invalid box size"));
+            }
+
+            let body = position + header_len;
+            let body_end = position + size;
+
+            match &kind {
+                b"moov" => {
+                    self.read_boxes(input, body, body_end, depth + 1, limits)?
+                },
+                b"uuid" if body_end - body >= 16 => {
+                    let mut uuid = [0; 16];
+                    input.read_exact(&mut uuid)?;
+
+                    if uuid == CANON_UUID {
+                        self.read_boxes(
+                            input,
+                            body + 16,
+                            body_end,
+                            depth + 1,
+                            limits,
+                        )?;
+                    } else if uuid == PREVIEW_UUID {
+                        // the PRVW box comes after 8 unknown bytes
+                        self.read_boxes(
+                            input,
+                            body + 24,
+                            body_end,
+                            depth + 1,
+                            limits,
+                        )?;
+                    }
+                },
+                b"THMB" | b"PRVW" => {
+                    if let Some(preview) = find_jpeg(input, body, body_end)? {
+                        self.previews.push(preview);
+                    }
+                },
+                b"CMT1" => {
+                    let mut tiff = Tiff::new(&mut *input)?;
+                    let ifd0 = tiff.read_ifd(tiff.first_ifd())?;
+                    self.orientation = tiff
+                        .tag(&ifd0, ORIENTATION)?
+                        .map(Orientation::from_tag);
+                },
+                _ => {},
+            }
+
+            position = body_end;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the JPEG referenced by an IFD, either as an EXIF-style thumbnail or
+/// the single strip of a JPEG-compressed image.
+fn ifd_preview<R>(
+    tiff: &mut Tiff<R>,
+    ifd: &Ifd,
+) -> Result<Option<Preview>, ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let offset = tiff.tag(ifd, JPEG_INTERCHANGE_FORMAT)?;
+    let len = tiff.tag(ifd, JPEG_INTERCHANGE_FORMAT_LENGTH)?;
+
+    if let (Some(offset), Some(len)) = (offset, len) {
+        return Ok(Some(Preview {
+            position: tiff.position(offset),
+            len: u64::from(len),
+        }));
+    }
+
+    // old-style and new-style JPEG compression, as used by CR2 and DNG
+    match tiff.tag(ifd, COMPRESSION)? {
+        Some(6) | Some(7) => {},
+        _ => return Ok(None),
+    }
+
+    match (ifd.get(STRIP_OFFSETS), ifd.get(STRIP_BYTE_COUNTS)) {
+        (Some(offset), Some(len)) if offset.count == 1 && len.count == 1 => {
+            let offset = tiff.value(offset)?;
+            Ok(Some(Preview {
+                position: tiff.position(offset),
+                len: u64::from(tiff.value(len)?),
+            }))
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Olympus cameras put their preview in the maker notes, which are a TIFF
+/// structure of their own with offsets relative to where they start.
+fn olympus_preview<R>(
+    input: &mut R,
+    start: u64,
+) -> Result<Option<Preview>, ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let mut header = [0; 12];
+    input.seek(SeekFrom::Start(start))?;
+    input.read_exact(&mut header)?;
+
+    if !header.starts_with(b"OLYMPUS\0") {
+        return Ok(None);
+    }
+    let byte_order = match ByteOrder::from_marker(&header[8..]) {
+        Some(byte_order) => byte_order,
+        None => return Ok(None),
+    };
+
+    let mut notes = Tiff::at(input, start, byte_order, 12);
+    let ifd = notes.read_ifd(notes.first_ifd())?;
+    let camera_settings = match ifd.get(OLYMPUS_CAMERA_SETTINGS) {
+        Some(entry) => notes.ifd_offset(entry)?,
+        None => return Ok(None),
+    };
+    let camera_settings = notes.read_ifd(camera_settings)?;
+
+    let offset = notes.tag(&camera_settings, OLYMPUS_PREVIEW_START)?;
+    let len = notes.tag(&camera_settings, OLYMPUS_PREVIEW_LENGTH)?;
+
+    match (offset, len) {
+        (Some(offset), Some(len)) if len > 0 => Ok(Some(Preview {
+            position: notes.position(offset),
+            len: u64::from(len),
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Canon's preview boxes start with a small header before the JPEG, so look
+/// for the start of the JPEG.
+fn find_jpeg<R>(
+    input: &mut R,
+    start: u64,
+    end: u64,
+) -> Result<Option<Preview>, ThumbnailError>
+where
+    R: Read + Seek,
+{
+    let mut header = Vec::new();
+    input.seek(SeekFrom::Start(start))?;
+    input.take(64.min(end - start)).read_to_end(&mut header)?;
+
+    let offset = header
+        .windows(3)
+        .position(|window| window == [0xFF, 0xD8, 0xFF]);
+
+    Ok(offset.map(|offset| {
+        let position = start + offset as u64;
+        Preview {
+            position,
+            len: end - position,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        providers::{
+            exif::tests::{is_close, jpeg},
+            tiff::tests::{build_tiff, LONG, SHORT, UNDEFINED},
+        },
+        Hint, Limit,
+    };
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// A DNG-ish file with a small thumbnail in IFD0 and larger previews in
+    /// the IFDs after it, all stored after the IFDs.
+    fn dng(orientation: u32, previews: &[&[u8]]) -> Vec<u8> {
+        let build = |offsets: &[u32]| {
+            let mut ifds: Vec<Vec<(u16, u16, Vec<u32>)>> = Vec::new();
+            ifds.push(vec![
+                (ORIENTATION, SHORT, vec![orientation]),
+                (JPEG_INTERCHANGE_FORMAT, LONG, vec![offsets[0]]),
+                (
+                    JPEG_INTERCHANGE_FORMAT_LENGTH,
+                    LONG,
+                    vec![previews[0].len() as u32],
+                ),
+            ]);
+            for (i, preview) in previews.iter().enumerate().skip(1) {
+                ifds.push(vec![
+                    (COMPRESSION, SHORT, vec![7]),
+                    (STRIP_OFFSETS, LONG, vec![offsets[i]]),
+                    (STRIP_BYTE_COUNTS, LONG, vec![preview.len() as u32]),
+                ]);
+            }
+            let ifds: Vec<Vec<(u16, u16, &[u32])>> = ifds
+                .iter()
+                .map(|ifd| {
+                    ifd.iter()
+                        .map(|(tag, kind, values)| (*tag, *kind, &values[..]))
+                        .collect()
+                })
+                .collect();
+            let ifds: Vec<&[(u16, u16, &[u32])]> =
+                ifds.iter().map(|ifd| &ifd[..]).collect();
+
+            build_tiff(&ifds, &previews.concat())
+        };
+
+        // the previews go at the end, so work out where that is
+        let placeholder = vec![0; previews.len()];
+        let header_len = build(&placeholder).len()
+            - previews.iter().map(|p| p.len()).sum::<usize>();
+        let mut offsets = Vec::new();
+        let mut offset = header_len as u32;
+        for preview in previews {
+            offsets.push(offset);
+            offset += preview.len() as u32;
+        }
+
+        build(&offsets)
+    }
+
+    #[test]
+    fn use_the_smallest_preview_which_is_large_enough() {
+        let file = dng(
+            1,
+            &[
+                &jpeg(16, 8, RED),
+                &jpeg(64, 32, GREEN),
+                &jpeg(256, 128, BLUE),
+            ],
+        );
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+        assert!(is_close(got.get_pixel(16, 8), GREEN));
+    }
+
+    #[test]
+    fn use_the_largest_preview_when_they_are_all_too_small() {
+        let file = dng(1, &[&jpeg(16, 8, RED), &jpeg(64, 32, GREEN)]);
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(128))
+            .unwrap();
+
+        assert!(is_close(got.get_pixel(16, 8), GREEN));
+    }
+
+    #[test]
+    fn rotate_according_to_the_orientation() {
+        let file = dng(6, &[&jpeg(16, 8, RED), &jpeg(64, 32, GREEN)]);
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::new(16, 32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 32));
+        assert!(is_close(got.get_pixel(8, 16), GREEN));
+    }
+
+    #[test]
+    fn broken_ifds_dont_hide_the_other_previews() {
+        let preview = jpeg(16, 8, RED);
+        let len = preview.len() as u32;
+        let build = |offset: u32| {
+            let ifds: [&[(u16, u16, &[u32])]; 1] = [&[
+                (JPEG_INTERCHANGE_FORMAT, LONG, &[offset]),
+                (JPEG_INTERCHANGE_FORMAT_LENGTH, LONG, &[len]),
+                (SUB_IFDS, LONG, &[0x00FF_FFFF]),
+                (EXIF_IFD, LONG, &[0x00FF_FFF0]),
+            ]];
+            build_tiff(&ifds, &preview)
+        };
+        // the preview goes at the end, so work out where that is
+        let offset = (build(0).len() - preview.len()) as u32;
+        let file = build(offset);
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(16))
+            .unwrap();
+
+        assert!(is_close(got.get_pixel(4, 2), RED));
+    }
+
+    #[test]
+    fn skip_fill_bytes_before_markers() {
+        let mut padded = jpeg(16, 8, RED);
+        padded.splice(2..2, [0xFF; 3]);
+
+        let got = jpeg_dimensions(&mut &padded[..]).unwrap();
+
+        assert_eq!(got, Some((16, 8)));
+    }
+
+    #[test]
+    fn skip_lossless_jpegs() {
+        // a JPEG whose frame header says it is lossless, like CR2 sensor data
+        let mut lossless = jpeg(256, 128, BLUE);
+        let sof = lossless
+            .windows(2)
+            .position(|window| window == [0xFF, 0xC0])
+            .unwrap();
+        lossless[sof + 1] = 0xC3;
+        let file = dng(1, &[&jpeg(16, 8, RED), &lossless]);
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(64))
+            .unwrap();
+
+        assert!(is_close(got.get_pixel(4, 2), RED));
+    }
+
+    #[test]
+    fn read_the_preview_from_a_raf_header() {
+        let preview = jpeg(64, 32, GREEN);
+        let mut file = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        file.resize(84, 0);
+        file.extend(&100_u32.to_be_bytes());
+        file.extend(&(preview.len() as u32).to_be_bytes());
+        file.resize(100, 0);
+        file.extend(&preview);
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+        assert!(is_close(got.get_pixel(16, 8), GREEN));
+    }
+
+    fn iso_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        bytes.extend(kind);
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn read_previews_from_cr3_boxes() {
+        let cmt1 = build_tiff(&[&[(ORIENTATION, SHORT, &[8])]], &[]);
+        let mut thmb = vec![0; 16];
+        thmb.extend(jpeg(16, 8, RED));
+        let mut canon = CANON_UUID.to_vec();
+        canon.extend(iso_box(b"CMT1", &cmt1));
+        canon.extend(iso_box(b"THMB", &thmb));
+
+        let mut prvw = vec![0; 24];
+        prvw.extend(jpeg(64, 32, GREEN));
+        let mut preview = PREVIEW_UUID.to_vec();
+        preview.extend(&[0; 8]);
+        preview.extend(iso_box(b"PRVW", &prvw));
+
+        let mut file = iso_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        file.extend(iso_box(b"moov", &iso_box(b"uuid", &canon)));
+        file.extend(iso_box(b"uuid", &preview));
+        file.extend(iso_box(b"mdat", &[0; 32]));
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (16, 32));
+        assert!(is_close(got.get_pixel(8, 16), GREEN));
+    }
+
+    #[test]
+    fn respect_the_frame_and_depth_limits() {
+        let provider = RawProvider::new();
+        let dng = dng(1, &[&jpeg(16, 8, RED), &jpeg(64, 32, GREEN)]);
+        let mut cr3 = iso_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        cr3.extend(iso_box(b"moov", &iso_box(b"free", &[])));
+        let inputs = [
+            (dng, Limits::none().with_max_frames(1), Limit::Frames(1)),
+            (cr3, Limits::none().with_max_depth(0), Limit::Depth(0)),
+        ];
+
+        for (file, limits, limit) in inputs.iter().cloned() {
+            let context = ThumbnailContext::new().with_limits(limits);
+
+            let err = provider
+                .get_thumbnail_with_context(
+                    Cursor::new(file),
+                    &context,
+                    Dimensions::square(32),
+                )
+                .unwrap_err();
+
+            assert!(
+                matches!(err, ThumbnailError::LimitExceeded(l) if l == limit),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn read_the_preview_from_olympus_maker_notes() {
+        let preview = jpeg(64, 32, GREEN);
+        let mut notes = b"OLYMPUS\0II\x03\0".to_vec();
+        // IFD at 12, pointing to the camera settings at 30
+        notes.extend(&1_u16.to_le_bytes());
+        notes.extend(&OLYMPUS_CAMERA_SETTINGS.to_le_bytes());
+        notes.extend(&UNDEFINED.to_le_bytes());
+        notes.extend(&1_u32.to_le_bytes());
+        notes.extend(&30_u32.to_le_bytes());
+        notes.extend(&0_u32.to_le_bytes());
+        // camera settings at 30, pointing to the preview at 60
+        notes.extend(&2_u16.to_le_bytes());
+        for &(tag, value) in &[
+            (OLYMPUS_PREVIEW_START, 60),
+            (OLYMPUS_PREVIEW_LENGTH, preview.len() as u32),
+        ] {
+            notes.extend(&tag.to_le_bytes());
+            notes.extend(&LONG.to_le_bytes());
+            notes.extend(&1_u32.to_le_bytes());
+            notes.extend(&value.to_le_bytes());
+        }
+        notes.extend(&0_u32.to_le_bytes());
+        notes.extend(&preview);
+
+        let notes: Vec<u32> = notes.into_iter().map(u32::from).collect();
+        let mut file = build_tiff(&[&[(MAKER_NOTE, UNDEFINED, &notes)]], &[]);
+        file[2] = b'R';
+        file[3] = b'O';
+
+        let got = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(32))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+        assert!(is_close(got.get_pixel(16, 8), GREEN));
+    }
+
+    #[test]
+    fn files_without_previews_are_unsupported() {
+        let file = build_tiff(&[&[(ORIENTATION, SHORT, &[1])]], &[]);
+
+        let err = RawProvider::new()
+            .get_thumbnail(&file[..], Dimensions::square(32))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Unsupported(_)));
+    }
+
+    #[test]
+    fn registered_ahead_of_the_image_provider() {
+        let file = dng(3, &[&jpeg(16, 8, RED), &jpeg(64, 32, GREEN)]);
+        let registry = ProviderRegistry::with_builtin_providers();
+
+        let got = registry
+            .thumbnail_for(
+                &mut &file[..],
+                &Hint::default().with_file_name("photo.dng"),
+                Dimensions::square(32),
+            )
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (32, 16));
+        assert!(is_close(got.get_pixel(16, 8), GREEN));
+    }
+}
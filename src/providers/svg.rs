@@ -0,0 +1,428 @@
+use crate::{
+    Dimensions, Fit, Limits, ProviderRegistry, ThumbnailContext,
+    ThumbnailError, ThumbnailProvider,
+};
+use flate2::read::GzDecoder;
+use image::{io::Reader, Rgba, RgbaImage};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{ImageHrefResolver, ImageKind, Options, Size, Tree},
+};
+use std::{
+    io::{Cursor, Read, Seek},
+    sync::{Mutex, PoisonError},
+};
+
+/// A [`ThumbnailProvider`] which renders SVG and SVGZ documents using
+/// [`resvg`].
+///
+/// Vector graphics have no natural resolution, so the document is always
+/// scaled to fill the requested [`Dimensions`] (i.e. [`Fit::Max`] behaves
+/// like [`Fit::Contain`] without the padding).
+///
+/// For safety, nothing outside the document is ever loaded. Images embedded
+/// using `data:` URLs are still rendered (as long as they are within the
+/// [`Limits`]), but references to other files are ignored. Text isn't
+/// rendered because no fonts are available.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SvgProvider;
+
+impl SvgProvider {
+    pub fn new() -> Self { SvgProvider }
+
+    /// Register an [`SvgProvider`] for SVG and SVGZ documents.
+    pub fn register(registry: &mut ProviderRegistry) {
+        registry
+            .register(SvgProvider::new())
+            .mime_type("image/svg+xml")
+            .mime_type("image/svg+xml-compressed")
+            .extension("svg")
+            .extension("svgz");
+    }
+
+    fn parse(
+        &self,
+        mut input: impl Read,
+        context: &ThumbnailContext,
+    ) -> Result<Tree, ThumbnailError> {
+        let mut document = Vec::new();
+        context
+            .limits
+            .reader(&mut input)
+            .read_to_end(&mut document)?;
+
+        // the uncompressed document counts towards the input limit too, so a
+        // small SVGZ can't expand into something enormous
+        if document.starts_with(&[0x1F, 0x8B]) {
+            let compressed = document;
+            document = Vec::new();
+            context
+                .limits
+                .reader(GzDecoder::new(&compressed[..]))
+                .read_to_end(&mut document)?;
+        }
+
+        context.check()?;
+
+        let rejected = Mutex::new(None);
+        let tree =
+            Tree::from_data(&document, &options(&context.limits, &rejected))
+                .map_err(ThumbnailError::corrupt)?;
+
+        match rejected
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+        {
+            Some(err) => Err(err),
+            None => Ok(tree),
+        }
+    }
+}
+
+/// Parsing options which never load anything from outside the document.
+///
+/// Embedded images aren't decoded until the document is rendered, so we
+/// check their dimensions up front. Images we can't measure are skipped, and
+/// the first one which is too large is saved in `rejected`.
+fn options<'a>(
+    limits: &'a Limits,
+    rejected: &'a Mutex<Option<ThumbnailError>>,
+) -> Options<'a> {
+    let resolve_data = ImageHrefResolver::default_data_resolver();
+
+    Options {
+        resources_dir: None,
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: Box::new(move |mime, data, options| {
+                let image = resolve_data(mime, data, options)?;
+                let (width, height) = match &image {
+                    // nested documents were parsed with these same options
+                    ImageKind::SVG(_) => return Some(image),
+                    ImageKind::JPEG(data)
+                    | ImageKind::PNG(data)
+                    | ImageKind::GIF(data)
+                    | ImageKind::WEBP(data) => raster_dimensions(data)?,
+                };
+
+                match limits.check_dimensions(width, height) {
+                    Ok(()) => Some(image),
+                    Err(err) => {
+                        rejected
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .get_or_insert(err);
+                        None
+                    },
+                }
+            }),
+            resolve_string: Box::new(|_, _| None),
+        },
+        ..Options::default()
+    }
+}
+
+/// Read a raster image's dimensions without decoding it.
+fn raster_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+impl ThumbnailProvider for SvgProvider {
+    type Error = ThumbnailError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        let context = ThumbnailContext::new();
+        let tree = self.parse(input, &context)?;
+        render(&tree, &context, desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        self.get_thumbnail(input, desired_dimensions)
+    }
+
+    fn get_thumbnail_with_context<R>(
+        &self,
+        input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        let tree = self.parse(input, context)?;
+        render(&tree, context, desired_dimensions)
+    }
+}
+
+fn render(
+    tree: &Tree,
+    context: &ThumbnailContext,
+    desired_dimensions: Dimensions,
+) -> Result<RgbaImage, ThumbnailError> {
+    let (width, height, transform) = layout(tree.size(), desired_dimensions);
+    context.limits.check_dimensions(width, height)?;
+
+    let mut pixmap = Pixmap::new(width, height).ok_or_else(|| {
+        ThumbnailError::internal(format!(
+            "Unable to allocate a {}x{} canvas",
+            width, height
+        ))
+    })?;
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+    context.check()?;
+
+    // tiny-skia uses premultiplied alpha
+    let mut image = RgbaImage::new(width, height);
+    for (pixel, colour) in image.pixels_mut().zip(pixmap.pixels()) {
+        let colour = colour.demultiply();
+        *pixel =
+            Rgba([colour.red(), colour.green(), colour.blue(), colour.alpha()]);
+    }
+
+    Ok(image)
+}
+
+/// Work out how big the canvas should be and how to map the document onto
+/// it.
+fn layout(size: Size, desired_dimensions: Dimensions) -> (u32, u32, Transform) {
+    let Dimensions { width, height, fit } = desired_dimensions;
+    let horizontal = width as f32 / size.width();
+    let vertical = height as f32 / size.height();
+
+    match fit {
+        Fit::Max => {
+            let scale = horizontal.min(vertical);
+            let scaled =
+                |original: f32| ((original * scale).round() as u32).max(1);
+            (
+                scaled(size.width()),
+                scaled(size.height()),
+                Transform::from_scale(scale, scale),
+            )
+        },
+        Fit::Contain | Fit::Cover => {
+            let scale = if fit == Fit::Contain {
+                horizontal.min(vertical)
+            } else {
+                horizontal.max(vertical)
+            };
+            // centre the document, cropping or padding the edges
+            let dx = (width as f32 - size.width() * scale) / 2.0;
+            let dy = (height as f32 - size.height() * scale) / 2.0;
+            (
+                width,
+                height,
+                Transform::from_row(scale, 0.0, 0.0, scale, dx, dy),
+            )
+        },
+        Fit::Stretch => {
+            (width, height, Transform::from_scale(horizontal, vertical))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, Limits};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+
+    const RED_RECTANGLE: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 100">
+        <rect width="200" height="100" fill="red"/>
+    </svg>"#;
+
+    #[test]
+    fn scale_the_view_box_to_fit() {
+        let got = SvgProvider::new()
+            .get_thumbnail(RED_RECTANGLE.as_bytes(), Dimensions::square(64))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (64, 32));
+        assert_eq!(*got.get_pixel(32, 16), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn vector_graphics_are_scaled_up() {
+        let got = SvgProvider::new()
+            .get_thumbnail(RED_RECTANGLE.as_bytes(), Dimensions::square(400))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (400, 200));
+    }
+
+    #[test]
+    fn honour_the_fit_mode() {
+        let provider = SvgProvider::new();
+        let dims = Dimensions::square(64);
+
+        let contain = provider
+            .get_thumbnail(
+                RED_RECTANGLE.as_bytes(),
+                dims.with_fit(Fit::Contain),
+            )
+            .unwrap();
+        assert_eq!(contain.dimensions(), (64, 64));
+        assert_eq!(contain.get_pixel(32, 4)[3], 0);
+        assert_eq!(*contain.get_pixel(32, 32), Rgba([255, 0, 0, 255]));
+
+        let cover = provider
+            .get_thumbnail(RED_RECTANGLE.as_bytes(), dims.with_fit(Fit::Cover))
+            .unwrap();
+        assert_eq!(cover.dimensions(), (64, 64));
+        assert_eq!(*cover.get_pixel(32, 4), Rgba([255, 0, 0, 255]));
+
+        let stretch = provider
+            .get_thumbnail(
+                RED_RECTANGLE.as_bytes(),
+                dims.with_fit(Fit::Stretch),
+            )
+            .unwrap();
+        assert_eq!(stretch.dimensions(), (64, 64));
+        assert_eq!(*stretch.get_pixel(32, 60), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn render_with_anti_aliasing_and_transparency() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">
+            <circle cx="50" cy="50" r="40" fill="blue" fill-opacity="0.5"/>
+        </svg>"#;
+
+        let got = SvgProvider::new()
+            .get_thumbnail(svg.as_bytes(), Dimensions::square(100))
+            .unwrap();
+
+        assert_eq!(*got.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        let centre = got.get_pixel(50, 50);
+        assert_eq!(centre[2], 255);
+        assert!((126..=129).contains(&centre[3]));
+        // the edge of the circle is partially covered
+        assert!(got.pixels().any(|p| p[3] > 0 && p[3] < 120));
+    }
+
+    #[test]
+    fn gunzip_svgz_documents() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(RED_RECTANGLE.as_bytes()).unwrap();
+        let svgz = encoder.finish().unwrap();
+
+        let got = SvgProvider::new()
+            .get_thumbnail(&svgz[..], Dimensions::square(64))
+            .unwrap();
+
+        assert_eq!(got.dimensions(), (64, 32));
+    }
+
+    #[test]
+    fn the_uncompressed_document_counts_towards_the_input_limit() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(RED_RECTANGLE.as_bytes()).unwrap();
+        encoder.write_all(&[b' '; 1024 * 1024]).unwrap();
+        let svgz = encoder.finish().unwrap();
+        let context = ThumbnailContext::new()
+            .with_limits(Limits::none().with_max_input_bytes(64 * 1024));
+
+        let err = SvgProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(svgz),
+                &context,
+                Dimensions::square(64),
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ThumbnailError::LimitExceeded(Limit::InputBytes(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn only_load_images_embedded_in_the_document() {
+        /// A 2x2 red PNG.
+        const RED_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAIAAAACCAYAAABytg0kAAAAEUlEQVR4nGP4z8DwH4QZYAwAR8oH+WdZbrcAAAAASUVORK5CYII=";
+
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("red.png");
+        RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]))
+            .save(&png)
+            .unwrap();
+        let svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 16 8">
+                <image width="8" height="8" xlink:href="data:image/png;base64,{}"/>
+                <image x="8" width="8" height="8" xlink:href="{}"/>
+            </svg>"#,
+            RED_PNG,
+            png.display()
+        );
+
+        let got = SvgProvider::new()
+            .get_thumbnail(svg.as_bytes(), Dimensions::new(16, 8))
+            .unwrap();
+
+        assert_eq!(*got.get_pixel(4, 4), Rgba([255, 0, 0, 255]));
+        assert_eq!(got.get_pixel(12, 4)[3], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn embedded_images_count_towards_the_pixel_limit() {
+        let mut png = Cursor::new(Vec::new());
+        RgbaImage::new(1000, 1000)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let href: String = png
+            .into_inner()
+            .iter()
+            .map(|byte| format!("%{:02X}", byte))
+            .collect();
+        let svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 16 8">
+                <image width="8" height="8" xlink:href="data:image/png,{}"/>
+            </svg>"#,
+            href
+        );
+        let context = ThumbnailContext::new()
+            .with_limits(Limits::none().with_max_pixels(100_000));
+
+        let err = SvgProvider::new()
+            .get_thumbnail_with_context(
+                Cursor::new(svg),
+                &context,
+                Dimensions::new(16, 8),
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ThumbnailError::LimitExceeded(Limit::Pixels(100_000))
+        ));
+    }
+
+    #[test]
+    fn invalid_documents_are_corrupt() {
+        let err = SvgProvider::new()
+            .get_thumbnail(&b"<svg"[..], Dimensions::square(8))
+            .unwrap_err();
+
+        assert!(matches!(err, ThumbnailError::Corrupt(_)));
+    }
+}
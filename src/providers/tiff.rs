@@ -0,0 +1,449 @@
+//! Just enough of a TIFF parser to walk the IFDs used by EXIF metadata and
+//! TIFF-based camera RAW formats.
+
+use crate::{Limits, ThumbnailError};
+use image::{imageops, RgbaImage};
+use std::io::{Read, Seek, SeekFrom};
+
+pub(crate) const COMPRESSION: u16 = 0x0103;
+pub(crate) const STRIP_OFFSETS: u16 = 0x0111;
+pub(crate) const ORIENTATION: u16 = 0x0112;
+pub(crate) const STRIP_BYTE_COUNTS: u16 = 0x0117;
+pub(crate) const SUB_IFDS: u16 = 0x014A;
+pub(crate) const JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+pub(crate) const JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x0202;
+pub(crate) const EXIF_IFD: u16 = 0x8769;
+pub(crate) const MAKER_NOTE: u16 = 0x927C;
+
+/// The most values we'll read for a single entry, so a corrupt count can't
+/// make us allocate a huge buffer.
+const MAX_VALUES: u32 = 4096;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// Parse the `II` or `MM` marker at the start of a TIFF structure.
+    pub(crate) fn from_marker(marker: &[u8]) -> Option<ByteOrder> {
+        match marker.get(..2)? {
+            b"II" => Some(ByteOrder::Little),
+            b"MM" => Some(ByteOrder::Big),
+            _ => None,
+        }
+    }
+}
+
+/// Does this look like the start of a TIFF structure?
+pub(crate) fn is_tiff(header: &[u8]) -> bool {
+    header.starts_with(b"II*\0") || header.starts_with(b"MM\0*")
+}
+
+/// Does this look like the start of a TIFF structure, or one of the variants
+/// used by camera RAW formats (e.g. Olympus ORF or Panasonic RW2)?
+pub(crate) fn is_tiff_like(header: &[u8]) -> bool {
+    let byte_order = match ByteOrder::from_marker(header) {
+        Some(byte_order) => byte_order,
+        None => return false,
+    };
+
+    match header.get(2..4) {
+        Some(magic) => TIFF_MAGIC.contains(&decode_u16(byte_order, magic)),
+        None => false,
+    }
+}
+
+/// The magic numbers for TIFF, Olympus ORF (`RO` and `RS`) and Panasonic
+/// RW2.
+const TIFF_MAGIC: [u16; 4] = [42, 0x4F52, 0x5352, 0x55];
+
+/// A TIFF structure, where all offsets are relative to where it starts.
+#[derive(Debug)]
+pub(crate) struct Tiff<R> {
+    reader: R,
+    base: u64,
+    byte_order: ByteOrder,
+    first_ifd: u32,
+}
+
+impl<R: Read + Seek> Tiff<R> {
+    /// Parse the TIFF header at the reader's current position.
+    pub(crate) fn new(mut reader: R) -> Result<Self, ThumbnailError> {
+        let base = reader.stream_position()?;
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+
+        let byte_order = match ByteOrder::from_marker(&header) {
+            Some(byte_order) if is_tiff_like(&header) => byte_order,
+            _ => return Err(ThumbnailError::corrupt("Invalid TIFF header")),
+        };
+        let first_ifd = decode_u32(byte_order, &header[4..]);
+
+        Ok(Tiff::at(reader, base, byte_order, first_ifd))
+    }
+
+    /// A TIFF structure without the usual header, such as the maker notes
+    /// inside EXIF metadata.
+    pub(crate) fn at(
+        reader: R,
+        base: u64,
+        byte_order: ByteOrder,
+        first_ifd: u32,
+    ) -> Self {
+        Tiff {
+            reader,
+            base,
+            byte_order,
+            first_ifd,
+        }
+    }
+
+    pub(crate) fn first_ifd(&self) -> u32 { self.first_ifd }
+
+    pub(crate) fn reader(&mut self) -> &mut R { &mut self.reader }
+
+    /// Where an offset is, relative to the start of the underlying reader.
+    pub(crate) fn position(&self, offset: u32) -> u64 {
+        self.base + u64::from(offset)
+    }
+
+    /// Where an entry's data is, when it is too big to fit in the entry
+    /// itself.
+    pub(crate) fn data_offset(&self, entry: &Entry) -> u32 {
+        decode_u32(self.byte_order, &entry.value)
+    }
+
+    pub(crate) fn read_ifd(
+        &mut self,
+        offset: u32,
+    ) -> Result<Ifd, ThumbnailError> {
+        self.seek(offset)?;
+        let mut buffer = [0; 12];
+
+        self.reader.read_exact(&mut buffer[..2])?;
+        let count = decode_u16(self.byte_order, &buffer);
+        let mut entries = Vec::with_capacity(usize::from(count));
+
+        for _ in 0..count {
+            self.reader.read_exact(&mut buffer)?;
+            entries.push(Entry {
+                tag: decode_u16(self.byte_order, &buffer[0..]),
+                kind: decode_u16(self.byte_order, &buffer[2..]),
+                count: decode_u32(self.byte_order, &buffer[4..]),
+                value: [buffer[8], buffer[9], buffer[10], buffer[11]],
+            });
+        }
+
+        self.reader.read_exact(&mut buffer[..4])?;
+        let next = decode_u32(self.byte_order, &buffer);
+
+        Ok(Ifd { entries, next })
+    }
+
+    /// Read an entry's values as integers.
+    pub(crate) fn values(
+        &mut self,
+        entry: &Entry,
+    ) -> Result<Vec<u32>, ThumbnailError> {
+        let size = match entry.kind {
+            TYPE_BYTE => 1,
+            TYPE_SHORT => 2,
+            TYPE_LONG | TYPE_IFD => 4,
+            _ => {
+                return Err(ThumbnailError::corrupt(format!(
+                    "Tag {:#06x} isn't an integer",
+                    entry.tag
+                )))
+            },
+        };
+        if entry.count > MAX_VALUES {
+            return Err(ThumbnailError::corrupt(format!(
+                "Tag {:#06x} has too many values",
+                entry.tag
+            )));
+        }
+
+        let len = size * entry.count as usize;
+        let mut raw = vec![0; len];
+
+        if len <= entry.value.len() {
+            raw.copy_from_slice(&entry.value[..len]);
+        } else {
+            self.seek(self.data_offset(entry))?;
+            self.reader.read_exact(&mut raw)?;
+        }
+
+        let values = raw
+            .chunks_exact(size)
+            .map(|chunk| match size {
+                1 => u32::from(chunk[0]),
+                2 => u32::from(decode_u16(self.byte_order, chunk)),
+                _ => decode_u32(self.byte_order, chunk),
+            })
+            .collect();
+
+        Ok(values)
+    }
+
+    /// Read the first value of an entry as an integer.
+    pub(crate) fn value(
+        &mut self,
+        entry: &Entry,
+    ) -> Result<u32, ThumbnailError> {
+        self.values(entry)?
+            .first()
+            .copied()
+            .ok_or_else(|| ThumbnailError::corrupt("Empty TIFF entry"))
+    }
+
+    /// Read the integer value associated with a tag, if it is present.
+    pub(crate) fn tag(
+        &mut self,
+        ifd: &Ifd,
+        tag: u16,
+    ) -> Result<Option<u32>, ThumbnailError> {
+        match ifd.get(tag) {
+            Some(entry) => self.value(entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the offset of a nested IFD, which some vendors store as a blob
+    /// rather than a `LONG` or `IFD`.
+    pub(crate) fn ifd_offset(
+        &mut self,
+        entry: &Entry,
+    ) -> Result<u32, ThumbnailError> {
+        match entry.kind {
+            TYPE_UNDEFINED => Ok(self.data_offset(entry)),
+            _ => self.value(entry),
+        }
+    }
+
+    /// Read a blob of data (e.g. an embedded JPEG).
+    pub(crate) fn read_bytes(
+        &mut self,
+        offset: u32,
+        len: u32,
+        limits: &Limits,
+    ) -> Result<Vec<u8>, ThumbnailError> {
+        limits.check_alloc(u64::from(len))?;
+
+        self.seek(offset)?;
+        let mut buffer = vec![0; len as usize];
+        self.reader.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), ThumbnailError> {
+        self.reader.seek(SeekFrom::Start(self.position(offset)))?;
+        Ok(())
+    }
+}
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+const TYPE_IFD: u16 = 13;
+
+/// An Image File Directory.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Ifd {
+    pub(crate) entries: Vec<Entry>,
+    /// The offset of the next IFD, or `0` if this is the last one.
+    pub(crate) next: u32,
+}
+
+impl Ifd {
+    pub(crate) fn get(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) tag: u16,
+    kind: u16,
+    pub(crate) count: u32,
+    value: [u8; 4],
+}
+
+/// The EXIF orientation tag, describing how an image needs to be transformed
+/// before it is displayed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Orientation(u32);
+
+impl Orientation {
+    pub(crate) const NORMAL: Orientation = Orientation(1);
+
+    pub(crate) fn from_tag(value: u32) -> Self {
+        if (1..=8).contains(&value) {
+            Orientation(value)
+        } else {
+            Orientation::NORMAL
+        }
+    }
+
+    /// Will the image's width and height be swapped when it is displayed?
+    pub(crate) fn swaps_axes(self) -> bool { self.0 >= 5 }
+
+    pub(crate) fn apply(self, image: RgbaImage) -> RgbaImage {
+        match self.0 {
+            2 => imageops::flip_horizontal(&image),
+            3 => imageops::rotate180(&image),
+            4 => imageops::flip_vertical(&image),
+            5 => imageops::flip_horizontal(&imageops::rotate90(&image)),
+            6 => imageops::rotate90(&image),
+            7 => imageops::flip_horizontal(&imageops::rotate270(&image)),
+            8 => imageops::rotate270(&image),
+            _ => image,
+        }
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self { Orientation::NORMAL }
+}
+
+fn decode_u16(byte_order: ByteOrder, bytes: &[u8]) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+
+    match byte_order {
+        ByteOrder::Little => u16::from_le_bytes(bytes),
+        ByteOrder::Big => u16::from_be_bytes(bytes),
+    }
+}
+
+fn decode_u32(byte_order: ByteOrder, bytes: &[u8]) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+    match byte_order {
+        ByteOrder::Little => u32::from_le_bytes(bytes),
+        ByteOrder::Big => u32::from_be_bytes(bytes),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::io::Cursor;
+
+    pub(crate) const SHORT: u16 = TYPE_SHORT;
+    pub(crate) const LONG: u16 = TYPE_LONG;
+    pub(crate) const UNDEFINED: u16 = TYPE_UNDEFINED;
+
+    /// Build a little-endian TIFF structure where each IFD is a list of
+    /// `(tag, type, values)`, followed by some trailing data.
+    pub(crate) fn build_tiff(
+        ifds: &[&[(u16, u16, &[u32])]],
+        trailer: &[u8],
+    ) -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(&8_u32.to_le_bytes());
+
+        for (i, ifd) in ifds.iter().enumerate() {
+            let start = tiff.len();
+            let size = 2 + 12 * ifd.len() + 4;
+            let mut extra = Vec::new();
+
+            tiff.extend(&(ifd.len() as u16).to_le_bytes());
+            for &(tag, kind, values) in ifd.iter() {
+                tiff.extend(&tag.to_le_bytes());
+                tiff.extend(&kind.to_le_bytes());
+                tiff.extend(&(values.len() as u32).to_le_bytes());
+
+                let mut raw = Vec::new();
+                for &value in values {
+                    match kind {
+                        TYPE_SHORT => raw.extend(&(value as u16).to_le_bytes()),
+                        TYPE_UNDEFINED => raw.push(value as u8),
+                        _ => raw.extend(&value.to_le_bytes()),
+                    }
+                }
+
+                if raw.len() <= 4 {
+                    raw.resize(4, 0);
+                    tiff.extend(&raw);
+                } else {
+                    let offset = start + size + extra.len();
+                    tiff.extend(&(offset as u32).to_le_bytes());
+                    extra.extend(raw);
+                }
+            }
+
+            let next = if i + 1 < ifds.len() {
+                start + size + extra.len()
+            } else {
+                0
+            };
+            tiff.extend(&(next as u32).to_le_bytes());
+            tiff.extend(extra);
+        }
+
+        tiff.extend(trailer);
+        tiff
+    }
+
+    #[test]
+    fn walk_a_chain_of_ifds() {
+        let raw = build_tiff(
+            &[
+                &[(ORIENTATION, TYPE_SHORT, &[6])],
+                &[(JPEG_INTERCHANGE_FORMAT, TYPE_LONG, &[1, 2, 3])],
+            ],
+            &[],
+        );
+        let mut tiff = Tiff::new(Cursor::new(raw)).unwrap();
+
+        let ifd0 = tiff.read_ifd(tiff.first_ifd()).unwrap();
+        assert_eq!(tiff.tag(&ifd0, ORIENTATION).unwrap(), Some(6));
+        assert_eq!(tiff.tag(&ifd0, JPEG_INTERCHANGE_FORMAT).unwrap(), None);
+
+        let ifd1 = tiff.read_ifd(ifd0.next).unwrap();
+        let offsets = tiff
+            .values(ifd1.get(JPEG_INTERCHANGE_FORMAT).unwrap())
+            .unwrap();
+        assert_eq!(offsets, vec![1, 2, 3]);
+        assert_eq!(ifd1.next, 0);
+    }
+
+    #[test]
+    fn reject_invalid_headers() {
+        assert!(Tiff::new(Cursor::new(b"GIF89a\0\0")).is_err());
+    }
+
+    #[test]
+    fn recognise_raw_variants() {
+        assert!(is_tiff_like(b"IIRO\x08\0\0\0"));
+        assert!(is_tiff_like(b"MM\0*\0\0\0\x08"));
+        assert!(!is_tiff_like(b"IIXX"));
+        assert!(!is_tiff_like(b"I"));
+    }
+
+    #[test]
+    fn rotate_to_the_right() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([1, 1, 1, 1]));
+
+        let rotated = Orientation::from_tag(6).apply(image);
+
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 0), &Rgba([1, 1, 1, 1]));
+    }
+
+    #[test]
+    fn transpose() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(2, 0, Rgba([1, 1, 1, 1]));
+
+        let transposed = Orientation::from_tag(5).apply(image);
+
+        assert_eq!(transposed.dimensions(), (2, 3));
+        assert_eq!(transposed.get_pixel(0, 2), &Rgba([1, 1, 1, 1]));
+    }
+}
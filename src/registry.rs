@@ -0,0 +1,1135 @@
+//! Dispatching to the right [`ThumbnailProvider`] based on a file's name or
+//! MIME type.
+
+use crate::{
+    Dimensions, DynThumbnailProvider, InputSources, Interruptible, ReadSeek,
+    SniffingReader, ThumbnailContext, ThumbnailProvider,
+};
+use image::RgbaImage;
+use std::{
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// A collection of [`ThumbnailProvider`]s which can be looked up by MIME type,
+/// glob pattern, or file extension.
+///
+/// When more than one provider matches a file they are tried in order of
+/// decreasing priority (ties are broken by registration order), falling back
+/// to the next candidate whenever a provider fails.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    entries: Vec<Entry>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self { ProviderRegistry::default() }
+
+    /// Create a registry containing all the providers built into this crate.
+    pub fn with_builtin_providers() -> Self {
+        let mut registry = ProviderRegistry::new();
+        crate::providers::register_builtin(&mut registry);
+        registry
+    }
+
+    /// Add a provider to the registry, returning a [`Registration`] which can
+    /// be used to say which files it should be used for.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use thumbnails::{ProviderRegistry, Dimensions, ThumbnailProvider};
+    /// # use image::RgbaImage;
+    /// # use std::io::{self, Read};
+    /// # struct PngProvider;
+    /// # impl ThumbnailProvider for PngProvider {
+    /// #     type Error = io::Error;
+    /// #     type Thumbnail = RgbaImage;
+    /// #     fn get_thumbnail<R: Read>(&self, _: R, _: Dimensions) -> io::Result<RgbaImage> {
+    /// #         unimplemented!()
+    /// #     }
+    /// # }
+    /// let mut registry = ProviderRegistry::new();
+    ///
+    /// registry
+    ///     .register(PngProvider)
+    ///     .mime_type("image/png")
+    ///     .extension("png")
+    ///     .priority(10);
+    /// ```
+    pub fn register<P>(&mut self, provider: P) -> Registration<'_>
+    where
+        P: DynThumbnailProvider + 'static,
+    {
+        self.register_boxed(Box::new(provider))
+    }
+
+    /// Add a provider which has already been boxed.
+    pub fn register_boxed(
+        &mut self,
+        provider: Box<dyn DynThumbnailProvider>,
+    ) -> Registration<'_> {
+        self.entries.push(Entry {
+            provider,
+            matchers: Vec::new(),
+            priority: 0,
+            fallback: false,
+        });
+
+        Registration {
+            entry: self.entries.last_mut().unwrap(),
+        }
+    }
+
+    /// Every MIME type a provider has been registered for, sorted and without
+    /// duplicates.
+    ///
+    /// Wildcards like `image/*` are skipped because they aren't real MIME
+    /// types.
+    pub fn mime_types(&self) -> Vec<&str> {
+        let mut mime_types: Vec<&str> = self
+            .entries
+            .iter()
+            .flat_map(|e| e.matchers.iter())
+            .filter_map(|m| match m {
+                Matcher::MimeType(mime) if !mime.ends_with("/*") => {
+                    Some(mime.as_str())
+                },
+                _ => None,
+            })
+            .collect();
+
+        mime_types.sort_unstable();
+        mime_types.dedup();
+
+        mime_types
+    }
+
+    /// Get the providers which may be able to handle a file, in the order they
+    /// should be tried.
+    pub fn candidates<'a>(
+        &'a self,
+        hint: &Hint,
+    ) -> impl Iterator<Item = &'a dyn DynThumbnailProvider> + 'a {
+        let mut matches: Vec<&Entry> =
+            self.entries.iter().filter(|e| e.is_match(hint)).collect();
+
+        // Note: sort_by_key() is stable, so registration order is preserved
+        matches
+            .sort_by_key(|e| (!e.is_specific_match(hint), Reverse(e.priority)));
+
+        matches.into_iter().map(|e| &*e.provider)
+    }
+
+    /// Generate a thumbnail for a file, trying each matching provider in turn.
+    ///
+    /// If the input is a path, the file name will be used to look up
+    /// providers (unless [`Hint::file_name`] is set) and the file is re-opened
+    /// for every provider that is tried. A reader will be buffered in memory
+    /// so it can be replayed when falling back to another provider.
+    ///
+    /// When the hint doesn't specify a MIME type, it will be detected from the
+    /// input's contents using [`sniff()`][crate::sniff()].
+    pub fn thumbnail_for<'a, I>(
+        &self,
+        input: I,
+        hint: &Hint,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, RegistryError>
+    where
+        I: Into<Input<'a>>,
+    {
+        let context = ThumbnailContext {
+            mime_type: hint.mime_type.clone(),
+            ..ThumbnailContext::default()
+        };
+
+        self.dispatch(input.into(), hint, context, desired_dimensions)
+    }
+
+    /// Generate a thumbnail for a file, passing the [`ThumbnailContext`] on
+    /// to each provider.
+    ///
+    /// This works like [`ProviderRegistry::thumbnail_for()`], except the
+    /// context's MIME type and file name are used to look up providers. When
+    /// the input is a path, any missing details (the path, MIME type and
+    /// modification time) are filled in before providers see the context.
+    pub fn thumbnail_for_context<'a, I>(
+        &self,
+        input: I,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, RegistryError>
+    where
+        I: Into<Input<'a>>,
+    {
+        let hint = Hint {
+            mime_type: context.mime_type.clone(),
+            file_name: context.file_name().map(String::from),
+        };
+
+        self.dispatch(input.into(), &hint, context.clone(), desired_dimensions)
+    }
+
+    fn dispatch(
+        &self,
+        input: Input<'_>,
+        hint: &Hint,
+        mut context: ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<RgbaImage, RegistryError> {
+        match input {
+            Input::Path(path) => {
+                let mut hint = hint.clone();
+                if hint.file_name.is_none() {
+                    hint.file_name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned());
+                }
+                if hint.mime_type.is_none() {
+                    let sniffed = SniffingReader::new(File::open(path)?)?;
+                    hint.mime_type = sniffed.mime_type().map(String::from);
+                }
+
+                if context.path.is_none() {
+                    context.path = Some(path.to_path_buf());
+                }
+                if context.mime_type.is_none() {
+                    context.mime_type = hint.mime_type.clone();
+                }
+                if context.modified.is_none() {
+                    context.modified =
+                        fs::metadata(path).and_then(|m| m.modified()).ok();
+                }
+
+                let candidates: Vec<_> = self.candidates(&hint).collect();
+
+                try_each(&candidates, &context, |provider| {
+                    let mut reader = context
+                        .limits
+                        .reader(BufReader::new(File::open(path)?));
+                    Ok(provider.get_thumbnail_dyn_with_context(
+                        &mut reader,
+                        &context,
+                        desired_dimensions,
+                    ))
+                })
+            },
+            Input::Seekable(reader) => {
+                let start = reader.stream_position()?;
+                let mut hint = hint.clone();
+                if hint.mime_type.is_none() {
+                    let sniffed = SniffingReader::new(&mut *reader)?;
+                    hint.mime_type = sniffed.mime_type().map(String::from);
+                }
+                if context.mime_type.is_none() {
+                    context.mime_type = hint.mime_type.clone();
+                }
+
+                let candidates: Vec<_> = self.candidates(&hint).collect();
+
+                try_each(&candidates, &context, |provider| {
+                    reader.seek(SeekFrom::Start(start))?;
+                    Ok(provider.get_thumbnail_dyn_with_context(
+                        &mut context.limits.reader(&mut *reader),
+                        &context,
+                        desired_dimensions,
+                    ))
+                })
+            },
+            Input::Reader(reader) => {
+                let mut reader = SniffingReader::new(reader)?;
+                let mut hint = hint.clone();
+                if hint.mime_type.is_none() {
+                    hint.mime_type = reader.mime_type().map(String::from);
+                }
+                if context.mime_type.is_none() {
+                    context.mime_type = hint.mime_type.clone();
+                }
+
+                let candidates: Vec<_> = self.candidates(&hint).collect();
+
+                // providers need random access and we may need to replay the
+                // input, so buffer it (up to the input limit)
+                let mut buffer = Vec::new();
+                context
+                    .limits
+                    .reader(Interruptible::new(&mut reader, &context))
+                    .read_to_end(&mut buffer)?;
+
+                try_each(&candidates, &context, |provider| {
+                    Ok(provider.get_thumbnail_dyn_with_context(
+                        &mut Cursor::new(&buffer[..]),
+                        &context,
+                        desired_dimensions,
+                    ))
+                })
+            },
+        }
+    }
+}
+
+fn try_each<F>(
+    candidates: &[&dyn DynThumbnailProvider],
+    context: &ThumbnailContext,
+    mut thunk: F,
+) -> Result<RgbaImage, RegistryError>
+where
+    F: FnMut(
+        &dyn DynThumbnailProvider,
+    ) -> Result<
+        Result<RgbaImage, Box<dyn Error + Send + Sync>>,
+        io::Error,
+    >,
+{
+    let mut errors = Vec::new();
+
+    for &provider in candidates {
+        if let Err(e) = context.check() {
+            // the caller has given up, so make sure they see why we stopped
+            // instead of an earlier provider's error
+            errors.insert(0, e.into());
+            break;
+        }
+
+        match thunk(provider)? {
+            Ok(thumbnail) => return Ok(thumbnail),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Err(RegistryError::NoProvider)
+    } else {
+        Err(RegistryError::AllFailed(errors))
+    }
+}
+
+impl ThumbnailProvider for ProviderRegistry {
+    type Error = RegistryError;
+    type Thumbnail = RgbaImage;
+
+    fn get_thumbnail<R>(
+        &self,
+        mut input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read,
+    {
+        self.thumbnail_for(&mut input, &Hint::default(), desired_dimensions)
+    }
+
+    fn get_thumbnail_seekable<R>(
+        &self,
+        mut input: R,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        self.thumbnail_for(
+            Input::Seekable(&mut input),
+            &Hint::default(),
+            desired_dimensions,
+        )
+    }
+
+    /// Accepts paths when at least one registered provider wants them.
+    fn input_sources(&self) -> InputSources {
+        let wants_path = self
+            .entries
+            .iter()
+            .any(|e| e.provider.input_sources_dyn().path);
+
+        if wants_path {
+            InputSources::STREAM.or(InputSources::PATH)
+        } else {
+            InputSources::STREAM
+        }
+    }
+
+    /// Generate a thumbnail, passing the context on to the providers.
+    fn get_thumbnail_with_context<R>(
+        &self,
+        mut input: R,
+        context: &ThumbnailContext,
+        desired_dimensions: Dimensions,
+    ) -> Result<Self::Thumbnail, Self::Error>
+    where
+        R: Read + Seek,
+    {
+        self.thumbnail_for_context(
+            Input::Seekable(&mut input),
+            context,
+            desired_dimensions,
+        )
+    }
+}
+
+/// A handle used to configure which files a newly registered provider
+/// should be used for.
+pub struct Registration<'a> {
+    entry: &'a mut Entry,
+}
+
+impl<'a> Registration<'a> {
+    /// Use this provider for a MIME type. Wildcards like `image/*` are
+    /// accepted.
+    pub fn mime_type<S: Into<String>>(self, mime_type: S) -> Self {
+        self.matcher(Matcher::MimeType(mime_type.into()))
+    }
+
+    /// Use this provider for file names matching a shell-style glob pattern
+    /// (e.g. `*.tar.gz` or `[Mm]akefile`).
+    pub fn glob<S: Into<String>>(self, pattern: S) -> Self {
+        self.matcher(Matcher::Glob(pattern.into()))
+    }
+
+    /// Use this provider for files with a particular extension.
+    pub fn extension<S: Into<String>>(self, extension: S) -> Self {
+        let extension = extension.into();
+        let extension = extension.trim_start_matches('.').to_string();
+        self.matcher(Matcher::Extension(extension))
+    }
+
+    pub fn matcher(self, matcher: Matcher) -> Self {
+        self.entry.matchers.push(matcher);
+        self
+    }
+
+    /// Set the provider's priority. Providers with a higher priority are
+    /// tried first, and the default is `0`.
+    pub fn priority(self, priority: i32) -> Self {
+        self.entry.priority = priority;
+        self
+    }
+
+    /// Try this provider for every file, after all providers which match the
+    /// file more specifically have failed.
+    pub fn fallback(self) -> Self {
+        self.entry.fallback = true;
+        self
+    }
+}
+
+/// A rule for deciding which files a provider can be used with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    MimeType(String),
+    Glob(String),
+    Extension(String),
+}
+
+impl Matcher {
+    pub fn is_match(&self, hint: &Hint) -> bool {
+        match self {
+            Matcher::MimeType(pattern) => hint
+                .mime_type
+                .as_ref()
+                .map(|mime| mime_type_matches(pattern, mime))
+                .unwrap_or(false),
+            Matcher::Glob(pattern) => hint
+                .file_name
+                .as_ref()
+                .map(|name| glob_matches(pattern, name))
+                .unwrap_or(false),
+            Matcher::Extension(ext) => hint
+                .file_name
+                .as_ref()
+                .map(|name| extension_matches(ext, name))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Extra information about a file which is used to pick a provider.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Hint {
+    pub mime_type: Option<String>,
+    pub file_name: Option<String>,
+}
+
+impl Hint {
+    pub fn with_mime_type<S: Into<String>>(self, mime_type: S) -> Self {
+        Hint {
+            mime_type: Some(mime_type.into()),
+            ..self
+        }
+    }
+
+    pub fn with_file_name<S: Into<String>>(self, file_name: S) -> Self {
+        Hint {
+            file_name: Some(file_name.into()),
+            ..self
+        }
+    }
+}
+
+/// The file a thumbnail should be generated for.
+pub enum Input<'a> {
+    Path(&'a Path),
+    Reader(&'a mut dyn Read),
+    /// A reader which supports random access, letting providers skip the
+    /// parts of the file they don't need.
+    Seekable(&'a mut dyn ReadSeek),
+}
+
+impl<'a> From<&'a Path> for Input<'a> {
+    fn from(path: &'a Path) -> Self { Input::Path(path) }
+}
+
+impl<'a> From<&'a PathBuf> for Input<'a> {
+    fn from(path: &'a PathBuf) -> Self { Input::Path(path) }
+}
+
+impl<'a, R: Read> From<&'a mut R> for Input<'a> {
+    fn from(reader: &'a mut R) -> Self { Input::Reader(reader) }
+}
+
+/// The error returned when a [`ProviderRegistry`] can't generate a thumbnail.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// No providers were registered for this file.
+    NoProvider,
+    /// Reading the input failed.
+    Io(io::Error),
+    /// Every matching provider was tried and they all failed, in order.
+    AllFailed(Vec<Box<dyn Error + Send + Sync>>),
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::NoProvider => {
+                write!(f, "No provider is registered for this file")
+            },
+            RegistryError::Io(_) => write!(f, "Unable to read the input"),
+            RegistryError::AllFailed(errors) => write!(
+                f,
+                "All {} matching providers failed to generate a thumbnail",
+                errors.len()
+            ),
+        }
+    }
+}
+
+impl Error for RegistryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegistryError::NoProvider => None,
+            RegistryError::Io(e) => Some(e),
+            // the first error is the highest priority provider's, or why we
+            // gave up early
+            RegistryError::AllFailed(errors) => {
+                errors.first().map(|e| &**e as &(dyn Error + 'static))
+            },
+        }
+    }
+}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self { RegistryError::Io(e) }
+}
+
+struct Entry {
+    provider: Box<dyn DynThumbnailProvider>,
+    matchers: Vec<Matcher>,
+    priority: i32,
+    fallback: bool,
+}
+
+impl Entry {
+    fn is_specific_match(&self, hint: &Hint) -> bool {
+        self.matchers.iter().any(|m| m.is_match(hint))
+    }
+
+    fn is_match(&self, hint: &Hint) -> bool {
+        self.fallback || self.is_specific_match(hint)
+    }
+}
+
+fn mime_type_matches(pattern: &str, mime_type: &str) -> bool {
+    // parameters like "; charset=utf-8" don't affect the type
+    let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => mime_type
+            .split('/')
+            .next()
+            .map(|t| t.eq_ignore_ascii_case(top_level))
+            .unwrap_or(false),
+        None => pattern.eq_ignore_ascii_case(mime_type),
+    }
+}
+
+fn extension_matches(extension: &str, file_name: &str) -> bool {
+    let file_name = file_name.to_ascii_lowercase();
+    let extension = extension.to_ascii_lowercase();
+
+    file_name.len() > extension.len() + 1
+        && file_name.ends_with(&extension)
+        && file_name[..file_name.len() - extension.len()].ends_with('.')
+}
+
+/// A case-insensitive implementation of shell-style globbing, supporting `*`,
+/// `?`, and `[...]` character classes.
+fn glob_matches(pattern: &str, file_name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let file_name: Vec<char> = file_name.to_lowercase().chars().collect();
+
+    glob_matches_chars(&pattern, &file_name)
+}
+
+/// The standard iterative wildcard algorithm. When a mismatch happens we
+/// backtrack to the most recent `*` and let it swallow one more character,
+/// so matching is never worse than `O(pattern * text)`.
+fn glob_matches_chars(pattern: &[char], text: &[char]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // the position just after the last "*", and where it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            backtrack = Some((p, t));
+            continue;
+        }
+
+        if let Some(next) = match_single(pattern, p, text[t]) {
+            p = next;
+            t += 1;
+            continue;
+        }
+
+        match backtrack {
+            Some((star, matched)) => {
+                p = star;
+                t = matched + 1;
+                backtrack = Some((star, t));
+            },
+            None => return false,
+        }
+    }
+
+    // any trailing "*"s can match nothing
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Try to match a single character against the (non-`*`) token at
+/// `pattern[p]`, returning the index of the next token on success.
+fn match_single(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => {
+            let rest = &pattern[p + 1..];
+            match rest.iter().position(|&c| c == ']') {
+                Some(end) if character_class_matches(&rest[..end], c) => {
+                    Some(p + end + 2)
+                },
+                Some(_) => None,
+                // an unterminated "[" is treated as a literal
+                None if c == '[' => Some(p + 1),
+                None => None,
+            }
+        },
+        &expected if expected == c => Some(p + 1),
+        _ => None,
+    }
+}
+
+fn character_class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) | Some(('^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            matched |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    matched != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CancellationToken, Limit, ThumbnailError};
+    use image::Rgba;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    /// A provider which always generates a solid image, counting how many
+    /// times it has been called.
+    struct Solid(u8, Arc<AtomicUsize>);
+
+    impl Solid {
+        fn new(value: u8) -> Self { Solid(value, Arc::default()) }
+    }
+
+    impl ThumbnailProvider for Solid {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            mut input: R,
+            desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error>
+        where
+            R: Read,
+        {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            let mut buffer = Vec::new();
+            input.read_to_end(&mut buffer)?;
+
+            Ok(RgbaImage::from_pixel(
+                desired_dimensions.width,
+                desired_dimensions.height,
+                Rgba([self.0, buffer.len() as u8, 0, 0]),
+            ))
+        }
+    }
+
+    struct AlwaysFails(Arc<AtomicUsize>);
+
+    impl ThumbnailProvider for AlwaysFails {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            mut input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error>
+        where
+            R: Read,
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            // consume the input to make sure it gets replayed
+            io::copy(&mut input, &mut io::sink())?;
+            Err(io::Error::other("Unsupported"))
+        }
+    }
+
+    const DIMS: Dimensions = Dimensions::new(1, 1);
+
+    fn first_pixel(registry: &ProviderRegistry, hint: &Hint) -> [u8; 4] {
+        let mut input: &[u8] = b"hello";
+        let thumbnail = registry.thumbnail_for(&mut input, hint, DIMS).unwrap();
+        thumbnail.get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn dispatch_by_mime_type() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).mime_type("image/png");
+        registry.register(Solid::new(2)).mime_type("image/*");
+        let hint = Hint::default().with_mime_type("image/png");
+
+        assert_eq!(first_pixel(&registry, &hint), [1, 5, 0, 0]);
+
+        let hint = Hint::default().with_mime_type("image/jpeg; q=0.5");
+        assert_eq!(first_pixel(&registry, &hint), [2, 5, 0, 0]);
+    }
+
+    #[test]
+    fn higher_priorities_are_tried_first() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).extension("png");
+        registry
+            .register(Solid::new(2))
+            .extension(".PNG")
+            .priority(5);
+        let hint = Hint::default().with_file_name("Picture.png");
+
+        assert_eq!(first_pixel(&registry, &hint), [2, 5, 0, 0]);
+    }
+
+    #[test]
+    fn fall_back_when_a_provider_fails() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).fallback();
+        registry.register(Solid::new(2)).glob("*.tar.gz");
+        registry
+            .register(AlwaysFails(Arc::clone(&calls)))
+            .glob("*.tar.gz")
+            .priority(1);
+        let hint = Hint::default().with_file_name("archive.tar.gz");
+
+        // the failing provider consumed the whole stream, but the next
+        // provider still sees all 5 bytes
+        assert_eq!(first_pixel(&registry, &hint), [2, 5, 0, 0]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // anything else goes to the fallback provider
+        let hint = Hint::default().with_file_name("notes.txt");
+        assert_eq!(first_pixel(&registry, &hint), [1, 5, 0, 0]);
+    }
+
+    #[test]
+    fn report_every_failure() {
+        let mut registry = ProviderRegistry::new();
+        registry
+            .register(AlwaysFails(Arc::default()))
+            .extension("png");
+        registry
+            .register(AlwaysFails(Arc::default()))
+            .mime_type("image/png");
+        let hint = Hint::default()
+            .with_file_name("a.png")
+            .with_mime_type("image/png");
+
+        let err = registry.thumbnail_for(&mut io::empty(), &hint, DIMS);
+
+        match err {
+            Err(RegistryError::AllFailed(errors)) => {
+                assert_eq!(errors.len(), 2)
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detect_the_mime_type_when_not_provided() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).fallback();
+        registry.register(Solid::new(2)).mime_type("image/gif");
+        let mut input: &[u8] = b"GIF89a";
+
+        let thumbnail = registry
+            .thumbnail_for(&mut input, &Hint::default(), DIMS)
+            .unwrap();
+
+        // the provider should also see the bytes used for sniffing
+        assert_eq!(thumbnail.get_pixel(0, 0).0, [2, 6, 0, 0]);
+    }
+
+    #[test]
+    fn seekable_inputs_are_rewound_for_each_provider() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(2)).mime_type("image/gif");
+        registry
+            .register(AlwaysFails(Arc::clone(&calls)))
+            .mime_type("image/gif")
+            .priority(1);
+        let mut input = Cursor::new(b"..GIF89a".to_vec());
+        input.set_position(2);
+
+        let thumbnail = registry
+            .thumbnail_for(Input::Seekable(&mut input), &Hint::default(), DIMS)
+            .unwrap();
+
+        // sniffing and the failed attempt shouldn't affect the next provider
+        assert_eq!(thumbnail.get_pixel(0, 0).0, [2, 6, 0, 0]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// A provider which generates a 1x1 thumbnail whose red channel is the
+    /// length of the file name it was given.
+    struct NeedsPath;
+
+    impl ThumbnailProvider for NeedsPath {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R>(
+            &self,
+            _input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            Err(io::ErrorKind::InvalidInput.into())
+        }
+
+        fn input_sources(&self) -> InputSources { InputSources::PATH }
+
+        fn get_thumbnail_with_context<R>(
+            &self,
+            _input: R,
+            context: &ThumbnailContext,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            assert!(context.modified.is_some());
+            let name = context.file_name().unwrap().len() as u8;
+            Ok(RgbaImage::from_pixel(1, 1, Rgba([name, 0, 0, 0])))
+        }
+    }
+
+    #[test]
+    fn providers_are_given_the_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("sidecar.xyz");
+        std::fs::write(&path, b"").unwrap();
+        let mut registry = ProviderRegistry::new();
+        assert_eq!(registry.input_sources(), InputSources::STREAM);
+        registry.register(NeedsPath).extension("xyz");
+
+        let thumbnail = registry
+            .thumbnail_for(&path, &Hint::default(), DIMS)
+            .unwrap();
+
+        assert_eq!(thumbnail.get_pixel(0, 0).0, [11, 0, 0, 0]);
+        assert!(registry.input_sources().path);
+    }
+
+    #[test]
+    fn no_matching_provider() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).extension("png");
+        let hint = Hint::default().with_file_name("a.jpg");
+
+        let err = registry.thumbnail_for(&mut io::empty(), &hint, DIMS);
+
+        assert!(matches!(err, Err(RegistryError::NoProvider)));
+    }
+
+    #[test]
+    fn list_mime_types() {
+        let mut registry = ProviderRegistry::new();
+        registry
+            .register(Solid::new(1))
+            .mime_type("image/png")
+            .mime_type("image/*")
+            .extension("png");
+        registry
+            .register(Solid::new(2))
+            .mime_type("image/jpeg")
+            .mime_type("image/png");
+
+        assert_eq!(registry.mime_types(), vec!["image/jpeg", "image/png"]);
+    }
+
+    #[test]
+    fn extension_matching() {
+        let inputs = vec![
+            ("png", "image.png", true),
+            ("png", "IMAGE.PNG", true),
+            ("png", "png", false),
+            ("png", ".png", false),
+            ("png", "image.apng", false),
+            ("tar.gz", "archive.tar.gz", true),
+        ];
+
+        for (extension, file_name, should_be) in inputs {
+            let got = extension_matches(extension, file_name);
+            assert_eq!(got, should_be, "{} vs {}", extension, file_name);
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        let inputs = vec![
+            ("*.png", "image.png", true),
+            ("*.png", "image.PNG", true),
+            ("*.png", "image.jpg", false),
+            ("[Mm]akefile", "makefile", true),
+            ("[Mm]akefile", "Makefile", true),
+            ("[!M]akefile", "Makefile", false),
+            ("image-[0-9].png", "image-7.png", true),
+            ("image-[0-9].png", "image-a.png", false),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("*", "", true),
+            ("[", "[", true),
+            ("*.tar.*", "archive.tar.gz", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("**.png", "image.png", true),
+        ];
+
+        for (pattern, file_name, should_be) in inputs {
+            let got = glob_matches(pattern, file_name);
+            assert_eq!(got, should_be, "{} vs {}", pattern, file_name);
+        }
+    }
+
+    #[test]
+    fn pathological_globs_finish_quickly() {
+        let pattern = "*a*a*a*a*a*a*a*a*a*a*b";
+        let file_name = "a".repeat(200);
+
+        assert!(!glob_matches(pattern, &file_name));
+    }
+
+    /// Reports the length of the MIME type it was given in its context.
+    struct MimeTypeLength;
+
+    impl ThumbnailProvider for MimeTypeLength {
+        type Error = io::Error;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            unreachable!()
+        }
+
+        fn get_thumbnail_with_context<R: Read + Seek>(
+            &self,
+            _input: R,
+            context: &ThumbnailContext,
+            desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            let len = context.mime_type.as_deref().unwrap_or_default().len();
+
+            Ok(RgbaImage::from_pixel(
+                desired_dimensions.width,
+                desired_dimensions.height,
+                Rgba([len as u8, 0, 0, 0]),
+            ))
+        }
+    }
+
+    #[test]
+    fn a_single_candidate_still_sees_the_context() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(MimeTypeLength).fallback();
+        let context =
+            ThumbnailContext::new().with_mime_type("application/x-dummy");
+        let mut input: &[u8] = b"hello";
+
+        let got = registry
+            .thumbnail_for_context(&mut input, &context, DIMS)
+            .unwrap();
+
+        assert_eq!(got.get_pixel(0, 0)[0], 19);
+    }
+
+    #[test]
+    fn every_kind_of_input_is_limited() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).fallback();
+        let context = ThumbnailContext::new()
+            .with_limits(crate::Limits::none().with_max_input_bytes(3));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.txt");
+        fs::write(&path, "hello").unwrap();
+        let mut cursor = Cursor::new(b"hello");
+        let mut reader: &[u8] = b"hello";
+
+        let inputs = vec![
+            Input::Path(&path),
+            Input::Seekable(&mut cursor),
+            Input::from(&mut reader),
+        ];
+
+        for input in inputs {
+            let err = registry
+                .thumbnail_for_context(input, &context, DIMS)
+                .unwrap_err();
+
+            assert!(
+                matches!(
+                    ThumbnailError::find(&err),
+                    Some(ThumbnailError::LimitExceeded(Limit::InputBytes(3)))
+                ),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    /// Fails with a corrupt file, cancelling the request on its way out.
+    struct CorruptThenCancel(CancellationToken);
+
+    impl ThumbnailProvider for CorruptThenCancel {
+        type Error = ThumbnailError;
+        type Thumbnail = RgbaImage;
+
+        fn get_thumbnail<R: Read>(
+            &self,
+            _input: R,
+            _desired_dimensions: Dimensions,
+        ) -> Result<Self::Thumbnail, Self::Error> {
+            self.0.cancel();
+            Err(ThumbnailError::corrupt("Bad data"))
+        }
+    }
+
+    #[test]
+    fn cancellation_is_the_source_of_the_error() {
+        let token = CancellationToken::new();
+        let mut registry = ProviderRegistry::new();
+        registry
+            .register(CorruptThenCancel(token.clone()))
+            .fallback();
+        registry.register(AlwaysFails(Arc::default())).fallback();
+        let context = ThumbnailContext::new().with_cancellation(token);
+        let mut input: &[u8] = b"hello";
+
+        let err = registry
+            .thumbnail_for_context(&mut input, &context, DIMS)
+            .unwrap_err();
+
+        assert!(matches!(
+            ThumbnailError::find(&err),
+            Some(ThumbnailError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn stop_trying_providers_once_cancelled() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut registry = ProviderRegistry::new();
+        registry
+            .register(AlwaysFails(Arc::clone(&calls)))
+            .fallback();
+        registry
+            .register(AlwaysFails(Arc::clone(&calls)))
+            .fallback();
+        let token = CancellationToken::new();
+        let context = ThumbnailContext::new().with_cancellation(token.clone());
+        token.cancel();
+        let mut input: &[u8] = b"hello";
+
+        let err = registry
+            .thumbnail_for_context(&mut input, &context, DIMS)
+            .unwrap_err();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(matches!(
+            ThumbnailError::from(err),
+            ThumbnailError::Cancelled
+        ));
+    }
+
+    #[test]
+    fn a_streaming_provider_is_interrupted() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Solid::new(1)).fallback();
+        let context = ThumbnailContext::new().with_deadline(Instant::now());
+        let mut input: &[u8] = b"hello";
+
+        let err = registry
+            .thumbnail_for_context(&mut input, &context, DIMS)
+            .unwrap_err();
+
+        assert!(matches!(
+            ThumbnailError::from(err),
+            ThumbnailError::TimedOut
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{dynamic::to_rgba, Dimensions, Fit};
+use image::{
+    imageops::{self, FilterType},
+    GenericImageView, Pixel, RgbaImage,
+};
+
+/// Resize an image according to the desired [`Dimensions`] and their
+/// [`Fit`].
+///
+/// All built-in providers use this function so thumbnails are consistent
+/// across formats.
+///
+/// # Examples
+///
+/// ```rust
+/// use image::RgbaImage;
+/// use thumbnails::{Dimensions, Fit};
+///
+/// let image = RgbaImage::new(400, 200);
+///
+/// let letterboxed =
+///     thumbnails::resize(&image, Dimensions::square(100).with_fit(Fit::Contain));
+///
+/// assert_eq!(letterboxed.dimensions(), (100, 100));
+/// ```
+pub fn resize<I>(image: &I, desired_dimensions: Dimensions) -> RgbaImage
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8> + 'static,
+{
+    let Dimensions {
+        width: desired_width,
+        height: desired_height,
+        fit,
+    } = desired_dimensions;
+    let (width, height) = image.dimensions();
+
+    if fit == Fit::Cover && width > 0 && height > 0 {
+        // crop before scaling, otherwise an image with an extreme aspect
+        // ratio would be scaled to something enormous first
+        let (crop_width, crop_height) =
+            cover_crop(width, height, desired_width, desired_height);
+        let cropped = imageops::crop_imm(
+            image,
+            (width - crop_width) / 2,
+            (height - crop_height) / 2,
+            crop_width,
+            crop_height,
+        );
+        return scale(&*cropped, desired_width, desired_height);
+    }
+
+    let (new_width, new_height) = desired_dimensions.scaled_size(width, height);
+    let scaled = scale(image, new_width, new_height);
+
+    match fit {
+        Fit::Contain => {
+            let mut canvas = RgbaImage::new(desired_width, desired_height);
+            imageops::overlay(
+                &mut canvas,
+                &scaled,
+                i64::from(desired_width.saturating_sub(new_width) / 2),
+                i64::from(desired_height.saturating_sub(new_height) / 2),
+            );
+            canvas
+        },
+        _ => scaled,
+    }
+}
+
+/// The largest region of a `width x height` image with the same aspect ratio
+/// as the desired dimensions.
+fn cover_crop(
+    width: u32,
+    height: u32,
+    desired_width: u32,
+    desired_height: u32,
+) -> (u32, u32) {
+    if desired_width == 0 || desired_height == 0 {
+        return (width, height);
+    }
+
+    let (w, h) = (u64::from(width), u64::from(height));
+    let (dw, dh) = (u64::from(desired_width), u64::from(desired_height));
+
+    if w * dh > h * dw {
+        // the image is wider than the box, so trim the sides
+        let crop_width = (h * dw + dh / 2) / dh;
+        (crop_width.clamp(1, w) as u32, height)
+    } else {
+        // trim the top and bottom
+        let crop_height = (w * dh + dw / 2) / dw;
+        (width, crop_height.clamp(1, h) as u32)
+    }
+}
+
+fn scale<I>(image: &I, new_width: u32, new_height: u32) -> RgbaImage
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = image.dimensions();
+
+    if (new_width, new_height) == (width, height) {
+        to_rgba(image)
+    } else if new_width <= width && new_height <= height {
+        to_rgba(&imageops::thumbnail(image, new_width, new_height))
+    } else {
+        to_rgba(&imageops::resize(
+            image,
+            new_width,
+            new_height,
+            FilterType::Triangle,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn red_and_blue(width: u32, height: u32) -> RgbaImage {
+        // the left half is red and the right half is blue
+        RgbaImage::from_fn(width, height, |x, _| {
+            if x < width / 2 {
+                RED
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn max_never_upscales() {
+        let image = red_and_blue(40, 20);
+
+        let got = resize(&image, Dimensions::new(100, 100));
+
+        assert_eq!(got, image);
+    }
+
+    #[test]
+    fn max_shrinks_to_fit() {
+        let image = red_and_blue(400, 200);
+
+        let got = resize(&image, Dimensions::new(100, 100));
+
+        assert_eq!(got.dimensions(), (100, 50));
+    }
+
+    #[test]
+    fn contain_adds_letterboxing() {
+        let image = red_and_blue(400, 200);
+        let dims = Dimensions::new(100, 100).with_fit(Fit::Contain);
+
+        let got = resize(&image, dims);
+
+        assert_eq!(got.dimensions(), (100, 100));
+        assert_eq!(*got.get_pixel(0, 0), TRANSPARENT);
+        assert_eq!(*got.get_pixel(0, 50), RED);
+        assert_eq!(*got.get_pixel(99, 99), TRANSPARENT);
+    }
+
+    #[test]
+    fn cover_crops_the_centre() {
+        let image = red_and_blue(400, 200);
+        let dims = Dimensions::new(100, 100).with_fit(Fit::Cover);
+
+        let got = resize(&image, dims);
+
+        assert_eq!(got.dimensions(), (100, 100));
+        // the outer quarters were cropped, leaving half red and half blue
+        assert_eq!(*got.get_pixel(0, 0), RED);
+        assert_eq!(*got.get_pixel(99, 0), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn cover_crops_extreme_aspect_ratios_before_scaling() {
+        let image = RgbaImage::from_pixel(1, 60_000, RED);
+        let dims = Dimensions::square(256).with_fit(Fit::Cover);
+
+        let got = resize(&image, dims);
+
+        assert_eq!(got.dimensions(), (256, 256));
+        assert_eq!(*got.get_pixel(128, 128), RED);
+    }
+
+    #[test]
+    fn stretch_ignores_the_aspect_ratio() {
+        let image = red_and_blue(40, 20);
+        let dims = Dimensions::new(10, 30).with_fit(Fit::Stretch);
+
+        let got = resize(&image, dims);
+
+        assert_eq!(got.dimensions(), (10, 30));
+    }
+}
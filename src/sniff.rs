@@ -0,0 +1,599 @@
+//! Detecting a file's type from its contents.
+
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+};
+
+/// How many bytes a [`SniffingReader`] will peek at by default.
+pub const DEFAULT_PEEK_SIZE: usize = 4096;
+
+/// Try to figure out the MIME type for a file based on its first few bytes.
+///
+/// # Examples
+///
+/// ```rust
+/// let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR";
+/// assert_eq!(thumbnails::sniff(png), Some("image/png"));
+///
+/// assert_eq!(thumbnails::sniff(b"Hello, World!"), None);
+/// ```
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let detectors: &[Detector] = &[
+        sniff_riff,
+        sniff_iso_bmff,
+        sniff_zip,
+        sniff_ogg,
+        sniff_matroska,
+        sniff_signature_table,
+        sniff_mpeg_audio,
+        sniff_svg,
+    ];
+
+    detectors.iter().filter_map(|detect| detect(bytes)).next()
+}
+
+type Detector = fn(&[u8]) -> Option<&'static str>;
+
+/// A [`Read`]er which peeks at the start of a stream to detect its type,
+/// then replays the peeked bytes so nothing is lost.
+#[derive(Debug)]
+pub struct SniffingReader<R> {
+    inner: R,
+    peeked: Vec<u8>,
+    position: usize,
+    mime_type: Option<&'static str>,
+}
+
+impl<R: Read> SniffingReader<R> {
+    /// Peek at the first [`DEFAULT_PEEK_SIZE`] bytes of a stream.
+    pub fn new(inner: R) -> io::Result<Self> {
+        SniffingReader::with_peek_size(inner, DEFAULT_PEEK_SIZE)
+    }
+
+    pub fn with_peek_size(mut inner: R, peek_size: usize) -> io::Result<Self> {
+        let mut peeked = Vec::with_capacity(peek_size);
+        (&mut inner)
+            .take(peek_size as u64)
+            .read_to_end(&mut peeked)?;
+        let mime_type = sniff(&peeked);
+
+        Ok(SniffingReader {
+            inner,
+            peeked,
+            position: 0,
+            mime_type,
+        })
+    }
+}
+
+impl<R> SniffingReader<R> {
+    /// The MIME type detected from the stream's contents, if known.
+    pub fn mime_type(&self) -> Option<&'static str> { self.mime_type }
+
+    /// The bytes which were read while detecting the MIME type.
+    pub fn peeked(&self) -> &[u8] { &self.peeked }
+
+    /// Get the underlying reader back.
+    ///
+    /// # Note
+    ///
+    /// Any peeked bytes which haven't been replayed yet will be lost.
+    pub fn into_inner(self) -> R { self.inner }
+}
+
+impl<R: Read> Read for SniffingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.peeked[self.position..];
+
+        if remaining.is_empty() {
+            return self.inner.read(buf);
+        }
+
+        let bytes_read = remaining.len().min(buf.len());
+        buf[..bytes_read].copy_from_slice(&remaining[..bytes_read]);
+        self.position += bytes_read;
+
+        Ok(bytes_read)
+    }
+}
+
+/// A file format which can be identified by a fixed sequence of bytes at a
+/// known offset.
+struct Signature {
+    offset: usize,
+    magic: &'static [u8],
+    mime_type: &'static str,
+}
+
+impl Signature {
+    const fn new(magic: &'static [u8], mime_type: &'static str) -> Self {
+        Signature::at(0, magic, mime_type)
+    }
+
+    const fn at(
+        offset: usize,
+        magic: &'static [u8],
+        mime_type: &'static str,
+    ) -> Self {
+        Signature {
+            offset,
+            magic,
+            mime_type,
+        }
+    }
+
+    fn is_match(&self, bytes: &[u8]) -> bool {
+        bytes
+            .get(self.offset..self.offset + self.magic.len())
+            .map(|b| b == self.magic)
+            .unwrap_or(false)
+    }
+}
+
+/// Formats with a simple magic number. More specific signatures must come
+/// before the formats they would otherwise be mistaken for (e.g. camera RAW
+/// files before plain TIFF).
+const SIGNATURES: &[Signature] = &[
+    Signature::new(b"\x89PNG\r\n\x1a\n", "image/png"),
+    Signature::new(b"\xff\xd8\xff", "image/jpeg"),
+    Signature::new(b"GIF87a", "image/gif"),
+    Signature::new(b"GIF89a", "image/gif"),
+    Signature::new(b"FUJIFILMCCD-RAW", "image/x-fuji-raf"),
+    Signature::at(8, b"CR\x02\x00", "image/x-canon-cr2"),
+    Signature::new(b"IIRO", "image/x-olympus-orf"),
+    Signature::new(b"IIRS", "image/x-olympus-orf"),
+    Signature::new(b"IIU\x00", "image/x-panasonic-rw2"),
+    Signature::new(b"II*\x00", "image/tiff"),
+    Signature::new(b"MM\x00*", "image/tiff"),
+    Signature::new(b"BM", "image/bmp"),
+    Signature::new(b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    Signature::new(b"qoif", "image/qoi"),
+    Signature::new(b"#?RADIANCE", "image/vnd.radiance"),
+    Signature::new(b"#?RGBE", "image/vnd.radiance"),
+    Signature::new(b"8BPS", "image/vnd.adobe.photoshop"),
+    Signature::new(b"\x76\x2f\x31\x01", "image/x-exr"),
+    Signature::new(b"P1\n", "image/x-portable-bitmap"),
+    Signature::new(b"P4\n", "image/x-portable-bitmap"),
+    Signature::new(b"P2\n", "image/x-portable-graymap"),
+    Signature::new(b"P5\n", "image/x-portable-graymap"),
+    Signature::new(b"P3\n", "image/x-portable-pixmap"),
+    Signature::new(b"P6\n", "image/x-portable-pixmap"),
+    Signature::new(b"P7\n", "image/x-portable-arbitrarymap"),
+    Signature::new(b"%PDF-", "application/pdf"),
+    Signature::new(b"\x1f\x8b", "application/gzip"),
+    Signature::new(b"ID3", "audio/mpeg"),
+    Signature::new(b"fLaC", "audio/flac"),
+    Signature::new(b"MAC ", "audio/x-ape"),
+];
+
+fn sniff_signature_table(bytes: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|sig| sig.is_match(bytes))
+        .map(|sig| sig.mime_type)
+}
+
+/// Formats using the Resource Interchange File Format container.
+fn sniff_riff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.get(..4)? != b"RIFF" {
+        return None;
+    }
+
+    match bytes.get(8..12)? {
+        b"WEBP" => Some("image/webp"),
+        b"WAVE" => Some("audio/wav"),
+        b"AVI " => Some("video/x-msvideo"),
+        _ => None,
+    }
+}
+
+/// Formats based on the ISO Base Media File Format (MP4, QuickTime, HEIF,
+/// etc.), identified by the brands in their `ftyp` box.
+fn sniff_iso_bmff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.get(4..8)? != b"ftyp" {
+        return None;
+    }
+
+    let box_size = u32::from_be_bytes(read_array(bytes, 0)?) as usize;
+    let end = box_size.min(bytes.len());
+    let major_brand: [u8; 4] = read_array(bytes, 8)?;
+    // the minor version sits between the major and compatible brands
+    let compatible_brands = bytes
+        .get(16..end)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]]);
+
+    let mut mime_type = None;
+
+    for brand in std::iter::once(major_brand).chain(compatible_brands) {
+        let candidate = match &brand {
+            b"avif" | b"avis" => return Some("image/avif"),
+            b"crx " => return Some("image/x-canon-cr3"),
+            b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => {
+                "image/heif"
+            },
+            b"M4A " | b"M4B " | b"M4P " => "audio/mp4",
+            b"qt  " => "video/quicktime",
+            b"3gp4" | b"3gp5" | b"3gp6" | b"3g2a" => "video/3gpp",
+            _ => "video/mp4",
+        };
+
+        // AVIF and CR3 files are recognised by any of their brands, but
+        // otherwise the major brand wins
+        mime_type = mime_type.or(Some(candidate));
+    }
+
+    mime_type
+}
+
+const ZIP_LOCAL_FILE_HEADER: &[u8] = b"PK\x03\x04";
+
+/// ZIP archives and the document formats which use them as a container.
+fn sniff_zip(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.get(..4)? != ZIP_LOCAL_FILE_HEADER {
+        return None;
+    }
+
+    // OpenDocument and EPUB files must start with an uncompressed "mimetype"
+    // entry containing the MIME type
+    if let Some(declared) = declared_zip_mime_type(bytes) {
+        if let Some(mime_type) = ZIP_MIME_TYPES.iter().find(|m| **m == declared)
+        {
+            return Some(mime_type);
+        }
+    }
+
+    // Office Open XML formats can only be identified by the files they
+    // contain, so look for well-known file names in the entries we've seen
+    let ooxml = [
+        (&b"word/"[..], "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+        (b"xl/", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        (b"ppt/", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ];
+
+    for entry in zip_entry_names(bytes) {
+        for &(prefix, mime_type) in &ooxml {
+            if entry.starts_with(prefix) {
+                return Some(mime_type);
+            }
+        }
+    }
+
+    Some("application/zip")
+}
+
+const ZIP_MIME_TYPES: &[&str] = &[
+    "application/epub+zip",
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/vnd.oasis.opendocument.graphics",
+    "application/vnd.sun.xml.writer",
+    "application/x-krita",
+    "image/openraster",
+];
+
+fn declared_zip_mime_type(bytes: &[u8]) -> Option<&str> {
+    let name_length = u16::from_le_bytes(read_array(bytes, 26)?) as usize;
+    let extra_length = u16::from_le_bytes(read_array(bytes, 28)?) as usize;
+    let compressed_size = u32::from_le_bytes(read_array(bytes, 18)?) as usize;
+
+    if bytes.get(30..30 + name_length)? != b"mimetype" {
+        return None;
+    }
+
+    let start = 30 + name_length + extra_length;
+    let content = bytes.get(start..start + compressed_size)?;
+    std::str::from_utf8(content).ok().map(str::trim)
+}
+
+/// Iterate over the names of the ZIP entries in a buffer.
+fn zip_entry_names(bytes: &[u8]) -> impl Iterator<Item = &[u8]> + '_ {
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        // entries may use data descriptors, so we can't always trust the
+        // compressed size. Scan for the next header instead.
+        let relative = bytes
+            .get(offset..)?
+            .windows(ZIP_LOCAL_FILE_HEADER.len())
+            .position(|w| w == ZIP_LOCAL_FILE_HEADER)?;
+        let header = offset + relative;
+        let name_length =
+            u16::from_le_bytes(read_array(bytes, header + 26)?) as usize;
+        let name = bytes.get(header + 30..header + 30 + name_length)?;
+        offset = header + 30 + name_length;
+
+        Some(name)
+    })
+}
+
+fn sniff_ogg(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.get(..4)? != b"OggS" {
+        return None;
+    }
+
+    // the first packet starts after the page header and its segment table
+    let segment_count = *bytes.get(26)? as usize;
+    let packet = bytes.get(27 + segment_count..)?;
+
+    if packet.starts_with(b"\x01vorbis")
+        || packet.starts_with(b"OpusHead")
+        || packet.starts_with(b"\x7fFLAC")
+        || packet.starts_with(b"Speex   ")
+    {
+        Some("audio/ogg")
+    } else if packet.starts_with(b"\x80theora") {
+        Some("video/ogg")
+    } else {
+        Some("application/ogg")
+    }
+}
+
+fn sniff_matroska(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.get(..4)? != b"\x1a\x45\xdf\xa3" {
+        return None;
+    }
+
+    // The DocType element is part of the EBML header at the very start
+    let header = &bytes[..bytes.len().min(64)];
+
+    if header.windows(4).any(|w| w == b"webm") {
+        Some("video/webm")
+    } else {
+        Some("video/x-matroska")
+    }
+}
+
+/// MPEG audio streams without an ID3 tag start with a frame sync.
+///
+/// Eleven set bits are easy to hit by accident (a UTF-16LE byte order mark
+/// is `FF FE`), so the rest of the frame header has to be valid too and, if
+/// we can see it, the next frame needs to start where this one ends.
+fn sniff_mpeg_audio(bytes: &[u8]) -> Option<&'static str> {
+    let header = bytes.get(..4)?;
+
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+
+    let version = (header[1] >> 3) & 0b11;
+    let layer = (header[1] >> 1) & 0b11;
+
+    // version "01" is reserved
+    if version == 0b01 {
+        return None;
+    }
+
+    // ADTS uses the same sync word with a layer of "00"
+    if layer == 0b00 {
+        let sample_rate_index = (header[2] >> 2) & 0b1111;
+        return if header[1] & 0xf0 == 0xf0 && sample_rate_index < 13 {
+            Some("audio/aac")
+        } else {
+            None
+        };
+    }
+
+    // treat a byte order mark as text, not an MPEG-1 Layer I frame
+    if header[1] == 0xfe {
+        return None;
+    }
+
+    let frame_len = mpeg_frame_len(header)?;
+
+    match bytes.get(frame_len..frame_len + 2) {
+        Some(next) if next[0] != 0xff || next[1] & 0xe0 != 0xe0 => None,
+        _ => Some("audio/mpeg"),
+    }
+}
+
+/// Calculate the length of an MPEG audio frame from its header, returning
+/// [`None`] if the header uses a reserved or "free" bitrate or sample rate.
+fn mpeg_frame_len(header: &[u8]) -> Option<usize> {
+    const BITRATES: [[u16; 15]; 5] = [
+        // MPEG-1 Layer I, II and III
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416,
+            448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        // MPEG-2 and 2.5 Layer I, then Layer II and III
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ];
+    const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    let version = (header[1] >> 3) & 0b11;
+    let layer = (header[1] >> 1) & 0b11;
+    let bitrate_index = usize::from(header[2] >> 4);
+    let sample_rate_index = usize::from((header[2] >> 2) & 0b11);
+    let padding = u32::from((header[2] >> 1) & 1);
+
+    if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+
+    let is_mpeg1 = version == 0b11;
+    let table = match (is_mpeg1, layer) {
+        (true, 0b11) => 0,
+        (true, 0b10) => 1,
+        (true, _) => 2,
+        (false, 0b11) => 3,
+        (false, _) => 4,
+    };
+    let bitrate = u32::from(BITRATES[table][bitrate_index]) * 1000;
+    // MPEG-2 halves the sample rate and MPEG-2.5 quarters it
+    let sample_rate = match version {
+        0b11 => SAMPLE_RATES[sample_rate_index],
+        0b10 => SAMPLE_RATES[sample_rate_index] / 2,
+        _ => SAMPLE_RATES[sample_rate_index] / 4,
+    };
+
+    let len = match layer {
+        0b11 => (12 * bitrate / sample_rate + padding) * 4,
+        0b01 if !is_mpeg1 => 72 * bitrate / sample_rate + padding,
+        _ => 144 * bitrate / sample_rate + padding,
+    };
+
+    Some(len as usize)
+}
+
+fn sniff_svg(bytes: &[u8]) -> Option<&'static str> {
+    let text = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace())?;
+    let text = &text[start..];
+
+    let looks_like_markup = text.starts_with(b"<?xml")
+        || text.starts_with(b"<svg")
+        || text.starts_with(b"<!DOCTYPE svg")
+        || text.starts_with(b"<!--");
+
+    if looks_like_markup && text.windows(4).any(|w| w == b"<svg") {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset + N)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_signatures() {
+        let inputs: Vec<(&[u8], &str)> = vec![
+            (b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR", "image/png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF\0", "image/jpeg"),
+            (b"GIF89a\x01\0\x01\0", "image/gif"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp"),
+            (b"RIFF\x24\0\0\0WAVEfmt ", "audio/wav"),
+            (b"%PDF-1.7\n", "application/pdf"),
+            (b"ID3\x04\0\0\0\0\0\0", "audio/mpeg"),
+            (b"\xff\xfb\x90\x64", "audio/mpeg"),
+            (b"\xff\xf1\x50\x80", "audio/aac"),
+            (b"fLaC\0\0\0\x22", "audio/flac"),
+            (b"II*\0\x08\0\0\0", "image/tiff"),
+            (b"II*\0\x10\0\0\0CR\x02\0", "image/x-canon-cr2"),
+            (
+                b"\x1a\x45\xdf\xa3\x9f\x42\x86\x81\x01\x42\x82\x84webm",
+                "video/webm",
+            ),
+            (
+                b"\x1a\x45\xdf\xa3\xa3\x42\x86\x81\x01\x42\x82\x88matroska",
+                "video/x-matroska",
+            ),
+            (
+                b"\0\0\0\x18ftypisom\0\0\x02\0isomiso2",
+                "video/mp4",
+            ),
+            (b"\0\0\0\x18ftypM4A \0\0\0\0M4A isom", "audio/mp4"),
+            (b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif", "image/avif"),
+            (b"\0\0\0\x18ftypheic\0\0\0\0mif1heic", "image/heif"),
+            (b"OggS\0\x02\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\x1e\x01vorbis", "audio/ogg"),
+            (
+                b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+                "image/svg+xml",
+            ),
+        ];
+
+        for (bytes, should_be) in inputs {
+            let got = sniff(bytes);
+            assert_eq!(got, Some(should_be), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn mpeg_frames_must_follow_each_other() {
+        let header = b"\xff\xfb\x90\x64";
+        // 128 kbps at 44.1 kHz
+        let frame_len = 417;
+        let mut stream = header.to_vec();
+        stream.resize(frame_len, 0);
+        stream.extend(header);
+        assert_eq!(sniff(&stream), Some("audio/mpeg"));
+
+        stream.truncate(frame_len);
+        stream.extend(b"text");
+        assert_eq!(sniff(&stream), None);
+    }
+
+    #[test]
+    fn unknown_formats() {
+        let inputs: Vec<&[u8]> = vec![
+            b"",
+            b"Hello, World!",
+            b"<?xml version=\"1.0\"?><html/>",
+            b"\0\0\0\x18ftyp",
+            // UTF-16LE text with a byte order mark
+            b"\xff\xfeH\0e\0l\0l\0o\0",
+            // MPEG audio with a reserved bitrate, then sample rate
+            b"\xff\xfb\xf0\x64",
+            b"\xff\xfb\x9c\x64",
+        ];
+
+        for bytes in inputs {
+            assert_eq!(sniff(bytes), None, "{:?}", bytes);
+        }
+    }
+
+    fn zip_entry(name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut entry = ZIP_LOCAL_FILE_HEADER.to_vec();
+        entry.extend(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        entry.extend(&(contents.len() as u32).to_le_bytes());
+        entry.extend(&(contents.len() as u32).to_le_bytes());
+        entry.extend(&(name.len() as u16).to_le_bytes());
+        entry.extend(&[0, 0]);
+        entry.extend(name.as_bytes());
+        entry.extend(contents);
+        entry
+    }
+
+    #[test]
+    fn zip_based_containers() {
+        let odt =
+            zip_entry("mimetype", b"application/vnd.oasis.opendocument.text");
+        assert_eq!(
+            sniff(&odt),
+            Some("application/vnd.oasis.opendocument.text")
+        );
+
+        let mut docx = zip_entry("[Content_Types].xml", b"<Types/>");
+        docx.extend(zip_entry("_rels/.rels", b"<Relationships/>"));
+        docx.extend(zip_entry("word/document.xml", b"<w:document/>"));
+        assert_eq!(
+            sniff(&docx),
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        );
+
+        let plain = zip_entry("hello.txt", b"Hello, World!");
+        assert_eq!(sniff(&plain), Some("application/zip"));
+    }
+
+    #[test]
+    fn peeked_bytes_are_replayed() {
+        let mut data = b"GIF89a".to_vec();
+        data.extend((0..100).map(|i| i as u8));
+
+        let mut reader = SniffingReader::with_peek_size(&data[..], 10).unwrap();
+        assert_eq!(reader.mime_type(), Some("image/gif"));
+        assert_eq!(reader.peeked(), &data[..10]);
+
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, data);
+    }
+}